@@ -36,6 +36,7 @@ pub fn sampler_from_gltf(sampler: gltf::texture::Sampler) -> Sampler {
         mag_filter: sampler.mag_filter().map(|f| f.as_gl_enum()),
         wrap_t: sampler.wrap_t().as_gl_enum(),
         wrap_s: sampler.wrap_s().as_gl_enum(),
+        anisotropy: None,
     }
 }
 
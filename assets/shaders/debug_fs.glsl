@@ -1,5 +1,7 @@
 out vec3 f_color;
 
+uniform vec3 color;
+
 void main() {
-    f_color = vec3(0.223, 1.0, 0.078);
+    f_color = color;
 }
\ No newline at end of file
@@ -0,0 +1,7 @@
+in vec3 v_color;
+
+out vec3 f_color;
+
+void main() {
+    f_color = v_color;
+}
\ No newline at end of file
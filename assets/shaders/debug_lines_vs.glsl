@@ -0,0 +1,12 @@
+in vec3 position;
+in vec3 color;
+
+out vec3 v_color;
+
+uniform mat4 projection;
+uniform mat4 view;
+
+void main() {
+    v_color = color;
+    gl_Position = projection * view * vec4(position, 1.);
+}
@@ -13,10 +13,10 @@ uniform float intensity;
 
 out vec4 f_color;
 
+#include "lighting_common.glsl"
+
 void main() {
-    vec3 norm = normalize(texture(normal, v_uv).rgb);
-    vec3 lightDir = normalize(direction.xyz);
-    float diff = max(dot(norm, lightDir), 0.0);
+    float diff = lambert(texture(normal, v_uv).rgb, direction.xyz);
 
     vec3 diffuse = intensity * diff * color * texture(diffuse, v_uv).rgb;
     f_color = vec4(diffuse, 1.0);
@@ -0,0 +1,6 @@
+// Helpers shared by the lighting passes. Use `#include "lighting_common.glsl"`.
+
+// Lambertian diffuse term between the surface normal and the direction toward the light.
+float lambert(vec3 normal, vec3 lightDir) {
+    return max(dot(normalize(normal), normalize(lightDir)), 0.0);
+}
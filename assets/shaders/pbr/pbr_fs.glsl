@@ -38,6 +38,13 @@ uniform vec3 u_LightColor;
 uniform vec3 u_AmbientLightColor;
 uniform float u_AmbientLightIntensity;
 
+#ifdef HAS_ENVIRONMENT_MAP
+uniform samplerCube u_IrradianceSampler;
+uniform samplerCube u_SpecularEnvSampler;
+uniform float u_MaxSpecularLevel;
+uniform float u_EnvironmentIntensity;
+#endif
+
 const float PI = 3.14159265359;
 
 
@@ -203,6 +210,43 @@ vec3 fresnelSchlick(float cosTheta, vec3 F0)
     return F0 + (1.0 - F0) * pow(1.0 - cosTheta, 5.0);
 }
 
+#ifdef HAS_ENVIRONMENT_MAP
+// ----------------------------------------------------------------------------
+// Rough surfaces reflect less at grazing angles.
+vec3 fresnelSchlickRoughness(float cosTheta, vec3 F0, float roughness)
+{
+    return F0 + (max(vec3(1.0 - roughness), F0) - F0) * pow(1.0 - cosTheta, 5.0);
+}
+// ----------------------------------------------------------------------------
+// Analytical fit of the split sum BRDF lookup table, from "Physically Based Shading on
+// Mobile" (Karis). Saves a texture.
+vec2 envBRDFApprox(float NdotV, float roughness)
+{
+    const vec4 c0 = vec4(-1.0, -0.0275, -0.572, 0.022);
+    const vec4 c1 = vec4(1.0, 0.0425, 1.04, -0.04);
+    vec4 r = roughness * c0 + c1;
+    float a004 = min(r.x * r.x, exp2(-9.28 * NdotV)) * r.x + r.y;
+    return vec2(-1.04, 1.04) * a004 + r.zw;
+}
+// ----------------------------------------------------------------------------
+vec3 environmentLighting(vec3 N, vec3 V, vec3 F0, vec3 albedo, float roughness, float metallic)
+{
+    float NdotV = max(dot(N, V), 0.0);
+    vec3 F = fresnelSchlickRoughness(NdotV, F0, roughness);
+    vec3 kD = (vec3(1.0) - F) * (1.0 - metallic);
+
+    vec3 irradiance = texture(u_IrradianceSampler, N).rgb;
+    vec3 diffuse = kD * irradiance * albedo;
+
+    vec3 R = reflect(-V, N);
+    vec3 prefiltered = textureLod(u_SpecularEnvSampler, R, roughness * u_MaxSpecularLevel).rgb;
+    vec2 brdf = envBRDFApprox(NdotV, roughness);
+    vec3 specular = prefiltered * (F * brdf.x + brdf.y);
+
+    return (diffuse + specular) * u_EnvironmentIntensity;
+}
+#endif
+
 void main() {
     vec3 N = v_Normal;//getNormal();
     vec3 V = normalize(u_Camera - v_Position);
@@ -264,7 +308,11 @@ void main() {
     // }
 
     // stop loop here.
+    #ifdef HAS_ENVIRONMENT_MAP
+    vec3 ambient = environmentLighting(N, V, F0, albedo, roughness, metallic);
+    #else
     vec3 ambient = u_AmbientLightColor * vec3(u_AmbientLightIntensity) * albedo;
+    #endif
     vec3 color   =  Lo + ambient;
     color = color / (color + vec3(1.0));
     //fragColor = vec4(ambient + specular_out, 1.0);
@@ -11,10 +11,11 @@ uniform vec3 light_position;
 
 out vec4 f_color;
 
+#include "lighting_common.glsl"
+
 void main() {
-    vec3 norm = normalize(texture(normal, v_uv).rgb);
-    vec3 lightDir = normalize(light_position.xyz - texture(position, v_uv).rgb);
-    float diff = max(dot(norm, lightDir), 0.0);
+    vec3 lightDir = light_position.xyz - texture(position, v_uv).rgb;
+    float diff = lambert(texture(normal, v_uv).rgb, lightDir);
 
     float light_distance = length(light_position.xyz - texture(position, v_uv).rgb);
     // Further decrease light_percent based on the distance with the light position.
@@ -2,9 +2,10 @@ in vec2 v_uv;
 out vec4 frag;
 
 uniform sampler2D tex;
+uniform vec3 tint;
 
 void main() {
     vec4 texture = texture(tex, v_uv);
     if ( texture.a < 0.1 ) discard;
-    frag = texture;
+    frag = vec4(texture.rgb * tint, texture.a);
 }
\ No newline at end of file
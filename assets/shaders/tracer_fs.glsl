@@ -0,0 +1,7 @@
+out vec4 frag;
+
+uniform vec4 color;
+
+void main() {
+    frag = color;
+}
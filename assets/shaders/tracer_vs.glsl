@@ -0,0 +1,16 @@
+// Unit quad along the z axis. The model matrix stretches it from the muzzle to the hit point
+// and orients it toward the camera.
+const vec3[4] QUAD_POS = vec3[](
+    vec3(-0.5, 0., 0.),
+    vec3( 0.5, 0., 0.),
+    vec3( 0.5, 0., 1.),
+    vec3(-0.5, 0., 1.)
+);
+
+uniform mat4 projection;
+uniform mat4 view;
+uniform mat4 model;
+
+void main() {
+    gl_Position = projection * view * model * vec4(QUAD_POS[gl_VertexID], 1.);
+}
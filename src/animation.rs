@@ -1,3 +1,4 @@
+use crate::ecs::{frames_to_update, UpdateRate};
 use crate::net::snapshot::Deltable;
 use crate::render::billboard::Billboard;
 use crate::render::sprite::SpriteRender;
@@ -29,6 +30,34 @@ impl Animation {
             elapsed_frame: 0,
         }
     }
+
+    /// Advance the animation by the given number of frames, going through as many keyframes
+    /// as needed. At most one loop of the animation is simulated, the rest would only bring it
+    /// back to the same keyframe. Returns true if a `single` animation has reached its end.
+    pub fn advance(&mut self, frames: usize) -> bool {
+        if self.keyframes.is_empty() {
+            return false;
+        }
+
+        self.elapsed_frame += frames;
+        if !self.single {
+            // Each keyframe lasts one more frame than its count.
+            let cycle: usize = self.keyframes.iter().map(|(_, n)| n + 1).sum();
+            self.elapsed_frame %= cycle;
+        }
+
+        while self.elapsed_frame > self.keyframes[self.current_index].1 {
+            self.elapsed_frame -= self.keyframes[self.current_index].1 + 1;
+            self.current_index = (self.current_index + 1) % self.keyframes.len();
+
+            if self.current_index == 0 && self.single {
+                self.elapsed_frame = 0;
+                return true;
+            }
+        }
+
+        false
+    }
 }
 
 /// All Animations for an entity
@@ -36,6 +65,7 @@ impl Animation {
 #[derive(Debug, Default, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct AnimationController {
     /// Animation will cycle through the sprites on its spritesheet
+    #[serde(serialize_with = "crate::ecs::serialization::serialize_sorted")]
     pub animations: HashMap<String, Animation>,
 
     /// if set to something, will play the corresponding animation
@@ -83,39 +113,43 @@ impl Animatable for Billboard {
     }
 }
 
-pub struct AnimationSystem;
+#[derive(Default)]
+pub struct AnimationSystem {
+    /// Number of calls to `animate`. Used for the entities with an `UpdateRate`.
+    frame: u64,
+}
 
 impl AnimationSystem {
     pub fn animate(&mut self, world: &mut hecs::World) {
         self.animate_impl::<SpriteRender>(world);
         self.animate_impl::<Billboard>(world);
+        self.frame += 1;
     }
 
     fn animate_impl<T>(&mut self, world: &mut hecs::World)
     where
         T: Animatable + 'static,
     {
-        for (e, (controller, sprite)) in world.query::<(&mut AnimationController, &mut T)>().iter()
+        for (e, (controller, sprite, rate)) in world
+            .query::<(&mut AnimationController, &mut T, Option<&UpdateRate>)>()
+            .iter()
         {
+            // skipped frames are caught up so that the animation keeps the same speed.
+            let frames = frames_to_update(rate, e, self.frame);
+            if frames == 0 {
+                continue;
+            }
             info!("Process animation for {:?}", e);
             let mut animation_finished = false;
             if let Some(ref animation_name) = controller.current_animation {
                 info!("Current animation is {:?}", animation_name);
                 if let Some(ref mut animation) = controller.animations.get_mut(animation_name) {
-                    sprite.set_animation_frame(animation.keyframes[animation.current_index].0);
-                    info!(
-                        "Sprite set {:?}",
-                        animation.keyframes[animation.current_index].0
-                    );
-                    animation.elapsed_frame += 1;
-                    info!("{:?}", animation);
-                    if animation.elapsed_frame > animation.keyframes[animation.current_index].1 {
-                        animation.elapsed_frame = 0;
-                        animation.current_index =
-                            (animation.current_index + 1) % animation.keyframes.len();
-
-                        animation_finished = animation.current_index == 0 && animation.single;
+                    animation_finished = animation.advance(frames as usize);
+                    if let Some((sprite_nb, _)) = animation.keyframes.get(animation.current_index) {
+                        sprite.set_animation_frame(*sprite_nb);
+                        info!("Sprite set {:?}", sprite_nb);
                     }
+                    info!("{:?}", animation);
                 } else {
                     error!("Cannot find animation with name = {}", animation_name);
                 }
@@ -126,3 +160,44 @@ impl AnimationSystem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_one_frame_at_a_time() {
+        let mut animation = Animation::new(vec![(0, 1), (1, 0), (2, 2)]);
+        let mut sprites = vec![];
+        for _ in 0..7 {
+            animation.advance(1);
+            sprites.push(animation.keyframes[animation.current_index].0);
+        }
+        assert_eq!(vec![0, 1, 2, 2, 2, 0, 0], sprites);
+    }
+
+    #[test]
+    fn catch_up_goes_through_the_keyframes() {
+        let keyframes = vec![(0, 1), (1, 0), (2, 2)];
+        let mut step_by_step = Animation::new(keyframes.clone());
+        let mut caught_up = Animation::new(keyframes);
+        for frames in &[3, 4, 1, 20, 2] {
+            for _ in 0..*frames {
+                step_by_step.advance(1);
+            }
+            caught_up.advance(*frames);
+            assert_eq!(step_by_step, caught_up);
+        }
+    }
+
+    #[test]
+    fn catch_up_stops_at_the_end_of_single_animation() {
+        let mut animation = Animation::new(vec![(0, 1), (1, 1)]);
+        animation.single = true;
+        assert!(!animation.advance(3));
+        assert_eq!(1, animation.current_index);
+        assert!(animation.advance(100));
+        assert_eq!(0, animation.current_index);
+        assert_eq!(0, animation.elapsed_frame);
+    }
+}
@@ -2,12 +2,15 @@
 //! Material can also contain some textures (color, normal, ...) so the manager needs to load them from
 //! file.
 use crate::assets::{AbstractGraphicContext, Asset, AssetError, Loader};
+use crate::paths::Paths;
 use crate::render::mesh::ShaderFlags;
+use crate::render::texture;
 use bitflags::_core::cell::RefCell;
 use crossbeam_channel::unbounded;
 use image::RgbImage;
 use log::error;
 use log::info;
+use log::warn;
 use luminance::context::GraphicsContext;
 use luminance::pixel::NormRGB8UI;
 use luminance::state::GraphicsState;
@@ -19,12 +22,36 @@ use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::thread;
 
+/// Name of the material used when a primitive does not have any material.
+pub const DEFAULT_MATERIAL: &str = "default_material";
+
+/// Fallback for the default material, in case it is not in the asset directory.
+const EMBEDDED_DEFAULT_MATERIAL: &str = include_str!("../../assets/embedded/default_material.ron");
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Sampler {
     min_filter: Option<u32>,
     mag_filter: Option<u32>,
     wrap_s: u32,
     wrap_t: u32,
+
+    /// Anisotropic filtering level. Sharpens textures seen at grazing angles (floors, walls).
+    /// Ignored if not supported by the GPU.
+    #[serde(default)]
+    anisotropy: Option<f32>,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        // GL_REPEAT, like the gltf default.
+        Self {
+            min_filter: None,
+            mag_filter: None,
+            wrap_s: 10_497,
+            wrap_t: 10_497,
+            anisotropy: None,
+        }
+    }
 }
 
 #[derive(Default, Serialize, Deserialize)]
@@ -79,9 +106,55 @@ impl Material {
             ao: self.ao,
             alpha_cutoff: self.alpha_cutoff,
             emissive_factor: self.emissive_factor,
+            shader_flags: self.shader_flags,
             ..Material::default()
         }
     }
+
+    /// Material imported from a gltf file. Its textures are already on the GPU so there is
+    /// nothing to upload.
+    pub fn from_imported(imported: crate::render::mesh::material::Material) -> Material {
+        let normal_scale = imported.normal_scale.unwrap_or(1.0);
+        Material {
+            base_color: imported.base_color,
+            metallic_roughness_values: imported.metallic_roughness_values,
+            ao: imported.ao,
+            alpha_cutoff: imported.alpha_cutoff,
+            color_texture: imported.albedo_texture.map(|t| t.texture),
+            color_texture_data: imported
+                .color_texture_coord_set
+                .map(|coord| (Sampler::default(), coord)),
+            normal_texture: imported.normal_texture.map(|t| t.texture),
+            normal_texture_data: imported
+                .normal_texture_coord_set
+                .map(|coord| (Sampler::default(), coord, normal_scale)),
+            roughness_metallic_texture: imported.roughness_metallic_texture.map(|t| t.texture),
+            roughness_metallic_texture_data: imported
+                .roughness_metallic_texture_coord_set
+                .map(|coord| (Sampler::default(), coord)),
+            shader_flags: imported.shader_flags,
+            ..Material::default()
+        }
+    }
+
+    /// Shader flags that the textures of the material allow. `shader_flags` can have less of
+    /// them when a map is disabled from the editor.
+    pub fn texture_flags(&self) -> ShaderFlags {
+        let mut flags = ShaderFlags::empty();
+        flags.set(
+            ShaderFlags::HAS_COLOR_TEXTURE,
+            self.color_texture_data.is_some(),
+        );
+        flags.set(
+            ShaderFlags::HAS_NORMAL_TEXTURE,
+            self.normal_texture_data.is_some(),
+        );
+        flags.set(
+            ShaderFlags::HAS_ROUGHNESS_METALLIC_MAP,
+            self.roughness_metallic_texture_data.is_some(),
+        );
+        flags
+    }
 }
 
 /// WARNING !!! It's not safe to share opengl state between states and textures should be uploaded
@@ -91,15 +164,17 @@ unsafe impl Send for Material {}
 
 pub struct SyncMaterialLoader {
     base_path: PathBuf,
+    /// Anisotropy of the textures whose sampler does not have one.
+    default_anisotropy: Option<f32>,
 }
 
 impl SyncMaterialLoader {
-    pub fn new() -> Self {
-        let base_path_str = std::env::var("ASSET_PATH").unwrap_or("./".to_string());
-        let base_path = Path::new(&base_path_str);
+    pub fn new(paths: &Paths, default_anisotropy: Option<f32>) -> Self {
+        let base_path = Path::new(paths.asset_path());
 
         Self {
             base_path: base_path.join("material"),
+            default_anisotropy,
         }
     }
 }
@@ -110,31 +185,46 @@ impl Loader<Material> for SyncMaterialLoader {
         asset
     }
 
-    fn upload_to_gpu(&self, ctx: &mut GlfwSurface, inner: &mut Material) {
-        upload_to_gpu(ctx, inner);
+    fn upload_to_gpu(
+        &self,
+        ctx: &mut GlfwSurface,
+        asset_name: &str,
+        inner: &mut Material,
+    ) -> Result<(), AssetError> {
+        upload_to_gpu(ctx, asset_name, inner, self.default_anisotropy)
     }
 }
 
-fn upload_to_gpu(ctx: &mut GlfwSurface, inner: &mut Material) {
+fn upload_to_gpu(
+    ctx: &mut GlfwSurface,
+    asset_name: &str,
+    inner: &mut Material,
+    default_anisotropy: Option<f32>,
+) -> Result<(), AssetError> {
     if let Some(img) = inner.color_image.take() {
         if let Some((sampler, _)) = inner.color_texture_data.as_ref() {
-            let tex = load_with_sampler(ctx, img, sampler).unwrap(); // FIXME unwrap.
+            let texture_name = format!("{}_color", asset_name);
+            let tex = load_with_sampler(ctx, img, sampler, default_anisotropy, &texture_name)?;
             inner.color_texture = Some(tex);
         }
     }
     if let Some(img) = inner.normal_image.take() {
         if let Some((sampler, _, _)) = inner.normal_texture_data.as_ref() {
-            let tex = load_with_sampler(ctx, img, sampler).unwrap(); // FIXME unwrap.
+            let texture_name = format!("{}_normal", asset_name);
+            let tex = load_with_sampler(ctx, img, sampler, default_anisotropy, &texture_name)?;
             inner.normal_texture = Some(tex);
         }
     }
 
     if let Some(img) = inner.roughness_metallic_image.take() {
         if let Some((sampler, _)) = inner.roughness_metallic_texture_data.as_ref() {
-            let tex = load_with_sampler(ctx, img, sampler).unwrap(); // FIXME unwrap.
+            let texture_name = format!("{}_roughness_metallic", asset_name);
+            let tex = load_with_sampler(ctx, img, sampler, default_anisotropy, &texture_name)?;
             inner.roughness_metallic_texture = Some(tex);
         }
     }
+
+    Ok(())
 }
 
 fn load_material(base_path: &PathBuf, asset_name: &str, mut asset: Asset<Material>) {
@@ -146,7 +236,15 @@ fn load_material(base_path: &PathBuf, asset_name: &str, mut asset: Asset<Materia
         material_path.display()
     );
 
-    match fs::read_to_string(material_path)
+    let content = match fs::read_to_string(material_path) {
+        Err(e) if asset_name == DEFAULT_MATERIAL => {
+            info!("Cannot read default material ({}), use embedded one", e);
+            Ok(EMBEDDED_DEFAULT_MATERIAL.to_owned())
+        }
+        content => content,
+    };
+
+    match content
         .map_err(AssetError::IoError)
         .and_then(|content| ron::de::from_str::<Material>(&content).map_err(AssetError::DeserError))
     {
@@ -206,6 +304,8 @@ fn load_with_sampler(
     ctx: &mut GlfwSurface,
     img: image::RgbImage,
     mat_sampler: &Sampler,
+    default_anisotropy: Option<f32>,
+    texture_name: &str,
 ) -> Result<luminance::texture::Texture<Dim2, NormRGB8UI>, AssetError> {
     //
     let (width, height) = img.dimensions();
@@ -244,7 +344,8 @@ fn load_with_sampler(
     match mat_sampler.mag_filter {
         Some(9728) => sampler.mag_filter = MagFilter::Nearest,
         Some(9729) => sampler.mag_filter = MagFilter::Linear,
-        _ => (),
+        Some(other) => warn!("Unknown mag_filter {} for texture {}", other, texture_name),
+        None => (),
     }
     match mat_sampler.min_filter {
         Some(9728) => sampler.min_filter = MinFilter::Nearest,
@@ -253,25 +354,33 @@ fn load_with_sampler(
         Some(9985) => sampler.min_filter = MinFilter::LinearMipmapNearest,
         Some(9986) => sampler.min_filter = MinFilter::NearestMipmapLinear,
         Some(9984) => sampler.min_filter = MinFilter::NearestMipmapNearest,
-        _ => (),
+        Some(other) => warn!("Unknown min_filter {} for texture {}", other, texture_name),
+        None => (),
     }
     match mat_sampler.wrap_s {
         10_497 => sampler.wrap_s = Wrap::Repeat,
         33_648 => sampler.wrap_s = Wrap::MirroredRepeat,
         33_071 => sampler.wrap_s = Wrap::ClampToEdge,
-        _ => (),
+        other => warn!("Unknown wrap_s {} for texture {}", other, texture_name),
     }
 
     match mat_sampler.wrap_t {
         10_497 => sampler.wrap_t = Wrap::Repeat,
         33_648 => sampler.wrap_t = Wrap::MirroredRepeat,
         33_071 => sampler.wrap_t = Wrap::ClampToEdge,
-        _ => (),
+        other => warn!("Unknown wrap_t {} for texture {}", other, texture_name),
     }
 
-    let tex = luminance::texture::Texture::new(ctx, [width, height], 0, sampler).unwrap();
+    let upload_error = |e| AssetError::UploadError(texture_name.to_owned(), format!("{:?}", e));
+    let tex =
+        luminance::texture::Texture::new(ctx, [width, height], 0, sampler).map_err(upload_error)?;
+    let handle = texture::bound_texture_2d();
 
-    tex.upload_raw(GenMipmaps::No, &texels).unwrap();
+    tex.upload_raw(GenMipmaps::No, &texels)
+        .map_err(upload_error)?;
+    if let Some(level) = mat_sampler.anisotropy.or(default_anisotropy) {
+        texture::set_anisotropy(handle, level);
+    }
     Ok(tex)
 }
 
@@ -283,23 +392,26 @@ fn read_image<P: AsRef<Path>>(path: P) -> Result<image::RgbImage, image::ImageEr
 pub struct AsyncMaterialLoader {
     child_thread: thread::JoinHandle<()>,
     tx: crossbeam_channel::Sender<(Asset<Material>, String)>,
+    /// Anisotropy of the textures whose sampler does not have one.
+    default_anisotropy: Option<f32>,
 }
 
 impl AsyncMaterialLoader {
-    pub fn new() -> Self {
+    pub fn new(paths: &Paths, default_anisotropy: Option<f32>) -> Self {
         let (tx, rx) = unbounded::<(Asset<Material>, String)>();
+        let base_path = Path::new(paths.asset_path()).join("material");
         let child_thread = thread::spawn(move || {
-            let base_path_str = std::env::var("ASSET_PATH").unwrap_or("./".to_string());
-            let base_path = Path::new(&base_path_str);
-            let base_path = base_path.join("material");
-
             //            let mut ctx = AbstractGraphicContext::new();
             while let Ok((asset, asset_name)) = rx.recv() {
                 load_material(&base_path, asset_name.as_str(), asset);
             }
         });
 
-        Self { child_thread, tx }
+        Self {
+            child_thread,
+            tx,
+            default_anisotropy,
+        }
     }
 }
 
@@ -312,7 +424,12 @@ impl Loader<Material> for AsyncMaterialLoader {
         asset
     }
 
-    fn upload_to_gpu(&self, ctx: &mut GlfwSurface, inner: &mut Material) {
-        upload_to_gpu(ctx, inner);
+    fn upload_to_gpu(
+        &self,
+        ctx: &mut GlfwSurface,
+        asset_name: &str,
+        inner: &mut Material,
+    ) -> Result<(), AssetError> {
+        upload_to_gpu(ctx, asset_name, inner, self.default_anisotropy)
     }
 }
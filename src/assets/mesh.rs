@@ -1,4 +1,5 @@
 use crate::assets::{AbstractGraphicContext, Asset, AssetError, Loader};
+use crate::paths::Paths;
 use crate::render::mesh::mesh::Mesh;
 use crate::render::mesh::primitive::Primitive;
 use crate::render::mesh::{
@@ -13,6 +14,10 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 pub type MaterialId = Option<String>;
 use std::rc::Rc;
+
+/// Name of the mesh used when a mesh cannot be loaded. If there is no mesh with this name in the
+/// asset directory, a cube built in code is used.
+pub const DEFAULT_MESH: &str = "default_mesh";
 // TODO figure out how to not duplicate.
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct RawMesh {
@@ -43,9 +48,8 @@ pub struct SyncMeshLoader {
 }
 
 impl SyncMeshLoader {
-    pub fn new(surface: &mut GlfwSurface) -> Self {
-        let base_path_str = std::env::var("ASSET_PATH").unwrap_or("./".to_string());
-        let base_path = Path::new(&base_path_str);
+    pub fn new(surface: &mut GlfwSurface, paths: &Paths) -> Self {
+        let base_path = Path::new(paths.asset_path());
         Self {
             base_path: base_path.join("mesh"),
             ctx: AbstractGraphicContext::from_glfw(surface),
@@ -58,10 +62,17 @@ impl Loader<Mesh> for SyncMeshLoader {
         info!("Loading mesh {}", asset_name);
         let mesh_from_path = self.base_path.join(asset_name.to_owned() + ".bincode");
 
-        return match std::fs::read(mesh_from_path)
-            .map_err(AssetError::IoError)
-            .and_then(|buf| bincode::deserialize::<RawMesh>(&buf).map_err(AssetError::BincodeError))
-        {
+        let raw_mesh = match std::fs::read(mesh_from_path) {
+            Err(e) if asset_name == DEFAULT_MESH => {
+                info!("Cannot read default mesh ({}), use embedded cube", e);
+                Ok(cube())
+            }
+            buf => buf.map_err(AssetError::IoError).and_then(|buf| {
+                bincode::deserialize::<RawMesh>(&buf).map_err(AssetError::BincodeError)
+            }),
+        };
+
+        return match raw_mesh {
             Ok(meshLoaded) => {
                 info!("Successfully deserialized asset file");
                 let mut primitives = vec![];
@@ -102,3 +113,56 @@ impl Loader<Mesh> for SyncMeshLoader {
         };
     }
 }
+
+/// 2x2x2 cube centered on the origin (same size as the default rigid body). Each face has its own
+/// vertices so that normals are not shared between faces.
+fn cube() -> RawMesh {
+    // (normal, u, v) with u x v = normal so that triangles are counter-clockwise.
+    let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+    ];
+    let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+    let mut vertex_buffer = vec![];
+    let mut index_buffer = vec![];
+    for (normal, u, v) in faces.iter() {
+        let first_index = vertex_buffer.len() as u32;
+        for (cu, cv) in corners.iter() {
+            let position = [
+                normal[0] + cu * u[0] + cv * v[0],
+                normal[1] + cu * u[1] + cv * v[1],
+                normal[2] + cu * u[2] + cv * v[2],
+            ];
+            vertex_buffer.push(RawVertex {
+                position,
+                normal: *normal,
+                tangent: [u[0], u[1], u[2], 1.0],
+                color: [1.0, 1.0, 1.0, 1.0],
+                tex_coord_0: [(cu + 1.0) / 2.0, (cv + 1.0) / 2.0],
+                tex_coord_1: [(cu + 1.0) / 2.0, (cv + 1.0) / 2.0],
+            });
+        }
+        index_buffer.extend_from_slice(&[
+            first_index,
+            first_index + 1,
+            first_index + 2,
+            first_index,
+            first_index + 2,
+            first_index + 3,
+        ]);
+    }
+
+    RawMesh {
+        primitives: vec![RawPrimitive {
+            vertex_buffer,
+            index_buffer: Some(index_buffer),
+            mode: gltf::mesh::Mode::Triangles,
+            material: None,
+        }],
+    }
+}
@@ -3,8 +3,11 @@
 use crate::assets::material::{AsyncMaterialLoader, Material};
 use crate::assets::mesh::SyncMeshLoader;
 use crate::assets::LoadingStatus::Loaded;
+use crate::paths::Paths;
 use crate::render::mesh::mesh::Mesh;
+use crate::render::RenderConfig;
 use crate::resources::Resources;
+use log::error;
 use luminance::context::GraphicsContext;
 use luminance::state::GraphicsState;
 use luminance_glfw::GlfwSurface;
@@ -21,11 +24,19 @@ pub mod mesh;
 pub mod prefab;
 
 pub fn create_asset_managers(surface: &mut GlfwSurface, resources: &mut Resources) {
+    let paths = resources.fetch::<Paths>().unwrap().clone();
+    let (upload_budget, anisotropy) = resources
+        .fetch::<RenderConfig>()
+        .map(|conf| (conf.upload_budget, conf.anisotropy))
+        .unwrap_or_default();
     let mut mesh_manager: AssetManager<Mesh> =
-        AssetManager::from_loader(Box::new(SyncMeshLoader::new(surface)));
+        AssetManager::from_loader(Box::new(SyncMeshLoader::new(surface, &paths)));
     let mut material_manager: AssetManager<Material> =
-        AssetManager::from_loader(Box::new(AsyncMaterialLoader::new()));
-    material_manager.load("default_material");
+        AssetManager::from_loader(Box::new(AsyncMaterialLoader::new(&paths, anisotropy)));
+    mesh_manager.set_upload_budget(upload_budget);
+    material_manager.set_upload_budget(upload_budget);
+    material_manager.load(material::DEFAULT_MATERIAL);
+    mesh_manager.load(mesh::DEFAULT_MESH);
     material_manager.load("material_Floor");
     mesh_manager.load("_simple_sphere_Sphere");
     mesh_manager.load("material_Cube");
@@ -69,6 +80,9 @@ pub enum AssetError {
 
     #[error(transparent)]
     BincodeError(#[from] bincode::Error),
+
+    #[error("Cannot upload {0} to the GPU = {1}")]
+    UploadError(String, String),
 }
 
 pub struct Asset<T> {
@@ -142,6 +156,14 @@ impl<T> Asset<T> {
         }
     }
 
+    pub fn status(&self) -> AssetStatus {
+        match &*self.asset.lock().unwrap() {
+            LoadingStatus::Ready(_) => AssetStatus::Ready,
+            LoadingStatus::Loaded(_) | LoadingStatus::Loading => AssetStatus::Loading,
+            LoadingStatus::Error(_) => AssetStatus::Error,
+        }
+    }
+
     /// Execute a function only if the asset is loaded.
     pub fn execute<F>(&self, mut f: F)
     where
@@ -177,6 +199,15 @@ impl<T: Clone> Asset<T> {
     }
 }
 
+/// Simplified `LoadingStatus`, without the asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetStatus {
+    /// Not loaded yet or not uploaded to the GPU yet.
+    Loading,
+    Ready,
+    Error,
+}
+
 pub enum LoadingStatus<T, E> {
     Ready(T),
     Loaded(T),
@@ -197,6 +228,10 @@ pub struct AssetManager<T: Default> {
     // might want to use a LRU instead...
     store: HashMap<Handle, Asset<T>>,
     loader: Box<dyn Loader<T>>,
+
+    /// Maximum number of assets uploaded to the GPU per call to `upload_all`. None means no
+    /// limit.
+    upload_budget: Option<usize>,
 }
 
 impl<T: Default> AssetManager<T> {
@@ -204,9 +239,18 @@ impl<T: Default> AssetManager<T> {
         Self {
             store: HashMap::new(),
             loader,
+            upload_budget: None,
         }
     }
 
+    /// Limit the number of assets uploaded per frame. When a lot of assets finish loading at
+    /// the same time, the uploads will be spread over several frames instead of making the
+    /// game freeze. A budget of 0 would never upload anything so at least one asset is
+    /// uploaded per frame.
+    pub fn set_upload_budget(&mut self, budget: Option<usize>) {
+        self.upload_budget = budget.map(|budget| budget.max(1));
+    }
+
     pub fn load(&mut self, asset_name: &str) -> Handle {
         let handle = Handle(asset_name.to_owned());
         if self.store.contains_key(&handle) {
@@ -217,13 +261,35 @@ impl<T: Default> AssetManager<T> {
         handle
     }
 
+    /// Add an asset that was created outside of the loader (for example when importing a gltf
+    /// file). It replaces any asset with the same name.
+    pub fn insert(&mut self, asset_name: &str, asset: T) -> Handle {
+        let handle = Handle(asset_name.to_owned());
+        self.store.insert(handle.clone(), Asset::from_asset(asset));
+        handle
+    }
+
     pub fn upload_all(&mut self, ctx: &mut GlfwSurface) {
         // once every now and then, check the resources ready to be uploaded by the current thread.
-        for asset in self.store.values() {
+        let mut uploaded = 0;
+        for (handle, asset) in self.store.iter() {
+            if let Some(budget) = self.upload_budget {
+                if uploaded >= budget {
+                    // The rest will be uploaded during the next frames.
+                    break;
+                }
+            }
+
             let mut asset = &mut *asset.asset.lock().unwrap();
             if let LoadingStatus::Loaded(ref mut t) = asset {
                 // UPLOAD
-                self.loader.upload_to_gpu(ctx, t);
+                uploaded += 1;
+                if let Err(e) = self.loader.upload_to_gpu(ctx, &handle.0, t) {
+                    // The lock is already held so `set_error` cannot be used here.
+                    error!("{}", e);
+                    *asset = LoadingStatus::Error(e);
+                    continue;
+                }
             }
             asset.move_to_read();
         }
@@ -255,11 +321,26 @@ impl<T: Default> AssetManager<T> {
     pub fn keys(&self) -> Keys<Handle, Asset<T>> {
         self.store.keys()
     }
+
+    /// Return the assets that are currently managed with their status.
+    pub fn iter_status(&self) -> impl Iterator<Item = (&Handle, AssetStatus)> {
+        self.store
+            .iter()
+            .map(|(handle, asset)| (handle, asset.status()))
+    }
 }
 
 pub trait Loader<T> {
     /// Get an asset from an handle
     fn load(&mut self, asset_name: &str) -> Asset<T>;
 
-    fn upload_to_gpu(&self, ctx: &mut GlfwSurface, inner: &mut T) {}
+    /// Upload the data of a loaded asset to the GPU. On error, the asset is marked as failed.
+    fn upload_to_gpu(
+        &self,
+        ctx: &mut GlfwSurface,
+        asset_name: &str,
+        inner: &mut T,
+    ) -> Result<(), AssetError> {
+        Ok(())
+    }
 }
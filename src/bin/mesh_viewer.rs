@@ -2,7 +2,7 @@ use luminance_glfw::{Action, GlfwSurface, Key, Surface, WindowDim, WindowOpt};
 use std::process::exit;
 use std::time::{Duration, Instant};
 
-use imgui::{Context, FontConfig, FontGlyphRanges, FontSource};
+use imgui::{im_str, Context, FontConfig, FontGlyphRanges, FontSource};
 #[allow(unused_imports)]
 use log::{debug, error, info};
 use luminance_windowing::CursorMode;
@@ -13,6 +13,7 @@ use r3dtest::camera::Camera;
 use r3dtest::colors::RgbColor;
 use r3dtest::controller::free::FreeController;
 use r3dtest::controller::{client, Controller, Fps};
+use r3dtest::ecs::serialization::{self, SerializedEntity};
 use r3dtest::ecs::WorldLoader;
 use r3dtest::event::Event;
 use r3dtest::gameplay::delete::GarbageCollector;
@@ -23,6 +24,7 @@ use r3dtest::gameplay::player::{
     spawn_player, update_player_orientations, MainPlayer, PlayerSystem,
 };
 use r3dtest::gameplay::ui::UiSystem;
+use r3dtest::paths::Paths;
 use r3dtest::physics::{BodyToEntity, PhysicWorld};
 use r3dtest::render::debug::update_debug_components;
 use r3dtest::render::lighting::{AmbientLight, DirectionalLight};
@@ -35,8 +37,12 @@ use r3dtest::{
 use serde::de::DeserializeOwned;
 use serde_derive::{Deserialize, Serialize};
 use shrev::EventChannel;
+use std::f32::consts::{FRAC_PI_2, PI};
 use std::fs::{self};
 
+/// Displayed when no asset is given on the command line or when it cannot be loaded.
+const DEFAULT_MESH: &str = "_simple_sphere_Sphere";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WindowConfig {
     width: u32,
@@ -47,9 +53,10 @@ fn main() {
     dotenv::dotenv().ok().unwrap();
     pretty_env_logger::init();
 
-    let map_name: String = std::env::args().nth(1).unwrap_or("lol.ron".to_string());
-    let window_config =
-        fs::read_to_string(std::env::var("CONFIG_PATH").unwrap() + "config.ron").unwrap();
+    // mesh or prefab to display.
+    let asset_name: Option<String> = std::env::args().nth(1);
+    let paths = Paths::from_env();
+    let window_config = fs::read_to_string(paths.config("config.ron")).unwrap();
     let conf: WindowConfig = ron::de::from_str(&window_config).unwrap();
     let surface = GlfwSurface::new(
         WindowDim::Windowed(conf.width, conf.height),
@@ -60,7 +67,7 @@ fn main() {
     match surface {
         Ok(surface) => {
             debug!("Will enter main loop");
-            main_loop(surface, map_name);
+            main_loop(surface, asset_name, paths);
         }
         Err(e) => {
             error!("Cannot create graphic surface: {}", e);
@@ -71,7 +78,8 @@ fn main() {
 }
 
 fn load_optional_config<T: DeserializeOwned + 'static>(path: &str, resources: &mut Resources) {
-    if let Ok(conf_str) = fs::read_to_string(std::env::var("CONFIG_PATH").unwrap() + path) {
+    let conf_path = resources.fetch::<Paths>().unwrap().config(path);
+    if let Ok(conf_str) = fs::read_to_string(conf_path) {
         let conf: Result<T, _> = ron::de::from_str(&conf_str);
         if let Ok(conf) = conf {
             resources.insert(conf);
@@ -84,8 +92,9 @@ fn load_optional_config<T: DeserializeOwned + 'static>(path: &str, resources: &m
     }
 }
 
-fn setup_resources() -> Resources {
+fn setup_resources(paths: Paths) -> Resources {
     let mut resources = Resources::default();
+    resources.insert(paths);
     let event_channel: EventChannel<GameEvent> = EventChannel::new();
     resources.insert(event_channel);
     let input = Input::new();
@@ -104,10 +113,10 @@ enum ControllerMode {
     Editor,
 }
 
-fn main_loop(mut surface: GlfwSurface, map_name: String) {
+fn main_loop(mut surface: GlfwSurface, asset_name: Option<String>, paths: Paths) {
     let mut world = hecs::World::new();
 
-    let mut resources = setup_resources();
+    let mut resources = setup_resources(paths);
 
     let mut garbage_collector = GarbageCollector::new(&mut resources);
 
@@ -167,27 +176,27 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
         },
     ));
 
-    // a sphere
-    world.spawn((
-        Transform::default(),
-        Render {
-            mesh: "_simple_sphere_Sphere".to_string(),
-            enabled: true,
-        },
-    ));
+    let previewed = match asset_name {
+        Some(name) => spawn_asset(&mut world, &resources, &name),
+        None => spawn_mesh(&mut world, DEFAULT_MESH),
+    };
 
     // some lights
-    world.spawn((AmbientLight {
+    let ambient_light = world.spawn((AmbientLight {
         color: r3dtest::colors::PASTEL_RED,
         intensity: 0.2,
     },));
-    world.spawn((DirectionalLight {
+    let directional_light = world.spawn((DirectionalLight {
         direction: glam::vec3(1.0, 11.0, 1.0),
         color: r3dtest::colors::PASTEL_RED,
         intensity: 0.2,
     },));
+    let mut turntable = Turntable::default();
+    let mut last_frame = Instant::now();
 
     'app: loop {
+        let elapsed = last_frame.elapsed();
+        last_frame = Instant::now();
         {
             let mut input = resources.fetch_mut::<Input>().unwrap();
             if let ControllerMode::Editor = controller_mode {
@@ -210,6 +219,7 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
         if let ControllerMode::Free = controller_mode {
             free_controller.process_input(&mut world, &mut resources, free_camera);
         }
+        turntable.update(&world, previewed, elapsed);
         renderer.update_view_matrix(&world);
         r3dtest::transform::update_transforms(&mut world);
         renderer.update(&mut world, dt, &mut resources);
@@ -220,6 +230,13 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
         // render the editor.
         let ui = imgui.frame();
         editor.show_components(&ui, &world, &mut resources);
+        show_viewer_controls(
+            &ui,
+            &world,
+            &mut turntable,
+            ambient_light,
+            directional_light,
+        );
         let draw_data = ui.render();
         imgui_renderer.prepare(&mut surface, draw_data);
         renderer.render(
@@ -246,6 +263,113 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
     }
 }
 
+/// Rotate the previewed entity around the Y axis.
+struct Turntable {
+    enabled: bool,
+    /// Radians per second.
+    speed: f32,
+}
+
+impl Default for Turntable {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            speed: 0.5,
+        }
+    }
+}
+
+impl Turntable {
+    fn update(&self, world: &hecs::World, e: hecs::Entity, elapsed: Duration) {
+        if !self.enabled {
+            return;
+        }
+        if let Ok(mut t) = world.get_mut::<Transform>(e) {
+            let angle = self.speed * elapsed.as_secs_f32();
+            t.rotation = glam::Quat::from_rotation_y(angle) * t.rotation;
+            t.dirty = true;
+        }
+    }
+}
+
+/// Turntable and lighting of the preview.
+fn show_viewer_controls(
+    ui: &imgui::Ui,
+    world: &hecs::World,
+    turntable: &mut Turntable,
+    ambient_light: hecs::Entity,
+    directional_light: hecs::Entity,
+) {
+    imgui::Window::new(im_str!("Viewer"))
+        .position([10.0, 10.0], imgui::Condition::FirstUseEver)
+        .size([300.0, 170.0], imgui::Condition::FirstUseEver)
+        .build(ui, || {
+            ui.checkbox(im_str!("Turntable"), &mut turntable.enabled);
+            imgui::Slider::new(im_str!("Speed (rad/s)"), -PI..=PI).build(ui, &mut turntable.speed);
+
+            ui.separator();
+            if let Ok(mut light) = world.get_mut::<DirectionalLight>(directional_light) {
+                let (mut azimuth, mut elevation) = light.angles();
+                let mut changed =
+                    imgui::Slider::new(im_str!("Light azimuth"), -PI..=PI).build(ui, &mut azimuth);
+                changed |= imgui::Slider::new(im_str!("Light elevation"), -FRAC_PI_2..=FRAC_PI_2)
+                    .build(ui, &mut elevation);
+                if changed {
+                    light.set_angles(azimuth, elevation);
+                }
+                imgui::Slider::new(im_str!("Light intensity"), 0.0..=1.0)
+                    .build(ui, &mut light.intensity);
+            }
+            if let Ok(mut light) = world.get_mut::<AmbientLight>(ambient_light) {
+                imgui::Slider::new(im_str!("Ambient intensity"), 0.0..=1.0)
+                    .build(ui, &mut light.intensity);
+            }
+        });
+}
+
+/// Spawn the asset at the origin. The name is first looked up in the prefabs
+/// (`assets/prefab/<name>.ron`), then in the meshes.
+fn spawn_asset(world: &mut hecs::World, resources: &Resources, name: &str) -> hecs::Entity {
+    let prefab_path = resources
+        .fetch::<Paths>()
+        .unwrap()
+        .asset(&format!("prefab/{}.ron", name));
+    if let Ok(prefab) = fs::read_to_string(&prefab_path) {
+        match ron::de::from_str::<SerializedEntity>(&prefab) {
+            Ok(ser) => {
+                info!("Display prefab {}", name);
+                let e = serialization::spawn_entity(world, &ser);
+                if let Ok(mut t) = world.get_mut::<Transform>(e) {
+                    t.translation = glam::Vec3::zero();
+                    t.dirty = true;
+                }
+                return e;
+            }
+            Err(e) => error!("Cannot deserialize prefab {}: {}", prefab_path, e),
+        }
+    }
+
+    let mut mesh_manager = resources.fetch_mut::<AssetManager<Mesh>>().unwrap();
+    let handle = mesh_manager.load(name);
+    if mesh_manager.is_error(&handle) {
+        error!("Cannot load {}, display {} instead", name, DEFAULT_MESH);
+        spawn_mesh(world, DEFAULT_MESH)
+    } else {
+        info!("Display mesh {}", name);
+        spawn_mesh(world, name)
+    }
+}
+
+fn spawn_mesh(world: &mut hecs::World, mesh: &str) -> hecs::Entity {
+    world.spawn((
+        Transform::default(),
+        Render {
+            mesh: mesh.to_string(),
+            enabled: true,
+        },
+    ))
+}
+
 fn editor_mode(
     surface: &mut GlfwSurface,
     current_controller_mode: &mut ControllerMode,
@@ -0,0 +1,245 @@
+//! Bounding volume hierarchy over static AABBs. Built once, then used for ray and overlap
+//! queries without testing every box.
+//!
+//! Nodes are stored in a flat vector; a node is split along the longest axis of its bounds,
+//! at the median of the children centers.
+use super::{Aabb, Ray};
+
+/// Maximum number of items in a leaf.
+const LEAF_SIZE: usize = 4;
+
+#[derive(Debug)]
+enum Node {
+    Leaf {
+        aabb: Aabb,
+        first: usize,
+        count: usize,
+    },
+    Internal {
+        aabb: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl Node {
+    fn aabb(&self) -> &Aabb {
+        match self {
+            Node::Leaf { aabb, .. } | Node::Internal { aabb, .. } => aabb,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Bvh<T> {
+    nodes: Vec<Node>,
+    /// Items, ordered so that each leaf points to a contiguous slice.
+    items: Vec<(Aabb, T)>,
+}
+
+impl<T: Clone> Bvh<T> {
+    pub fn new(items: &[(Aabb, T)]) -> Self {
+        let mut bvh = Self {
+            nodes: vec![],
+            items: items.to_vec(),
+        };
+
+        if !bvh.items.is_empty() {
+            bvh.build(0, bvh.items.len());
+        }
+
+        bvh
+    }
+}
+
+impl<T> Bvh<T> {
+    /// Build the node for items[first..first+count]. Return the index of the node.
+    fn build(&mut self, first: usize, count: usize) -> usize {
+        let slice = &mut self.items[first..first + count];
+        let aabb = slice
+            .iter()
+            .skip(1)
+            .fold(slice[0].0, |acc, (aabb, _)| acc.merged(aabb));
+
+        if count <= LEAF_SIZE {
+            self.nodes.push(Node::Leaf { aabb, first, count });
+            return self.nodes.len() - 1;
+        }
+
+        // split along the longest axis.
+        let extents: [f32; 3] = (aabb.max - aabb.min).into();
+        let axis = if extents[0] >= extents[1] && extents[0] >= extents[2] {
+            0
+        } else if extents[1] >= extents[2] {
+            1
+        } else {
+            2
+        };
+        slice.sort_by(|(a, _), (b, _)| {
+            let a: [f32; 3] = a.center().into();
+            let b: [f32; 3] = b.center().into();
+            a[axis]
+                .partial_cmp(&b[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        // Reserve the slot so that the parent is before its children.
+        let idx = self.nodes.len();
+        self.nodes.push(Node::Leaf { aabb, first, count });
+        let half = count / 2;
+        let left = self.build(first, half);
+        let right = self.build(first + half, count - half);
+        self.nodes[idx] = Node::Internal { aabb, left, right };
+        idx
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Closest item hit by the ray, with the time of impact.
+    pub fn raycast(&self, ray: Ray) -> Option<(f32, &T)> {
+        let mut closest: Option<(f32, &T)> = None;
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            match node.aabb().raycast(&ray) {
+                Some(toi) if closest.map(|(best, _)| toi < best).unwrap_or(true) => (),
+                _ => continue,
+            }
+
+            match node {
+                Node::Leaf { first, count, .. } => {
+                    for (aabb, item) in &self.items[*first..*first + *count] {
+                        if let Some(toi) = aabb.raycast(&ray) {
+                            if closest.map(|(best, _)| toi < best).unwrap_or(true) {
+                                closest = Some((toi, item));
+                            }
+                        }
+                    }
+                }
+                Node::Internal { left, right, .. } => {
+                    stack.push(*left);
+                    stack.push(*right);
+                }
+            }
+        }
+
+        closest
+    }
+
+    /// All the items whose bounding box intersects the given one.
+    pub fn query_aabb(&self, aabb: Aabb) -> Vec<&T> {
+        let mut result = vec![];
+        if self.nodes.is_empty() {
+            return result;
+        }
+
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if !node.aabb().intersects(&aabb) {
+                continue;
+            }
+
+            match node {
+                Node::Leaf { first, count, .. } => {
+                    result.extend(
+                        self.items[*first..*first + *count]
+                            .iter()
+                            .filter(|(item_aabb, _)| item_aabb.intersects(&aabb))
+                            .map(|(_, item)| item),
+                    );
+                }
+                Node::Internal { left, right, .. } => {
+                    stack.push(*left);
+                    stack.push(*right);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    /// 10x10 grid of unit boxes on the XZ plane, spaced by 2 units. Item is (i, j).
+    fn grid() -> Vec<(Aabb, (usize, usize))> {
+        let mut boxes = vec![];
+        for i in 0..10 {
+            for j in 0..10 {
+                let center = glam::vec3(2.0 * i as f32, 0.0, 2.0 * j as f32);
+                boxes.push((Aabb::from_center(center, glam::vec3(0.5, 0.5, 0.5)), (i, j)));
+            }
+        }
+        boxes
+    }
+
+    #[test]
+    fn raycast_closest() {
+        let bvh = Bvh::new(&grid());
+        assert_eq!(100, bvh.len());
+
+        // along the row i = 3, from outside the grid.
+        let ray = Ray::new(glam::vec3(6.0, 0.0, -10.0), glam::vec3(0.0, 0.0, 1.0));
+        let (toi, item) = bvh.raycast(ray).unwrap();
+        assert_eq!((3, 0), *item);
+        assert!((toi - 9.5).abs() < 1e-5);
+
+        // opposite direction.
+        let ray = Ray::new(glam::vec3(6.0, 0.0, 30.0), glam::vec3(0.0, 0.0, -1.0));
+        let (_, item) = bvh.raycast(ray).unwrap();
+        assert_eq!((3, 9), *item);
+
+        // from above.
+        let ray = Ray::new(glam::vec3(10.0, 10.0, 14.0), glam::vec3(0.0, -1.0, 0.0));
+        let (toi, item) = bvh.raycast(ray).unwrap();
+        assert_eq!((5, 7), *item);
+        assert!((toi - 9.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn raycast_miss() {
+        let bvh = Bvh::new(&grid());
+
+        // between two rows.
+        let ray = Ray::new(glam::vec3(5.0, 0.0, -10.0), glam::vec3(0.0, 0.0, 1.0));
+        assert!(bvh.raycast(ray).is_none());
+
+        // pointing away.
+        let ray = Ray::new(glam::vec3(6.0, 0.0, -10.0), glam::vec3(0.0, 0.0, -1.0));
+        assert!(bvh.raycast(ray).is_none());
+
+        let empty: Bvh<usize> = Bvh::new(&[]);
+        assert!(empty.raycast(ray).is_none());
+    }
+
+    #[test]
+    fn query_aabb_in_grid() {
+        let bvh = Bvh::new(&grid());
+
+        // covers boxes i in [1, 2], j in [4, 6]
+        let query = Aabb::new(glam::vec3(1.8, -1.0, 7.8), glam::vec3(4.2, 1.0, 12.2));
+        let mut result: Vec<(usize, usize)> = bvh.query_aabb(query).into_iter().cloned().collect();
+        result.sort();
+        assert_eq!(vec![(1, 4), (1, 5), (1, 6), (2, 4), (2, 5), (2, 6)], result);
+
+        let everything = Aabb::new(Vec3::splat(-100.0), Vec3::splat(100.0));
+        assert_eq!(100, bvh.query_aabb(everything).len());
+
+        let nothing = Aabb::new(Vec3::splat(50.0), Vec3::splat(60.0));
+        assert!(bvh.query_aabb(nothing).is_empty());
+    }
+}
@@ -0,0 +1,197 @@
+//! Axis-aligned bounding boxes and rays, for custom queries that do not need to go through
+//! the physics world (editor picking, line of sight...).
+use glam::Vec3;
+
+pub mod bvh;
+pub mod sweep;
+pub use bvh::Bvh;
+pub use sweep::resolve_movement;
+
+/// Half-line starting at `origin`. The direction does not need to be normalized, the time of
+/// impact returned by the queries is expressed in units of `direction`.
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction }
+    }
+
+    pub fn point_at(&self, toi: f32) -> Vec3 {
+        self.origin + self.direction * toi
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// Create the bounding box from its center and half size on each axis.
+    pub fn from_center(center: Vec3, half_extents: Vec3) -> Self {
+        Self {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Smallest bounding box that contains both boxes.
+    pub fn merged(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// True if the boxes overlap. Touching boxes are not intersecting.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x() < other.max.x()
+            && self.max.x() > other.min.x()
+            && self.min.y() < other.max.y()
+            && self.max.y() > other.min.y()
+            && self.min.z() < other.max.z()
+            && self.max.z() > other.min.z()
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        point.x() >= self.min.x()
+            && point.x() <= self.max.x()
+            && point.y() >= self.min.y()
+            && point.y() <= self.max.y()
+            && point.z() >= self.min.z()
+            && point.z() <= self.max.z()
+    }
+
+    /// Time of impact of the ray with the box (slab method). If the ray starts inside the
+    /// box, the time of impact is 0.
+    pub fn raycast(&self, ray: &Ray) -> Option<f32> {
+        let origin: [f32; 3] = ray.origin.into();
+        let direction: [f32; 3] = ray.direction.into();
+        let min: [f32; 3] = self.min.into();
+        let max: [f32; 3] = self.max.into();
+
+        let mut t_min = 0.0f32;
+        let mut t_max = std::f32::INFINITY;
+        let slabs = origin.iter().zip(&direction).zip(min.iter().zip(&max));
+        for ((&o, &d), (&lo, &hi)) in slabs {
+            if d.abs() < std::f32::EPSILON {
+                // parallel to the slab, the origin has to be between the planes.
+                if o < lo || o > hi {
+                    return None;
+                }
+            } else {
+                let inv = 1.0 / d;
+                let mut t1 = (lo - o) * inv;
+                let mut t2 = (hi - o) * inv;
+                if t1 > t2 {
+                    std::mem::swap(&mut t1, &mut t2);
+                }
+                t_min = t_min.max(t1);
+                t_max = t_max.min(t2);
+                if t_min > t_max {
+                    return None;
+                }
+            }
+        }
+
+        Some(t_min)
+    }
+
+    /// Time of impact, as a fraction of `displacement`, when moving this box toward a static
+    /// one. None if they do not collide during the movement or if they already overlap.
+    pub fn intersect_moving(&self, displacement: Vec3, other: &Aabb) -> Option<f32> {
+        self.sweep(displacement, other).map(|(toi, _)| toi)
+    }
+
+    /// Same as `intersect_moving` but also returns the normal of the face of `other` that is
+    /// hit.
+    pub fn sweep(&self, displacement: Vec3, other: &Aabb) -> Option<(f32, Vec3)> {
+        // Minkowski sum: the problem becomes a ray (center of this box) against the other box
+        // extended by our half extents.
+        let expanded =
+            Aabb::from_center(other.center(), other.half_extents() + self.half_extents());
+        let origin: [f32; 3] = self.center().into();
+        let direction: [f32; 3] = displacement.into();
+        let min: [f32; 3] = expanded.min.into();
+        let max: [f32; 3] = expanded.max.into();
+
+        let mut t_entry = std::f32::NEG_INFINITY;
+        let mut t_exit = std::f32::INFINITY;
+        let mut normal = Vec3::zero();
+        for axis in 0..3 {
+            let (o, d, lo, hi) = (origin[axis], direction[axis], min[axis], max[axis]);
+            if d.abs() < std::f32::EPSILON {
+                // Touching faces do not block the movement, so that we can slide on them.
+                if o <= lo || o >= hi {
+                    return None;
+                }
+            } else {
+                let (t1, t2) = ((lo - o) / d, (hi - o) / d);
+                let (t1, t2) = if t1 < t2 { (t1, t2) } else { (t2, t1) };
+                if t1 > t_entry {
+                    t_entry = t1;
+                    let mut n = [0.0; 3];
+                    n[axis] = -d.signum();
+                    normal = n.into();
+                }
+                t_exit = t_exit.min(t2);
+            }
+        }
+
+        if t_entry <= t_exit && t_entry >= 0.0 && t_entry <= 1.0 && t_exit > 0.0 {
+            Some((t_entry, normal))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raycast_hit_and_miss() {
+        let aabb = Aabb::from_center(glam::vec3(0.0, 0.0, 5.0), glam::vec3(1.0, 1.0, 1.0));
+
+        let ray = Ray::new(Vec3::zero(), glam::vec3(0.0, 0.0, 1.0));
+        assert_eq!(Some(4.0), aabb.raycast(&ray));
+
+        let ray = Ray::new(Vec3::zero(), glam::vec3(0.0, 0.0, -1.0));
+        assert_eq!(None, aabb.raycast(&ray));
+
+        let ray = Ray::new(glam::vec3(2.0, 0.0, 0.0), glam::vec3(0.0, 0.0, 1.0));
+        assert_eq!(None, aabb.raycast(&ray));
+
+        // starts inside.
+        let ray = Ray::new(glam::vec3(0.0, 0.0, 5.0), glam::vec3(1.0, 0.0, 0.0));
+        assert_eq!(Some(0.0), aabb.raycast(&ray));
+    }
+
+    #[test]
+    fn intersects() {
+        let a = Aabb::new(Vec3::zero(), glam::vec3(1.0, 1.0, 1.0));
+        let b = Aabb::new(glam::vec3(0.5, 0.5, 0.5), glam::vec3(2.0, 2.0, 2.0));
+        let c = Aabb::new(glam::vec3(1.0, 0.0, 0.0), glam::vec3(2.0, 1.0, 1.0));
+        assert!(a.intersects(&b));
+        assert!(b.intersects(&a));
+        assert!(!a.intersects(&c));
+    }
+}
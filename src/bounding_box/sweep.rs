@@ -0,0 +1,113 @@
+//! Collision resolution for a moving AABB against static AABBs. This is a simple and
+//! deterministic alternative to the nphysics character: move until contact, then slide along
+//! the surface with what is left of the displacement.
+use super::Aabb;
+use glam::Vec3;
+
+/// Number of contacts resolved per call. 3 is enough to get stuck in a corner.
+const MAX_ITERATIONS: usize = 3;
+
+/// Return the displacement to apply to `moving` so that it does not go through any of the
+/// `statics` boxes. On contact, the component of the displacement along the contact normal
+/// is removed so that the box slides along the surface.
+pub fn resolve_movement(moving: &Aabb, displacement: Vec3, statics: &[Aabb]) -> Vec3 {
+    let mut current = *moving;
+    let mut remaining = displacement;
+    let mut total = Vec3::zero();
+
+    for _ in 0..MAX_ITERATIONS {
+        if remaining.length_squared() < std::f32::EPSILON {
+            break;
+        }
+
+        let closest = statics
+            .iter()
+            .filter_map(|s| current.sweep(remaining, s))
+            .fold(
+                None,
+                |closest: Option<(f32, Vec3)>, (toi, normal)| match closest {
+                    Some((best, _)) if best <= toi => closest,
+                    _ => Some((toi, normal)),
+                },
+            );
+
+        match closest {
+            Some((toi, normal)) => {
+                let moved = remaining * toi;
+                total += moved;
+                current = Aabb::new(current.min + moved, current.max + moved);
+
+                let left = remaining * (1.0 - toi);
+                remaining = left - normal * left.dot(normal);
+            }
+            None => {
+                total += remaining;
+                break;
+            }
+        }
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player() -> Aabb {
+        Aabb::from_center(Vec3::zero(), glam::vec3(0.5, 1.0, 0.5))
+    }
+
+    /// Wall in the plane z = 5.
+    fn wall() -> Aabb {
+        Aabb::new(glam::vec3(-10.0, -10.0, 5.0), glam::vec3(10.0, 10.0, 6.0))
+    }
+
+    fn assert_close(expected: Vec3, actual: Vec3) {
+        assert!(
+            (expected - actual).length() < 1e-4,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn no_obstacle() {
+        let displacement = glam::vec3(1.0, 2.0, 3.0);
+        let resolved = resolve_movement(&player(), displacement, &[wall()]);
+        assert_close(displacement, resolved);
+    }
+
+    #[test]
+    fn hit_wall_head_on() {
+        let resolved = resolve_movement(&player(), glam::vec3(0.0, 0.0, 10.0), &[wall()]);
+        // stops when the front face touches the wall.
+        assert_close(glam::vec3(0.0, 0.0, 4.5), resolved);
+
+        // Already in contact, cannot move further.
+        let touching = Aabb::from_center(glam::vec3(0.0, 0.0, 4.5), glam::vec3(0.5, 1.0, 0.5));
+        let resolved = resolve_movement(&touching, glam::vec3(0.0, 0.0, 1.0), &[wall()]);
+        assert_close(Vec3::zero(), resolved);
+    }
+
+    #[test]
+    fn slide_along_wall() {
+        let resolved = resolve_movement(&player(), glam::vec3(3.0, 0.0, 9.0), &[wall()]);
+        // hits the wall after half the movement, then keep the tangential part.
+        assert_close(glam::vec3(3.0, 0.0, 4.5), resolved);
+
+        // Touching the wall and moving along it.
+        let touching = Aabb::from_center(glam::vec3(0.0, 0.0, 4.5), glam::vec3(0.5, 1.0, 0.5));
+        let resolved = resolve_movement(&touching, glam::vec3(2.0, 0.0, 0.0), &[wall()]);
+        assert_close(glam::vec3(2.0, 0.0, 0.0), resolved);
+    }
+
+    #[test]
+    fn stuck_in_corner() {
+        let other_wall = Aabb::new(glam::vec3(3.0, -10.0, -10.0), glam::vec3(4.0, 10.0, 10.0));
+        let resolved =
+            resolve_movement(&player(), glam::vec3(5.0, 0.0, 10.0), &[wall(), other_wall]);
+        assert_close(glam::vec3(2.5, 0.0, 4.5), resolved);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::bounding_box::Ray;
 use crate::ecs::Transform;
 use crate::net::snapshot::Deltable;
 use serde_derive::{Deserialize, Serialize};
@@ -24,6 +25,48 @@ pub fn find_main_camera(world: &hecs::World) -> Option<hecs::Entity> {
     None
 }
 
+/// Ray that starts on the near plane and goes through the given screen position. The screen
+/// position is in pixels with the origin at the top-left corner (like the mouse position). The
+/// direction is normalized.
+pub fn screen_to_ray(
+    projection: &glam::Mat4,
+    view: &glam::Mat4,
+    screen_pos: glam::Vec2,
+    viewport_size: glam::Vec2,
+) -> Ray {
+    let x = 2.0 * screen_pos.x() / viewport_size.x() - 1.0;
+    let y = 1.0 - 2.0 * screen_pos.y() / viewport_size.y();
+    let inverse = (*projection * *view).inverse();
+    let unproject = |z: f32| {
+        let p = inverse * glam::vec4(x, y, z, 1.0);
+        p.truncate() / p.w()
+    };
+
+    let near = unproject(-1.0);
+    let far = unproject(1.0);
+    Ray::new(near, (far - near).normalize())
+}
+
+/// Screen position (in pixels, origin at the top-left corner) of a point in the world. None if
+/// the point is behind the camera.
+pub fn world_to_screen(
+    projection: &glam::Mat4,
+    view: &glam::Mat4,
+    world_pos: glam::Vec3,
+    viewport_size: glam::Vec2,
+) -> Option<glam::Vec2> {
+    let clip = *projection * *view * world_pos.extend(1.0);
+    if clip.w() <= 0.0 {
+        return None;
+    }
+
+    let ndc = clip.truncate() / clip.w();
+    Some(glam::vec2(
+        (ndc.x() + 1.0) * 0.5 * viewport_size.x(),
+        (1.0 - ndc.y()) * 0.5 * viewport_size.y(),
+    ))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Camera {
     pub active: bool,
@@ -109,3 +152,59 @@ impl Camera {
         self.left = world_up.cross(self.front);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: (f32, f32) = (800.0, 600.0);
+
+    fn matrices(eye: glam::Vec3, front: glam::Vec3) -> (glam::Mat4, glam::Mat4) {
+        let projection = glam::Mat4::perspective_rh_gl(0.8, VIEWPORT.0 / VIEWPORT.1, 0.1, 100.0);
+        let view = glam::Mat4::look_at_rh(eye, eye + front, glam::Vec3::unit_y());
+        (projection, view)
+    }
+
+    fn assert_close(expected: glam::Vec3, actual: glam::Vec3) {
+        assert!(
+            (expected - actual).length() < 1e-3,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn screen_center_is_camera_forward() {
+        let viewport = glam::vec2(VIEWPORT.0, VIEWPORT.1);
+        let eye = glam::vec3(1.0, 2.0, 3.0);
+        for front in &[
+            glam::vec3(0.0, 0.0, -1.0),
+            glam::vec3(1.0, 0.0, 0.0),
+            glam::vec3(1.0, -1.0, 1.0).normalize(),
+        ] {
+            let (projection, view) = matrices(eye, *front);
+            let ray = screen_to_ray(&projection, &view, viewport * 0.5, viewport);
+            assert_close(*front, ray.direction);
+            // starts on the near plane.
+            assert_close(eye + *front * 0.1, ray.origin);
+        }
+    }
+
+    #[test]
+    fn world_to_screen_is_inverse() {
+        let viewport = glam::vec2(VIEWPORT.0, VIEWPORT.1);
+        let (projection, view) = matrices(glam::Vec3::zero(), glam::vec3(0.0, 0.0, -1.0));
+
+        let screen_pos = glam::vec2(200.0, 100.0);
+        let ray = screen_to_ray(&projection, &view, screen_pos, viewport);
+        let projected = world_to_screen(&projection, &view, ray.point_at(10.0), viewport).unwrap();
+        assert!((projected - screen_pos).length() < 1e-2);
+
+        // top-left of the screen is up and to the left of the camera.
+        assert!(ray.direction.x() < 0.0 && ray.direction.y() > 0.0);
+
+        let behind = glam::vec3(0.0, 0.0, 5.0);
+        assert!(world_to_screen(&projection, &view, behind, viewport).is_none());
+    }
+}
@@ -12,6 +12,8 @@ pub const RED: RgbColor = RgbColor::new(255, 0, 0);
 pub const BLUE: RgbColor = RgbColor::new(0, 0, 255);
 #[allow(unused)]
 pub const GREEN: RgbColor = RgbColor::new(0, 255, 0);
+#[allow(unused)]
+pub const WHITE: RgbColor = RgbColor::new(255, 255, 255);
 use crate::net::snapshot::Deltable;
 use serde_derive::{Deserialize, Serialize};
 
@@ -0,0 +1,307 @@
+//! Computer-controlled player for offline practice. The bot does not modify the world itself:
+//! it produces the same `ClientCommand`s as the human player so that they go through
+//! `Controller::apply_inputs`.
+//!
+//! The bot walks toward the closest alive player, looks at it and shoots when nothing is in
+//! between.
+
+use crate::camera::Camera;
+use crate::controller::client::ClientCommand;
+use crate::ecs::{Transform, UpdateRate};
+use crate::event::Event;
+use crate::gameplay::gun::Gun;
+use crate::gameplay::player::{spawn_player, Player, PlayerState};
+use crate::net::snapshot::mark_networked;
+use crate::physics::{BodyToEntity, PhysicWorld, RigidBody};
+use crate::resources::Resources;
+use crate::transform::HasChildren;
+use hecs::Entity;
+#[allow(unused_imports)]
+use log::{debug, info};
+use serde_derive::{Deserialize, Serialize};
+
+/// Entities with this component are controlled by the `BotController`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Bot {
+    /// Players further than that are ignored.
+    pub sight_radius: f32,
+    /// The bot stops walking toward its target at this distance.
+    pub stop_distance: f32,
+}
+
+impl Default for Bot {
+    fn default() -> Self {
+        Self {
+            sight_radius: 30.0,
+            stop_distance: 5.0,
+        }
+    }
+}
+
+/// Spawn a player entity controlled by a bot at the given position.
+pub fn spawn_bot(
+    world: &mut hecs::World,
+    physics: &mut PhysicWorld,
+    resources: &Resources,
+    position: glam::Vec3,
+) -> Entity {
+    let e = spawn_player(world, physics, resources);
+
+    // The prefab has the main camera as a child. Only the main player looks through it.
+    if let Ok(children) = world.get::<HasChildren>(e) {
+        for child in &children.children {
+            if let Ok(mut camera) = world.get_mut::<Camera>(*child) {
+                camera.active = false;
+            }
+        }
+    }
+
+    if let Ok(mut t) = world.get_mut::<Transform>(e) {
+        t.translation = position;
+        t.dirty = true;
+    }
+    // the body was created at the prefab position.
+    let mut body_to_entity = resources.fetch_mut::<BodyToEntity>().unwrap();
+    physics.register_entity(world, &mut body_to_entity, e);
+    world.insert_one(e, Bot::default()).unwrap();
+    // bots are part of the saved game state.
+    mark_networked(world, e);
+
+    info!("Spawned bot {:?} at {:?}", e, position);
+    e
+}
+
+#[derive(Default)]
+pub struct BotController {
+    /// Number of calls to `process`. Used for the bots with an `UpdateRate`.
+    frame: u64,
+}
+
+impl BotController {
+    /// Decide what each bot does this frame.
+    pub fn process(
+        &mut self,
+        world: &hecs::World,
+        physics: &PhysicWorld,
+        resources: &Resources,
+    ) -> Vec<(Entity, Event)> {
+        let body_to_entity = resources.fetch::<BodyToEntity>().unwrap();
+        let mut commands = vec![];
+
+        let frame = self.frame;
+        self.frame += 1;
+
+        for (e, (bot, t, rb, player, gun, rate)) in world
+            .query::<(
+                &Bot,
+                &Transform,
+                &RigidBody,
+                &Player,
+                &Gun,
+                Option<&UpdateRate>,
+            )>()
+            .iter()
+        {
+            if player.state != PlayerState::Alive {
+                continue;
+            }
+            if let Some(rate) = rate {
+                if !rate.is_due(e, frame) {
+                    continue;
+                }
+            }
+            let h = match rb.handle {
+                Some(h) => h,
+                None => continue,
+            };
+
+            let target = physics
+                .entities_in_radius(world, &body_to_entity, t.translation, bot.sight_radius)
+                .into_iter()
+                .filter(|(other, _)| *other != e)
+                .find(|(other, _)| {
+                    world
+                        .get::<Player>(*other)
+                        .map(|p| p.state == PlayerState::Alive)
+                        .unwrap_or(false)
+                });
+            let (target, distance) = match target {
+                Some(target) => target,
+                None => continue,
+            };
+            let target_position = world.get::<Transform>(target).unwrap().translation;
+            let to_target = target_position - t.translation;
+
+            let (pitch, yaw) = crate::geom::pitch_yaw_from_direction(to_target);
+            commands.push((e, Event::Client(ClientCommand::LookAt(pitch, yaw))));
+
+            if distance > bot.stop_distance {
+                let horizontal = glam::vec3(to_target.x(), 0.0, to_target.z());
+                if horizontal.length_squared() > std::f32::EPSILON {
+                    commands.push((
+                        e,
+                        Event::Client(ClientCommand::Move(horizontal.normalize())),
+                    ));
+                }
+            }
+
+            // the body of the target does not hide its own center.
+            let mut ignore = vec![h];
+            ignore.extend(world.get::<RigidBody>(target).ok().and_then(|rb| rb.handle));
+            if gun.can_shoot() && physics.has_line_of_sight(t.translation, target_position, &ignore)
+            {
+                debug!("Bot {:?} shoots at {:?}", e, target);
+                commands.push((e, Event::Client(ClientCommand::Shoot)));
+            }
+        }
+
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Name;
+    use crate::event::GameEvent;
+    use crate::paths::Paths;
+    use crate::physics::Shape;
+    use shrev::EventChannel;
+
+    fn test_resources() -> Resources {
+        let mut resources = Resources::new();
+        resources.insert(EventChannel::<GameEvent>::new());
+        resources.insert(BodyToEntity::default());
+        resources.insert(Paths::new("assets/", "config/"));
+        resources
+    }
+
+    fn spawn_body<C: hecs::DynamicBundle>(
+        world: &mut hecs::World,
+        physics: &mut PhysicWorld,
+        resources: &Resources,
+        position: glam::Vec3,
+        half_extents: glam::Vec3,
+        components: C,
+    ) -> Entity {
+        let mut transform = Transform::default();
+        transform.translation = position;
+        let rb = RigidBody {
+            shape: Shape::AABB(half_extents),
+            ..RigidBody::default()
+        };
+        let e = world.spawn((transform, rb));
+        world.insert(e, components).unwrap();
+        physics.register_entity(
+            world,
+            &mut resources.fetch_mut::<BodyToEntity>().unwrap(),
+            e,
+        );
+        e
+    }
+
+    fn spawn_test_bot(
+        world: &mut hecs::World,
+        physics: &mut PhysicWorld,
+        resources: &Resources,
+    ) -> Entity {
+        let mut gun = Gun::default();
+        gun.ammo = 10;
+        spawn_body(
+            world,
+            physics,
+            resources,
+            glam::Vec3::zero(),
+            glam::vec3(0.5, 0.5, 0.5),
+            (Bot::default(), Player::default(), gun),
+        )
+    }
+
+    fn commands_of(commands: &[(Entity, Event)], e: Entity) -> Vec<ClientCommand> {
+        commands
+            .iter()
+            .filter(|(other, _)| *other == e)
+            .filter_map(|(_, ev)| match ev {
+                Event::Client(cmd) => Some(*cmd),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn walk_to_and_shoot_the_closest_player() {
+        let mut resources = test_resources();
+        let mut physics = PhysicWorld::new(&mut resources);
+        let mut world = hecs::World::new();
+        let bot = spawn_test_bot(&mut world, &mut physics, &resources);
+        let half = glam::vec3(0.5, 0.5, 0.5);
+        spawn_body(
+            &mut world,
+            &mut physics,
+            &resources,
+            glam::vec3(0.0, 0.0, 10.0),
+            half,
+            (Player::default(),),
+        );
+        // further than the sight radius.
+        spawn_body(
+            &mut world,
+            &mut physics,
+            &resources,
+            glam::vec3(0.0, 0.0, -50.0),
+            half,
+            (Player::default(),),
+        );
+        // update the broad phase.
+        physics.step();
+
+        let commands = BotController::default().process(&world, &physics, &resources);
+        let commands = commands_of(&commands, bot);
+
+        assert_eq!(3, commands.len());
+        match commands[1] {
+            ClientCommand::Move(dir) => assert!((dir - glam::Vec3::unit_z()).length() < 1e-4),
+            ref cmd => panic!("Expected to move toward the target, got {:?}", cmd),
+        }
+        match commands[2] {
+            ClientCommand::Shoot => (),
+            ref cmd => panic!("Expected to shoot, got {:?}", cmd),
+        }
+    }
+
+    #[test]
+    fn do_not_shoot_through_walls() {
+        let mut resources = test_resources();
+        let mut physics = PhysicWorld::new(&mut resources);
+        let mut world = hecs::World::new();
+        let bot = spawn_test_bot(&mut world, &mut physics, &resources);
+        // within the stop distance so the bot does not move.
+        spawn_body(
+            &mut world,
+            &mut physics,
+            &resources,
+            glam::vec3(0.0, 0.0, 4.0),
+            glam::vec3(0.5, 0.5, 0.5),
+            (Player::default(),),
+        );
+        spawn_body(
+            &mut world,
+            &mut physics,
+            &resources,
+            glam::vec3(0.0, 0.0, 2.0),
+            glam::vec3(1.0, 1.0, 0.1),
+            (Name("wall".to_string()),),
+        );
+        physics.step();
+
+        let commands = BotController::default().process(&world, &physics, &resources);
+        let commands = commands_of(&commands, bot);
+
+        // still looks at the target.
+        assert_eq!(1, commands.len());
+        match commands[0] {
+            ClientCommand::LookAt(..) => (),
+            ref cmd => panic!("Expected to look at the target, got {:?}", cmd),
+        }
+    }
+}
@@ -15,15 +15,30 @@ use shrev::EventChannel;
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum ClientCommand {
     Move(glam::Vec3),
-    CameraMoved,
-    LookAt(f32, f32), // pitch and yaw
+    /// New orientation of the player, as pitch and yaw (same convention as the `Camera`).
+    LookAt(f32, f32),
     Jump,
     Shoot,
+    ThrowGrenade,
     ChangeGun(GunSlot),
     Forward(f32),
     Lateral(f32),
 }
 
+impl ClientCommand {
+    /// Commands that change the velocity of the body. They are applied at every physics step
+    /// so that the movement does not depend on the framerate.
+    pub fn is_movement(&self) -> bool {
+        match self {
+            ClientCommand::Move(_)
+            | ClientCommand::Jump
+            | ClientCommand::Forward(_)
+            | ClientCommand::Lateral(_) => true,
+            _ => false,
+        }
+    }
+}
+
 pub struct ClientController {
     net: bool,
 }
@@ -93,7 +108,10 @@ impl ClientController {
                 if let Some((offset_x, offset_y)) = input.mouse_delta {
                     info!("Apply mouse delta {} {}", offset_x, offset_y);
                     apply_delta_dir(offset_x, offset_y, t, fps.sensitivity, left);
-                    commands.push(ClientCommand::CameraMoved);
+                    let (pitch, yaw) = crate::geom::pitch_yaw_from_direction(
+                        crate::geom::quat_to_direction(t.rotation).0,
+                    );
+                    commands.push(ClientCommand::LookAt(pitch, yaw));
                 }
 
                 if input.has_key_down(Key::Space) {
@@ -117,6 +135,10 @@ impl ClientController {
                     }
                 }
 
+                if input.has_key_event_happened(Key::G, Action::Press) {
+                    commands.push(ClientCommand::ThrowGrenade);
+                }
+
                 if input.has_key_event_happened(Key::Num1, Action::Press) {
                     commands.push(ClientCommand::ChangeGun(0))
                 } else if input.has_key_event_happened(Key::Num2, Action::Press) {
@@ -72,7 +72,7 @@ impl FpsController {
                 let raycast_result = physics.raycast(h, center_position, ray_dir);
                 debug!("First ray = {:?}", raycast_result);
                 for result in raycast_result {
-                    if result.0 <= 1.0 {
+                    if result.toi <= 1.0 {
                         can_move = false;
                         break;
                     }
@@ -82,7 +82,7 @@ impl FpsController {
                 debug!("Second ray = {:?}", raycast_result);
 
                 for result in raycast_result {
-                    if result.0 <= 1.0 {
+                    if result.toi <= 1.0 {
                         can_move = false;
                         break;
                     }
@@ -90,7 +90,7 @@ impl FpsController {
                 let raycast_result = physics.raycast(h, right_position, ray_dir);
                 debug!("Third ray = {:?}", raycast_result);
                 for result in raycast_result {
-                    if result.0 <= 1.0 {
+                    if result.toi <= 1.0 {
                         can_move = false;
                         break;
                     }
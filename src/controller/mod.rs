@@ -2,19 +2,34 @@ use crate::camera::{Camera, LookAt};
 use crate::controller::client::ClientCommand;
 use crate::ecs::Transform;
 use crate::event::{Event, GameEvent};
-use crate::gameplay::gun::{Gun, GunInventory};
-use crate::gameplay::player::{Player, PlayerState};
-use crate::physics::{BodyIndex, BodyToEntity, PhysicWorld, RigidBody};
+use crate::gameplay::gun::{Gun, GunConfig, GunInventory};
+use crate::gameplay::health::apply_damage;
+use crate::gameplay::player::{GameModeConfig, Player, PlayerState, SpawnProtection};
+use crate::gameplay::projectile::{spawn_projectile, Projectile, ProjectileKind};
+use crate::physics::{BodyToEntity, PhysicWorld, RaycastHit, RigidBody};
+use crate::render::tracer::Tracer;
 use crate::resources::Resources;
 use hecs::Entity;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace};
 use serde_derive::{Deserialize, Serialize};
 use shrev::EventChannel;
+pub mod bot;
 pub mod client;
 pub mod fps;
 pub mod free;
 
+/// Length of the tracer when the shot does not hit anything.
+const TRACER_MAX_LENGTH: f32 = 100.0;
+/// How long a tracer stays visible, in seconds.
+const TRACER_LIFETIME: f32 = 0.1;
+/// A player is on the ground when there is something under it closer than that.
+const GROUND_DISTANCE: f32 = 1.5;
+/// Speed of a thrown grenade, in units per second.
+const GRENADE_SPEED: f32 = 10.0;
+/// Grenades are spawned that far in front of the player so that they do not push it.
+const GRENADE_SPAWN_DISTANCE: f32 = 1.0;
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Fps {
     pub speed: f32,
@@ -29,6 +44,10 @@ pub struct Fps {
 
     #[serde(skip)]
     pub moving: bool,
+
+    /// Highest downward speed since the body left the ground. Used for the fall damage.
+    #[serde(skip)]
+    pub fall_speed: f32,
 }
 
 impl Fps {
@@ -77,20 +96,40 @@ fn apply_cmd(
     physics: &mut PhysicWorld,
     resources: &Resources,
 ) {
+    // Moving or shooting gives up the spawn protection.
+    match cmd {
+        ClientCommand::Move(_)
+        | ClientCommand::Forward(_)
+        | ClientCommand::Lateral(_)
+        | ClientCommand::Jump
+        | ClientCommand::Shoot
+        | ClientCommand::ThrowGrenade => {
+            let _ = world.remove_one::<SpawnProtection>(e);
+        }
+        _ => (),
+    }
+
     match cmd {
-        //        ClientCommand::LookAt(pitch, yaw) => {
-        //            let mut camera = world.get_mut::<Camera>(e).unwrap();
-        //            let mut lookat = world.get_mut::<LookAt>(e).unwrap();
-        //            camera.pitch = pitch;
-        //            camera.yaw = yaw;
-        //            camera.compute_vectors();
-        //            lookat.0 = camera.front;
-        //        }
-        //        ClientCommand::CameraMoved => {
-        //            let rb = world.get::<RigidBody>(e).unwrap();
-        //            let t = world.get::<Transform>(e).unwrap();
-        //            physics.set_rotation(rb.handle.unwrap(), *t);
-        //        }
+        ClientCommand::LookAt(pitch, yaw) => {
+            let rotation = crate::geom::quat_from_pitch_yaw(pitch, yaw);
+            if let Ok(mut t) = world.get_mut::<Transform>(e) {
+                t.rotation = rotation;
+                t.dirty = true;
+                // otherwise the rotation is overwritten by the physics at the next step.
+                if let Some(h) = world.get::<RigidBody>(e).ok().and_then(|rb| rb.handle) {
+                    physics.set_rotation(h, *t);
+                }
+            }
+
+            if let Ok(mut camera) = world.get_mut::<Camera>(e) {
+                camera.pitch = pitch;
+                camera.yaw = yaw;
+                camera.compute_vectors();
+            }
+            if let Ok(mut lookat) = world.get_mut::<LookAt>(e) {
+                lookat.0 = crate::geom::quat_to_direction(rotation).0;
+            }
+        }
         ClientCommand::Move(dir) => {
             let rb = world.get::<RigidBody>(e).unwrap();
             let mut fps = world.get_mut::<Fps>(e).unwrap();
@@ -111,26 +150,73 @@ fn apply_cmd(
             }
         }
         ClientCommand::Shoot => {
-            // let camera = world.get::<Camera>(e).unwrap();
-            let rb = world.get::<RigidBody>(e).unwrap();
-            let t = world.get::<Transform>(e).unwrap();
-            let directions = crate::geom::quat_to_direction(t.rotation);
-            if let Ok(mut gun) = world.get_mut::<Gun>(e) {
-                if gun.can_shoot() {
-                    gun.shoot();
-                    let h = rb.handle.unwrap();
-
-                    let mut d = physics.raycast(h, t.translation, directions.0);
-                    trace!("{:?}", d);
-                    d.sort_by(|(toi, _), (toi_o, _)| toi.partial_cmp(toi_o).unwrap());
-                    if let Some(ev) = create_shot_event(d, resources, directions.0) {
-                        let mut event_channel =
-                            resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
-                        event_channel.single_write(ev);
+            // Components are borrowed from the world so the tracer is spawned afterwards.
+            let tracer = {
+                // let camera = world.get::<Camera>(e).unwrap();
+                let rb = world.get::<RigidBody>(e).unwrap();
+                let t = world.get::<Transform>(e).unwrap();
+                let directions = crate::geom::quat_to_direction(t.rotation);
+                if let Ok(mut gun) = world.get_mut::<Gun>(e) {
+                    if gun.can_shoot() {
+                        gun.shoot();
+                        let h = rb.handle.unwrap();
+                        let origin = resources
+                            .fetch::<GunConfig>()
+                            .map(|config| config.muzzle_position(gun.gun_type, &t))
+                            .unwrap_or(t.translation);
+
+                        let mut d = physics.raycast(h, origin, directions.0);
+                        trace!("{:?}", d);
+                        d.sort_by(|a, b| a.toi.partial_cmp(&b.toi).unwrap());
+                        let end = d
+                            .first()
+                            .map(|hit| hit.point)
+                            .unwrap_or(origin + directions.0 * TRACER_MAX_LENGTH);
+                        if let Some(ev) = create_shot_event(d, resources, e, directions.0) {
+                            let mut event_channel =
+                                resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
+                            event_channel.single_write(ev);
+                        }
+
+                        Some(Tracer::new(
+                            origin,
+                            end,
+                            gun.gun_type.get_tracer_color(),
+                            gun.gun_type.get_tracer_width(),
+                            TRACER_LIFETIME,
+                        ))
+                    } else {
+                        None
                     }
+                } else {
+                    error!("Cannot shoot without a gun");
+                    None
                 }
-            } else {
-                error!("Cannot shoot without a gun");
+            };
+
+            if let Some(tracer) = tracer {
+                world.spawn((tracer,));
+            }
+        }
+        ClientCommand::ThrowGrenade => {
+            let (origin, dir) = match world.get::<Transform>(e) {
+                Ok(t) => {
+                    let front = crate::geom::quat_to_direction(t.rotation).0;
+                    (t.translation + front * GRENADE_SPAWN_DISTANCE, front)
+                }
+                Err(_) => return,
+            };
+            let grenade = spawn_projectile(
+                world,
+                physics,
+                resources,
+                origin,
+                dir,
+                GRENADE_SPEED,
+                ProjectileKind::Grenade,
+            );
+            if let Ok(mut p) = world.get_mut::<Projectile>(grenade) {
+                p.owner = Some(e);
             }
         }
         ClientCommand::ChangeGun(gun_slot) => {
@@ -172,23 +258,39 @@ impl Controller {
         }
     }
 
-    /// Check at each frames if the body is on ground.
+    /// Check at each frames if the body is on ground. Bodies that land too fast take fall
+    /// damage.
     pub fn update(
         &self,
         world: &mut hecs::World,
         physics: &mut PhysicWorld,
-        _resources: &Resources,
+        resources: &Resources,
     ) {
-        for (_, (fps, rb, t)) in world.query::<(&mut Fps, &RigidBody, &Transform)>().iter() {
+        let fall_damage = resources
+            .fetch::<GameModeConfig>()
+            .map(|config| config.fall_damage.clone())
+            .unwrap_or_default();
+        let mut landings = vec![];
+
+        for (e, (fps, rb, t)) in world.query::<(&mut Fps, &RigidBody, &Transform)>().iter() {
             let h = rb.handle.unwrap();
             let on_ground = {
-                let mut d = physics.raycast(h, t.translation, -glam::Vec3::unit_y());
-                d.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+                let mut d = physics.raycast_with_max_distance(
+                    h,
+                    t.translation,
+                    -glam::Vec3::unit_y(),
+                    GROUND_DISTANCE,
+                );
+                d.sort_by(|a, b| a.toi.partial_cmp(&b.toi).unwrap());
 
                 trace!("Raycast on_ground = {:?}", d);
-                if let Some((minimum_distance, _)) = d.first() {
+                if let Some(RaycastHit {
+                    toi: minimum_distance,
+                    ..
+                }) = d.first()
+                {
                     trace!("Minimum distance = {}", minimum_distance);
-                    if *minimum_distance < 1.5 {
+                    if *minimum_distance < GROUND_DISTANCE {
                         true
                     } else {
                         false
@@ -200,10 +302,18 @@ impl Controller {
             if on_ground {
                 trace!(" NOW ON GROUND!");
             }
+            if on_ground && !fps.on_ground && fps.fall_speed > 0.0 {
+                landings.push((e, fps.fall_speed));
+            }
+            if on_ground {
+                fps.fall_speed = 0.0;
+            } else if let Some(vel) = physics.get_linear_velocity(h) {
+                fps.fall_speed = fps.fall_speed.max(-vel.y());
+            }
             fps.on_ground = on_ground;
 
             if fps.jumping && on_ground {
-                //physics.set_friction(h, 10.0);
+                //physics.set_linear_damping(h, 10.0);
                 fps.jumping = false;
             }
             if !fps.moving && on_ground {
@@ -215,24 +325,39 @@ impl Controller {
 
             fps.moving = false;
         }
+
+        for (e, speed) in landings {
+            let alive = world
+                .get::<Player>(e)
+                .map(|p| p.state == PlayerState::Alive)
+                .unwrap_or(true);
+            let damage = fall_damage.damage(speed);
+            if alive && damage > 0.0 {
+                debug!("{:?} landed at {} and takes {} damage", e, speed, damage);
+                apply_damage(world, resources, e, damage, None);
+            }
+        }
     }
 }
 
 fn create_shot_event(
-    raycast_result: Vec<(f32, BodyIndex)>,
+    raycast_result: Vec<RaycastHit>,
     resources: &Resources,
+    attacker: Entity,
     direction: glam::Vec3,
 ) -> Option<GameEvent> {
     raycast_result
         .iter()
-        .map(|(_, h)| {
+        .map(|hit| {
             info!("Body to entity");
             let body_to_entity = resources.fetch::<BodyToEntity>().unwrap();
             info!("Get entity");
-            let entity = body_to_entity.get(&h).unwrap();
+            let entity = body_to_entity.get(&hit.body).unwrap();
             GameEvent::EntityShot {
                 entity: *entity,
+                attacker,
                 dir: direction,
+                hit_point: hit.point,
             }
         })
         .next()
@@ -2,6 +2,7 @@ use crate::event::GameEvent;
 use crate::net::snapshot::Deltable;
 use crate::physics::{BodyToEntity, PhysicWorld, RigidBody};
 use crate::resources::Resources;
+use crate::transform::HasChildren;
 use glam::{Quat, Vec3};
 use hecs::Entity;
 use log::error;
@@ -13,12 +14,38 @@ use std::fs;
 use std::sync::mpsc::Receiver;
 use std::time::Duration;
 
+pub mod save;
 pub mod serialization;
 const EPSILON: f32 = 0.00001;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Name(pub String);
 
+/// Update frequency of an entity for the systems that can skip frames (animation, AI...).
+/// Entities without this component are updated every frame.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct UpdateRate {
+    pub every_n_frames: u32,
+}
+
+impl UpdateRate {
+    /// True if the entity is updated at this frame. The entities are staggered by id so that
+    /// the ones with the same rate are not all updated during the same frame.
+    pub fn is_due(&self, e: Entity, frame: u64) -> bool {
+        self.every_n_frames <= 1 || (frame + e.id() as u64) % self.every_n_frames as u64 == 0
+    }
+}
+
+/// Number of frames to simulate for the entity at this frame. 0 if the entity is skipped,
+/// otherwise the number of frames since its last update.
+pub fn frames_to_update(rate: Option<&UpdateRate>, e: Entity, frame: u64) -> u32 {
+    match rate {
+        Some(rate) if !rate.is_due(e, frame) => 0,
+        Some(rate) => rate.every_n_frames.max(1),
+        None => 1,
+    }
+}
+
 /// Simple transform component. Where is the game object.
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Transform {
@@ -102,6 +129,13 @@ pub struct TransformDelta {
     scale: Option<Vec3>,
 }
 
+impl TransformDelta {
+    /// Remove the rotation from the delta, to keep the rotation that is set locally.
+    pub fn clear_rotation(&mut self) {
+        self.rotation = None;
+    }
+}
+
 impl Deltable for Transform {
     type Delta = TransformDelta;
 
@@ -116,9 +150,10 @@ impl Deltable for Transform {
             None
         };
 
-        let delta_rot = if delta_rot.length_squared() > EPSILON {
-            // Some(delta_rot.into())
-            None // FIXME
+        // Unit quaternion, so it is the identity when w is 1.
+        let [_, _, _, w]: [f32; 4] = delta_rot.into();
+        let delta_rot = if 1.0 - w.abs() > EPSILON {
+            Some(delta_rot)
         } else {
             None
         };
@@ -167,6 +202,78 @@ impl Deltable for Transform {
     }
 }
 
+/// Transform at the previous physics step. The renderer displays a transform interpolated
+/// between this one and the current `Transform` so that the movement is smooth even when the
+/// frame rate does not match the physics rate.
+#[derive(Debug, Clone, Copy)]
+pub struct PreviousTransform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+impl PreviousTransform {
+    /// Transform to render. `alpha` is the fraction of physics step accumulated since the
+    /// current state: 0 gives the previous state and 1 the current one.
+    pub fn interpolate(&self, current: &Transform, alpha: f32) -> Transform {
+        let alpha = alpha.max(0.0).min(1.0);
+        Transform {
+            translation: self.translation.lerp(current.translation, alpha),
+            rotation: self.rotation.lerp(current.rotation, alpha),
+            ..*current
+        }
+    }
+}
+
+/// Transform to render for an entity. It is interpolated when the entity has a
+/// `PreviousTransform`.
+pub fn render_transform(
+    current: &Transform,
+    previous: Option<&PreviousTransform>,
+    alpha: f32,
+) -> Transform {
+    previous
+        .map(|previous| previous.interpolate(current, alpha))
+        .unwrap_or(*current)
+}
+
+/// Forget the previous transforms of the entity and of its children. Use that when an entity
+/// jumps to another position (respawn, body registered again...) so that it is not rendered
+/// between the old and the new position for a frame. They get a new one at the next physics
+/// step.
+pub fn reset_previous_transforms(world: &mut hecs::World, e: Entity) {
+    let children = world
+        .get::<HasChildren>(e)
+        .map(|c| c.children.clone())
+        .unwrap_or_default();
+    let _ = world.remove_one::<PreviousTransform>(e);
+    for child in children {
+        reset_previous_transforms(world, child);
+    }
+}
+
+/// Keep the current transforms before stepping the physics. The entities that did not have a
+/// `PreviousTransform` get one.
+pub fn save_previous_transforms(world: &mut hecs::World) {
+    let mut missing = vec![];
+    for (e, (t, previous)) in world
+        .query::<(&Transform, Option<&mut PreviousTransform>)>()
+        .iter()
+    {
+        let saved = PreviousTransform {
+            translation: t.translation,
+            rotation: t.rotation,
+        };
+        match previous {
+            Some(previous) => *previous = saved,
+            None => missing.push((e, saved)),
+        }
+    }
+
+    for (e, previous) in missing {
+        let _ = world.insert_one(e, previous);
+    }
+}
+
 pub struct WorldLoader {
     entities: Vec<Entity>,
     rx: Receiver<Result<notify::Event, notify::Error>>,
@@ -201,6 +308,17 @@ impl WorldLoader {
         )
     }
 
+    /// Entities spawned from the map file. They are deleted when the map is reloaded.
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Use that when the entities of the map are replaced outside of the loader (quick load),
+    /// otherwise the next reload would not delete them.
+    pub fn set_entities(&mut self, entities: Vec<Entity>) {
+        self.entities = entities;
+    }
+
     pub fn update(
         &mut self,
         world: &mut hecs::World,
@@ -254,3 +372,48 @@ impl WorldLoader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_to_update_without_rate() {
+        let mut world = hecs::World::new();
+        let e = world.spawn((Transform::default(),));
+        for frame in 0..10 {
+            assert_eq!(1, frames_to_update(None, e, frame));
+        }
+        let every_frame = UpdateRate { every_n_frames: 0 };
+        for frame in 0..10 {
+            assert_eq!(1, frames_to_update(Some(&every_frame), e, frame));
+        }
+    }
+
+    #[test]
+    fn frames_to_update_accumulates_skipped_frames() {
+        let mut world = hecs::World::new();
+        let entities: Vec<_> = (0..3)
+            .map(|_| world.spawn((Transform::default(),)))
+            .collect();
+        let rate = UpdateRate { every_n_frames: 3 };
+
+        for e in &entities {
+            let frames: Vec<u32> = (0..12)
+                .map(|frame| frames_to_update(Some(&rate), *e, frame))
+                .collect();
+            // one update every 3 frames, which simulates the 3 frames.
+            assert_eq!(4, frames.iter().filter(|f| **f > 0).count());
+            assert_eq!(12, frames.iter().sum::<u32>());
+        }
+
+        // the entities are not all updated during the same frame.
+        for frame in 0..3 {
+            let updated = entities
+                .iter()
+                .filter(|e| frames_to_update(Some(&rate), **e, frame) > 0)
+                .count();
+            assert_eq!(1, updated);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Save the state of the game to a file and restore it later (quick save/quick load). Only the
+//! entities that have the `Networked` component are saved, with their children: they are the
+//! ones that change during a game (players, bots, pickups...). The rest of the world (UI,
+//! free camera...) is left as is when loading.
+//!
+//! The linear velocity of the root bodies is saved as well so that a falling player keeps
+//! falling after loading.
+//!
+//! The saved entities are new entities after loading, so the `Entity` kept outside of the
+//! world are not valid anymore. The main player can be found again with the `MainPlayer`
+//! component. The entities that come from the map are given back so that the `WorldLoader`
+//! can delete them when the map is reloaded.
+use super::serialization::{self, SerializedEntity};
+use crate::gameplay::projectile::Projectile;
+use crate::net::snapshot::Networked;
+use crate::physics::{BodyToEntity, PhysicWorld, RigidBody};
+use crate::resources::Resources;
+use crate::transform::{HasChildren, HasParent};
+use hecs::{Entity, World};
+#[allow(unused_imports)]
+use log::{debug, info};
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SaveError {
+    #[error("Cannot access the save file {0}: {1}")]
+    IoError(String, std::io::Error),
+
+    #[error("Cannot serialize the game state: {0}")]
+    SerializeError(ron::ser::Error),
+
+    #[error("Cannot deserialize the game state: {0}")]
+    DeserializeError(ron::de::Error),
+}
+
+/// A saved root entity with the state of its body that is not in the components.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedEntity {
+    entity: SerializedEntity,
+    #[serde(default)]
+    linear_velocity: Option<glam::Vec3>,
+    /// True if the entity was spawned from the map file.
+    #[serde(default)]
+    map: bool,
+}
+
+/// Entities spawned by `load_state`.
+#[derive(Debug)]
+pub struct LoadedEntities {
+    /// The saved root entities.
+    pub roots: Vec<Entity>,
+    /// The entities of the map after loading: the ones that were not saved and the loaded roots
+    /// that were spawned from the map.
+    pub map_entities: Vec<Entity>,
+}
+
+/// Root entities that are part of the saved state. Projectiles only live for a few seconds
+/// and cannot be serialized so they are not saved.
+fn saved_entities(world: &World) -> Vec<Entity> {
+    let mut entities: Vec<Entity> = world
+        .query::<&Networked>()
+        .iter()
+        .map(|(e, _)| e)
+        .filter(|e| world.get::<HasParent>(*e).is_err() && world.get::<Projectile>(*e).is_err())
+        .collect();
+    entities.sort_by_key(|e| e.to_bits());
+    entities
+}
+
+/// Add the entity and all its descendants to `entities`.
+fn with_children(world: &World, e: Entity, entities: &mut Vec<Entity>) {
+    entities.push(e);
+    if let Ok(children) = world.get::<HasChildren>(e) {
+        for child in &children.children {
+            with_children(world, *child, entities);
+        }
+    }
+}
+
+/// Write the saved entities to `path`. The components are saved with their current value
+/// (current health, ammo...). `map_entities` are the entities spawned from the map file.
+pub fn save_state(
+    world: &World,
+    physics: &PhysicWorld,
+    map_entities: &[Entity],
+    path: &str,
+) -> Result<(), SaveError> {
+    let entities: Vec<SavedEntity> = saved_entities(world)
+        .into_iter()
+        .filter_map(|e| {
+            let linear_velocity = world
+                .get::<RigidBody>(e)
+                .ok()
+                .and_then(|rb| rb.handle)
+                .and_then(|h| physics.get_linear_velocity(h));
+            serialization::serialize_entity(e, world).map(|entity| SavedEntity {
+                entity,
+                linear_velocity,
+                map: map_entities.contains(&e),
+            })
+        })
+        .collect();
+    let content = ron::ser::to_string_pretty(&entities, ron::ser::PrettyConfig::default())
+        .map_err(SaveError::SerializeError)?;
+    fs::write(path, content).map_err(|e| SaveError::IoError(path.to_owned(), e))?;
+
+    info!("Saved {} entities to {}", entities.len(), path);
+    Ok(())
+}
+
+/// Replace the saved entities of the world by the ones in the file at `path`. Their rigid
+/// bodies are added to the physic world. `map_entities` are the entities spawned from the
+/// map file; the ones that are replaced are removed from the returned list and the loaded
+/// ones are added to it.
+///
+/// The world is not modified if the file cannot be read.
+pub fn load_state(
+    world: &mut World,
+    physics: &mut PhysicWorld,
+    resources: &Resources,
+    map_entities: &[Entity],
+    path: &str,
+) -> Result<LoadedEntities, SaveError> {
+    let content = fs::read_to_string(path).map_err(|e| SaveError::IoError(path.to_owned(), e))?;
+    let saved: Vec<SavedEntity> =
+        ron::de::from_str(&content).map_err(SaveError::DeserializeError)?;
+    let mut entities = vec![];
+    let mut states = vec![];
+    for saved in saved {
+        entities.push(saved.entity);
+        states.push((saved.linear_velocity, saved.map));
+    }
+
+    let mut body_to_entity = resources.fetch_mut::<BodyToEntity>().unwrap();
+    let mut to_remove = vec![];
+    for e in saved_entities(world) {
+        with_children(world, e, &mut to_remove);
+    }
+    let mut new_map_entities: Vec<Entity> = map_entities
+        .iter()
+        .filter(|e| !to_remove.contains(e))
+        .copied()
+        .collect();
+    for e in to_remove {
+        physics.unregister_entity(world, &mut body_to_entity, e);
+        if let Err(err) = world.despawn(e) {
+            debug!("Entity {:?} was already deleted = {}", e, err);
+        }
+    }
+
+    let roots = serialization::add_to_world(world, entities);
+    let mut loaded = vec![];
+    for e in &roots {
+        with_children(world, *e, &mut loaded);
+    }
+    for e in loaded {
+        if world.get::<RigidBody>(e).is_ok() {
+            physics.register_entity(world, &mut body_to_entity, e);
+        }
+    }
+    for (e, (velocity, map)) in roots.iter().zip(states) {
+        let h = world.get::<RigidBody>(*e).ok().and_then(|rb| rb.handle);
+        if let (Some(h), Some(velocity)) = (h, velocity) {
+            physics.set_linear_velocity(h, velocity);
+        }
+        if map {
+            new_map_entities.push(*e);
+        }
+    }
+
+    info!("Loaded {} entities from {}", roots.len(), path);
+    Ok(LoadedEntities {
+        roots,
+        map_entities: new_map_entities,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Transform;
+    use crate::event::GameEvent;
+    use crate::gameplay::health::Health;
+    use crate::paths::Paths;
+    use crate::physics::BodyType;
+    use shrev::EventChannel;
+
+    fn test_resources() -> Resources {
+        let mut resources = Resources::new();
+        resources.insert(EventChannel::<GameEvent>::new());
+        resources.insert(BodyToEntity::default());
+        resources.insert(Paths::new("assets/", "config/"));
+        resources
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut resources = test_resources();
+        let mut physics = PhysicWorld::new(&mut resources);
+        let mut world = World::new();
+
+        let health = Health {
+            current: 3.0,
+            max: 10.0,
+        };
+        let saved = world.spawn((
+            Transform::default(),
+            RigidBody {
+                ty: BodyType::Dynamic,
+                max_linear_velocity: 100.0,
+                ..RigidBody::default()
+            },
+            health,
+            Networked,
+        ));
+        let child = world.spawn((Transform::default(), Networked));
+        world
+            .insert_one(child, HasParent { entity: saved })
+            .unwrap();
+        world
+            .insert_one(
+                saved,
+                HasChildren {
+                    children: vec![child],
+                },
+            )
+            .unwrap();
+        let not_saved = world.spawn((health,));
+        physics.register_entity(
+            &world,
+            &mut resources.fetch_mut::<BodyToEntity>().unwrap(),
+            saved,
+        );
+
+        let path = std::env::temp_dir().join("r3dtest_save_and_load.ron");
+        let path = path.to_str().unwrap();
+        let h = world.get::<RigidBody>(saved).unwrap().handle.unwrap();
+        physics.set_linear_velocity(h, glam::vec3(0.0, -5.0, 0.0));
+        save_state(&world, &physics, &[], path).unwrap();
+
+        world.get_mut::<Health>(saved).unwrap().current = 1.0;
+        world.get_mut::<Health>(not_saved).unwrap().current = 1.0;
+        let roots = load_state(&mut world, &mut physics, &resources, &[], path)
+            .unwrap()
+            .roots;
+        let _ = fs::remove_file(path);
+
+        assert_eq!(1, roots.len());
+        let loaded = roots[0];
+        assert!(world.get::<Health>(saved).is_err());
+        assert_eq!(3.0, world.get::<Health>(loaded).unwrap().current);
+        assert_eq!(1, world.get::<HasChildren>(loaded).unwrap().children.len());
+        // the other entities are not touched.
+        assert_eq!(1.0, world.get::<Health>(not_saved).unwrap().current);
+
+        let h = world.get::<RigidBody>(loaded).unwrap().handle.unwrap();
+        let body_to_entity = resources.fetch::<BodyToEntity>().unwrap();
+        assert_eq!(Some(&loaded), body_to_entity.get(&h));
+        assert_eq!(
+            Some(glam::vec3(0.0, -5.0, 0.0)),
+            physics.get_linear_velocity(h)
+        );
+        // the body of the previous entity was removed.
+        assert_eq!(1, physics.stats().bodies);
+    }
+
+    #[test]
+    fn load_replaces_the_map_entities() {
+        let mut resources = test_resources();
+        let mut physics = PhysicWorld::new(&mut resources);
+        let mut world = World::new();
+
+        let map_saved = world.spawn((Transform::default(), Networked));
+        let map_not_saved = world.spawn((Transform::default(),));
+        let player = world.spawn((Transform::default(), Networked));
+
+        let path = std::env::temp_dir().join("r3dtest_load_replaces_the_map_entities.ron");
+        let path = path.to_str().unwrap();
+        let map_entities = vec![map_saved, map_not_saved];
+        save_state(&world, &physics, &map_entities, path).unwrap();
+        let loaded = load_state(&mut world, &mut physics, &resources, &map_entities, path).unwrap();
+        let _ = fs::remove_file(path);
+
+        assert_eq!(2, loaded.roots.len());
+        assert!(world.get::<Transform>(map_saved).is_err());
+        assert!(world.get::<Transform>(player).is_err());
+        // only the loaded entity that comes from the map replaces the previous one.
+        assert_eq!(2, loaded.map_entities.len());
+        assert_eq!(map_not_saved, loaded.map_entities[0]);
+        assert!(loaded.roots.contains(&loaded.map_entities[1]));
+    }
+}
@@ -1,24 +1,32 @@
 #![allow(unused)]
-use super::{Name, Transform};
+use super::{Name, Transform, UpdateRate};
 use crate::animation::AnimationController;
 use crate::camera::{Camera, LookAt};
 use crate::colors::RgbColor;
+use crate::controller::bot::Bot;
 use crate::controller::Fps;
 use crate::gameplay::{
-    gun::Gun, gun::GunInventory, health::Health, pickup::PickUp, player::Player,
+    gun::Gun,
+    gun::GunInventory,
+    health::Health,
+    pickup::PickUp,
+    player::{MainPlayer, Player, Team},
 };
-use crate::physics::RigidBody;
+use crate::net::snapshot::Networked;
+use crate::physics::{gravity_zone::GravityZone, RigidBody};
 use crate::render::{
     billboard::Billboard,
     debug::DebugRender,
     lighting::{AmbientLight, DirectionalLight, Emissive, PointLight},
     particle::ParticleEmitter,
     sprite::{ScreenPosition, SpriteRender},
-    Render,
+    Render, RenderLayer,
 };
 use crate::transform::{HasChildren, HasParent, LocalTransform};
 use hecs::World;
+use serde::Serializer;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -30,6 +38,19 @@ pub enum SerializationError {
     SerializeError(ron::ser::Error),
 }
 
+/// Serialize a map with its keys sorted. The iteration order of a `HashMap` changes from one
+/// run to the other, which would make the saved files different even when nothing changed.
+/// Use it with `#[serde(serialize_with = "crate::ecs::serialization::serialize_sorted")]`.
+pub fn serialize_sorted<S, K, V>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    K: serde::Serialize + Ord,
+    V: serde::Serialize,
+{
+    let sorted: BTreeMap<_, _> = map.iter().collect();
+    serde::Serialize::serialize(&sorted, serializer)
+}
+
 fn get_component<T>(world: &World, e: hecs::Entity) -> Option<T>
 where
     T: Clone + Send + Sync + 'static,
@@ -73,10 +94,13 @@ macro_rules! serialize {
         }
 
         pub fn serialize_entities(world: &hecs::World) -> Vec<SerializedEntity> {
-            let entities: Vec<_> = world.iter()
-                .filter(|(e, _)| {
-                    world.get::<HasChildren>(*e).is_ok()
-                }).filter_map(|(e, _)| {
+            let mut sorted: Vec<_> = world.iter().map(|(e, _)| e).collect();
+            sorted.sort_by_key(|e| e.to_bits());
+            let entities: Vec<_> = sorted.into_iter()
+                // the children are serialized with their parent.
+                .filter(|e| {
+                    world.get::<HasParent>(*e).is_err()
+                }).filter_map(|e| {
                     serialize_entity(e, world)
                 }).collect();
 
@@ -121,7 +145,7 @@ macro_rules! serialize {
 
             if let Ok(children_component) = world.get::<HasChildren>(e) {
                 for c in &children_component.children {
-                    if let Some(serialized_entity) = serialize_entity(e, world) {
+                    if let Some(serialized_entity) = serialize_entity(*c, world) {
                         children.push(serialized_entity);
                     }
                 }
@@ -152,6 +176,7 @@ serialize! {
     (transform, Transform),
     (local_transform, LocalTransform),
     (render, Render),
+    (render_layer, RenderLayer),
     (rigid_body, RigidBody),
     (color, RgbColor),
     (camera, Camera),
@@ -172,5 +197,65 @@ serialize! {
     (directional_light, DirectionalLight),
     (emissive, Emissive),
     (point_light, PointLight),
-    (name, Name)
+    (name, Name),
+    (update_rate, UpdateRate),
+    (networked, Networked),
+    (gravity_zone, GravityZone),
+    (team, Team),
+    (main_player, MainPlayer),
+    (bot, Bot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::Animation;
+
+    fn serialize_animations(names: &[&str]) -> String {
+        let mut animations = HashMap::new();
+        for name in names {
+            animations.insert(name.to_string(), Animation::new(vec![(name.len(), 1)]));
+        }
+        let controller = AnimationController {
+            animations,
+            current_animation: None,
+        };
+        ron::ser::to_string_pretty(&controller, ron::ser::PrettyConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn serialized_maps_are_sorted() {
+        let names = ["walk", "idle", "run", "jump", "shoot", "die"];
+        let mut reversed = names;
+        reversed.reverse();
+
+        let first = serialize_animations(&names);
+        let second = serialize_animations(&reversed);
+        assert_eq!(first, second);
+        assert!(first.find("\"idle\"").unwrap() < first.find("\"walk\"").unwrap());
+    }
+
+    #[test]
+    fn serialize_children_with_their_parent() {
+        let mut world = World::new();
+        let parent = world.spawn((Name("parent".to_string()),));
+        let child = world.spawn((Name("child".to_string()), HasParent { entity: parent }));
+        world
+            .insert_one(
+                parent,
+                HasChildren {
+                    children: vec![child],
+                },
+            )
+            .unwrap();
+        // not a parent, not a child.
+        world.spawn((Name("alone".to_string()),));
+
+        let entities = serialize_entities(&world);
+        assert_eq!(2, entities.len());
+        assert_eq!(1, entities[0].children.len());
+        let child_name = entities[0].children[0].name.as_ref().map(|n| n.0.as_str());
+        assert_eq!(Some("child"), child_name);
+        assert!(entities[1].children.is_empty());
+    }
 }
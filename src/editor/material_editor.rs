@@ -1,11 +1,14 @@
 use crate::assets::material::Material;
 use crate::assets::{AssetManager, Handle};
+use crate::render::mesh::ShaderFlags;
 use crate::resources::Resources;
 use imgui::{im_str, ColorEdit, Selectable};
 
 pub struct MaterialEditor {
     material: Option<Material>,
     selected_material: Option<String>,
+    /// Flags that can be toggled on the selected material, i.e. the ones it has a texture for.
+    available_flags: ShaderFlags,
 }
 
 impl Default for MaterialEditor {
@@ -13,6 +16,7 @@ impl Default for MaterialEditor {
         Self {
             material: None,
             selected_material: None,
+            available_flags: ShaderFlags::empty(),
         }
     }
 }
@@ -50,12 +54,34 @@ impl MaterialEditor {
                 edited = true;
             }
 
+            // Disable a map to see the material without it. A different shader variant is
+            // compiled by the renderer.
+            let flags = [
+                (im_str!("Color texture"), ShaderFlags::HAS_COLOR_TEXTURE),
+                (im_str!("Normal texture"), ShaderFlags::HAS_NORMAL_TEXTURE),
+                (
+                    im_str!("Roughness/metallic map"),
+                    ShaderFlags::HAS_ROUGHNESS_METALLIC_MAP,
+                ),
+            ];
+            for (label, flag) in flags.iter() {
+                if !self.available_flags.contains(*flag) {
+                    continue;
+                }
+                let mut enabled = material.shader_flags.contains(*flag);
+                if ui.checkbox(label, &mut enabled) {
+                    material.shader_flags.set(*flag, enabled);
+                    edited = true;
+                }
+            }
+
             if edited {
                 if let Some(asset) = material_manager.get(&Handle(name.clone())) {
                     asset.execute_mut(|m| {
                         m.emissive_factor = material.emissive_factor;
                         m.base_color = material.base_color;
                         m.metallic_roughness_values = material.metallic_roughness_values;
+                        m.shader_flags = material.shader_flags;
                     })
                 }
             }
@@ -70,6 +96,7 @@ impl MaterialEditor {
                         asset.execute(|m| {
                             self.selected_material = Some(name.clone());
                             self.material = Some(m.clone_strip());
+                            self.available_flags = m.texture_flags();
                         })
                     }
                 }
@@ -1,7 +1,7 @@
 //! Mesh editor is a window that allows changing the materials of a mesh's primitive.
 
 use crate::assets::material::Material;
-use crate::assets::{Asset, AssetManager, Handle};
+use crate::assets::{Asset, AssetManager, AssetStatus, Handle};
 use crate::render::mesh::mesh::Mesh;
 use crate::resources::Resources;
 use imgui::{im_str, Selectable};
@@ -25,7 +25,10 @@ impl MeshEditor {
         let mut mesh_manager = resources.fetch_mut::<AssetManager<Mesh>>().unwrap();
         let material_manager = resources.fetch::<AssetManager<Material>>().unwrap();
         let all_materials: Vec<String> = material_manager.keys().map(|k| k.0.clone()).collect();
-        let assets: Vec<String> = mesh_manager.keys().map(|k| k.0.clone()).collect();
+        let assets: Vec<(String, AssetStatus)> = mesh_manager
+            .iter_status()
+            .map(|(k, status)| (k.0.clone(), status))
+            .collect();
 
         if ui.small_button(im_str!("Select..")) {
             ui.open_popup(im_str!("select"));
@@ -86,8 +89,16 @@ impl MeshEditor {
         }
 
         ui.popup(im_str!("select"), || {
-            for name in assets.iter() {
-                if Selectable::new(&im_str!("{}", name)).build(ui) {
+            for (name, status) in assets.iter() {
+                let label = match status {
+                    AssetStatus::Ready => im_str!("{}", name),
+                    AssetStatus::Loading => im_str!("{} (loading)", name),
+                    AssetStatus::Error => im_str!("{} (error)", name),
+                };
+                if Selectable::new(&label)
+                    .disabled(*status != AssetStatus::Ready)
+                    .build(ui)
+                {
                     let mut materials = vec![];
                     if let Some(asset) = mesh_manager.get(&Handle(name.clone())) {
                         asset.execute(|m| {
@@ -12,7 +12,8 @@ use crate::editor::components::{
 use crate::editor::material_editor::MaterialEditor;
 use crate::editor::mesh_editor::MeshEditor;
 use crate::event::GameEvent;
-use crate::physics::{BodyToEntity, PhysicWorld, RigidBody};
+use crate::gameplay::ui::HudConfig;
+use crate::physics::{BodyToEntity, PhysicWorld, PhysicsStats, RigidBody};
 use crate::render::lighting::{AmbientLight, DirectionalLight};
 use crate::render::Render;
 use crate::resources::Resources;
@@ -41,6 +42,9 @@ pub struct Editor {
 
     // material editor.
     material_editor: MaterialEditor,
+
+    // Crosshair sprite being typed. Initialized from the HUD config.
+    crosshair_sprite: Option<ImString>,
 }
 
 impl Editor {
@@ -56,6 +60,7 @@ impl Editor {
             gltf_to_load: None,
             mesh_editor: MeshEditor::default(),
             material_editor: MaterialEditor::default(),
+            crosshair_sprite: None,
         }
     }
 
@@ -115,7 +120,7 @@ impl Editor {
                 }
                 self.show_load_gltf_popup(ui);
 
-                let parent_nodes: Vec<(hecs::Entity, Vec<hecs::Entity>)> = world
+                let mut parent_nodes: Vec<(hecs::Entity, Vec<hecs::Entity>)> = world
                     .iter()
                     .filter(|(e, _)| {
                         let has_parent = world.get::<HasParent>(*e);
@@ -131,6 +136,7 @@ impl Editor {
                         (e, children)
                     })
                     .collect();
+                parent_nodes.sort_by_key(|(e, _)| e.to_bits());
 
                 for (parent, children) in parent_nodes {
                     self.build_tree(world, parent, children, ui);
@@ -149,20 +155,34 @@ impl Editor {
                     TabItem::new(im_str!("components")).build(ui, || {
                         if let Some(entity) = self.selected_entity {
                             if let Ok(mut t) = world.get_mut::<Transform>(entity) {
+                                if reset_button(ui, "transform", &mut *t)
+                                    && world.get::<RigidBody>(entity).is_ok()
+                                {
+                                    // the physics would move the entity back to its body.
+                                    let mut chan =
+                                        resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
+                                    chan.single_write(GameEvent::RbUpdate(entity));
+                                }
                                 self.transform_editor.edit(ui, &mut t);
                             }
 
                             if let Ok(mut t) = world.get_mut::<LocalTransform>(entity) {
+                                reset_button(ui, "local transform", &mut *t);
                                 LocalTransformEditor::default().edit(ui, &mut t);
                             }
 
                             if let Ok(mut n) = world.get_mut::<Name>(entity) {
+                                reset_button(ui, "name", &mut *n);
                                 self.name_editor.edit(ui, &mut n);
                             }
 
                             if let Ok(mut rb) = world.get_mut::<RigidBody>(entity) {
                                 if let Ok(t) = world.get::<Transform>(entity) {
-                                    if self.rigidbody_editor.edit(ui, &mut rb) {
+                                    // the physics system needs the handle to replace the body.
+                                    let handle = rb.handle;
+                                    let reset = reset_button(ui, "rigid body", &mut *rb);
+                                    rb.handle = handle;
+                                    if self.rigidbody_editor.edit(ui, &mut rb) || reset {
                                         // edited.
                                         let mut chan = resources
                                             .fetch_mut::<EventChannel<GameEvent>>()
@@ -173,14 +193,17 @@ impl Editor {
                             }
 
                             if let Ok(mut ambient) = world.get_mut::<AmbientLight>(entity) {
+                                reset_button(ui, "ambient light", &mut *ambient);
                                 AmbientLightEditor::default().edit(ui, &mut ambient);
                             }
 
                             if let Ok(mut light) = world.get_mut::<DirectionalLight>(entity) {
+                                reset_button(ui, "directional light", &mut *light);
                                 DirectionalLightEditor::default().edit(ui, &mut light);
                             }
 
                             if let Ok(mut render) = world.get_mut::<Render>(entity) {
+                                reset_button(ui, "render", &mut *render);
                                 RenderEditor::default().edit(ui, &mut render, resources);
                             }
                         }
@@ -195,6 +218,76 @@ impl Editor {
             })
     }
 
+    /// Display the counters of the physic world.
+    pub fn show_physics_stats(&self, ui: &imgui::Ui, stats: PhysicsStats) {
+        imgui::Window::new(im_str!("Physics"))
+            .opened(&mut true)
+            .position(
+                [10.0, self.h as f32 - 160.0],
+                imgui::Condition::FirstUseEver,
+            )
+            .size([200.0, 150.0], imgui::Condition::FirstUseEver)
+            .build(ui, || {
+                ui.text(im_str!("Steps: {}", stats.steps));
+                ui.text(im_str!("Substeps: {}", stats.substeps));
+                ui.text(im_str!("Bodies: {}", stats.bodies));
+                ui.text(im_str!("Active bodies: {}", stats.active_bodies));
+                ui.text(im_str!("Colliders: {}", stats.colliders));
+                ui.text(im_str!("Contacts: {}", stats.contacts));
+            });
+    }
+
+    /// Show/hide the HUD elements.
+    pub fn show_hud_config(&mut self, ui: &imgui::Ui, resources: &Resources) {
+        if let Some(mut hud) = resources.fetch_mut::<HudConfig>() {
+            let mut changed = false;
+            let crosshair_sprite = self.crosshair_sprite.get_or_insert_with(|| {
+                let mut sprite = ImString::with_capacity(64);
+                sprite.push_str(&hud.crosshair_style.sprite);
+                sprite
+            });
+            imgui::Window::new(im_str!("HUD"))
+                .opened(&mut true)
+                .position(
+                    [220.0, self.h as f32 - 160.0],
+                    imgui::Condition::FirstUseEver,
+                )
+                .size([300.0, 250.0], imgui::Condition::FirstUseEver)
+                .build(ui, || {
+                    changed |= ui.checkbox(im_str!("Crosshair"), &mut hud.crosshair);
+                    changed |= ui.checkbox(im_str!("Gun"), &mut hud.gun);
+                    changed |= ui.checkbox(im_str!("Ammo"), &mut hud.ammo);
+                    changed |= ui.checkbox(im_str!("Health"), &mut hud.health);
+                    changed |= ui.checkbox(im_str!("Armor"), &mut hud.armor);
+                    ui.input_int(im_str!("Text layer"), &mut hud.text_layer)
+                        .build();
+
+                    ui.separator();
+                    // the sprite is loaded when pressing enter.
+                    if ui
+                        .input_text(im_str!("Crosshair sprite"), crosshair_sprite)
+                        .enter_returns_true(true)
+                        .build()
+                    {
+                        hud.crosshair_style.sprite = crosshair_sprite.to_str().to_string();
+                        changed = true;
+                    }
+                    ui.input_float(im_str!("Crosshair size"), &mut hud.crosshair_style.size)
+                        .build();
+                    let mut color = hud.crosshair_style.color.to_rgba_normalized();
+                    if ColorEdit::new(im_str!("Crosshair color"), &mut color).build(ui) {
+                        hud.crosshair_style.color = color.into();
+                    }
+                });
+
+            if changed {
+                // text is only rendered again on this event.
+                let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
+                chan.single_write(GameEvent::UpdateText);
+            }
+        }
+    }
+
     fn show_load_gltf_popup(&mut self, ui: &imgui::Ui) {
         ui.popup_modal(im_str!("Import?"))
             .always_auto_resize(true)
@@ -218,3 +311,14 @@ impl Editor {
             });
     }
 }
+
+/// Small button to replace a component by its default value. Return true if the component
+/// was reset.
+fn reset_button<T: Default>(ui: &imgui::Ui, name: &str, component: &mut T) -> bool {
+    if ui.small_button(&im_str!("Reset {}", name)) {
+        *component = T::default();
+        true
+    } else {
+        false
+    }
+}
@@ -15,7 +15,11 @@ pub enum GameEvent {
 
     EntityShot {
         entity: Entity,
+        /// Entity that fired the shot.
+        attacker: Entity,
         dir: glam::Vec3, // from where the shot came
+        /// Where the shot touched the entity.
+        hit_point: glam::Vec3,
     },
     Delete(Entity),
 
@@ -31,6 +35,8 @@ pub enum GameEvent {
     /// One of the player is dead. Change its state to spawning ;)
     PlayerDead {
         entity: Entity,
+        /// Entity that killed the player, if any.
+        attacker: Option<Entity>,
     },
 
     /// The main player changed its gun. need to update UI and so on.
@@ -52,4 +58,12 @@ pub enum GameEvent {
     },
 
     RbUpdate(Entity),
+
+    /// Two bodies started touching. Normal is from `a` to `b`.
+    Collision {
+        a: Entity,
+        b: Entity,
+        normal: glam::Vec3,
+        depth: f32,
+    },
 }
@@ -6,9 +6,12 @@
 //!
 //! When the player switches gun, the current gun's ammo will be saved in the inventory.
 
+use crate::colors::RgbColor;
+use crate::ecs::Transform;
 use crate::event::GameEvent;
 use crate::gameplay::player::MainPlayer;
 use crate::net::snapshot::Deltable;
+use crate::paths::Paths;
 use crate::resources::Resources;
 use hecs::World;
 use log::info;
@@ -27,7 +30,10 @@ pub struct Gun {
     /// Current amount of ammo in the gun
     pub ammo: i32,
 
-    /// When 0, the player can shoot
+    /// When <= 0, the player can shoot. It can go a bit below 0 (less than a frame), in which
+    /// case the extra time is deducted from the next wait so that the fire rate does not depend
+    /// on the framerate. It is never below 0 after a shot, so a long frame cannot be turned into
+    /// a burst.
     countdown: f32,
 }
 
@@ -40,7 +46,15 @@ impl Gun {
     /// Decrease the amount of ammo and reset countdown
     pub fn shoot(&mut self) {
         self.ammo = 0i32.max(self.ammo - 1);
-        self.countdown = self.gun_type.get_time_to_wait();
+        self.countdown = (self.countdown + self.gun_type.get_time_to_wait()).max(0.0);
+    }
+
+    /// Advance the countdown by the elapsed time (in seconds).
+    pub fn update_countdown(&mut self, elapsed: f32) {
+        // Only count down while waiting, otherwise an idle gun would accumulate shots.
+        if self.countdown > 0.0 {
+            self.countdown -= elapsed;
+        }
     }
 }
 
@@ -97,7 +111,7 @@ impl Deltable for Gun {
         def
     }
 }
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub enum GunType {
     Shotgun,
     Pistol,
@@ -126,6 +140,22 @@ impl GunType {
         }
     }
 
+    /// Color of the tracer drawn when shooting.
+    pub fn get_tracer_color(self) -> RgbColor {
+        match self {
+            GunType::Pistol => RgbColor::new(255, 236, 153),
+            GunType::Shotgun => RgbColor::new(255, 180, 90),
+        }
+    }
+
+    /// Width of the tracer, in world units.
+    pub fn get_tracer_width(self) -> f32 {
+        match self {
+            GunType::Pistol => 0.02,
+            GunType::Shotgun => 0.05,
+        }
+    }
+
     pub fn get_ammo_pickup(self) -> i32 {
         match self {
             GunType::Pistol => 10,
@@ -140,17 +170,46 @@ impl GunType {
         }
     }
 
-    pub fn get_prefab_path(self) -> String {
+    pub fn get_prefab_path(self, paths: &Paths) -> String {
         let filename = match self {
             GunType::Pistol => "pistol",
             GunType::Shotgun => "shotgun",
         };
 
-        format!(
-            "{}prefab/{}.ron",
-            std::env::var("ASSET_PATH").unwrap(),
-            filename
-        )
+        paths.asset(&format!("prefab/{}.ron", filename))
+    }
+}
+
+/// Position of the barrel relative to the player, along its forward, right and up vectors.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+pub struct MuzzleOffset {
+    #[serde(default)]
+    pub forward: f32,
+    #[serde(default)]
+    pub right: f32,
+    #[serde(default)]
+    pub up: f32,
+}
+
+/// Configuration of the guns, read from `guns.ron`. A gun without a muzzle offset shoots from
+/// the center of the player.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct GunConfig {
+    #[serde(default)]
+    pub muzzle_offsets: HashMap<GunType, MuzzleOffset>,
+}
+
+impl GunConfig {
+    /// World position of the barrel of the gun for a player at the given transform. This is
+    /// where the shots start.
+    pub fn muzzle_position(&self, gun_type: GunType, transform: &Transform) -> glam::Vec3 {
+        let offset = self
+            .muzzle_offsets
+            .get(&gun_type)
+            .copied()
+            .unwrap_or_default();
+        let (forward, up, left) = crate::geom::quat_to_direction(transform.rotation);
+        transform.translation + forward * offset.forward - left * offset.right + up * offset.up
     }
 }
 
@@ -158,6 +217,7 @@ pub type GunSlot = usize;
 /// Contains the weapons of a player.
 #[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
 pub struct GunInventory {
+    #[serde(serialize_with = "crate::ecs::serialization::serialize_sorted")]
     guns: HashMap<GunSlot, Gun>,
 }
 
@@ -230,10 +290,12 @@ impl GunSystem {
             rdr_id: chan.register_reader(),
         }
     }
-    pub fn update(&mut self, world: &mut World, dt: Duration, resources: &mut Resources) {
-        let as_secs = dt.as_secs_f32();
+    /// `elapsed` should be the real time since the last update, not a fixed timestep, so that
+    /// the fire rate is the same whatever the framerate.
+    pub fn update(&mut self, world: &mut World, elapsed: Duration, resources: &mut Resources) {
+        let as_secs = elapsed.as_secs_f32();
         for (_, g) in world.query::<&mut Gun>().iter() {
-            g.countdown = 0.0f32.max(g.countdown - as_secs);
+            g.update_countdown(as_secs);
         }
 
         // If there is any pick up event :)
@@ -286,3 +348,111 @@ impl GunSystem {
         chan.drain_vec_write(&mut to_send);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn muzzle_position_follows_player() {
+        let mut config = GunConfig::default();
+        config.muzzle_offsets.insert(
+            GunType::Pistol,
+            MuzzleOffset {
+                forward: 1.0,
+                right: 0.5,
+                up: 0.2,
+            },
+        );
+        let t = Transform::new(
+            glam::vec3(1.0, 2.0, 3.0),
+            glam::Quat::identity(),
+            glam::Vec3::one(),
+        );
+
+        let pistol = config.muzzle_position(GunType::Pistol, &t);
+        assert!((pistol - glam::vec3(0.5, 2.2, 4.0)).length() < 1e-5);
+
+        // no offset, same as before.
+        assert_eq!(t.translation, config.muzzle_position(GunType::Shotgun, &t));
+    }
+
+    /// Keep the trigger pressed during `duration` seconds at the given framerate.
+    fn shots_fired(gun_type: GunType, fps: u32, duration: f32) -> usize {
+        let mut gun = Gun {
+            gun_type,
+            ammo: 10000,
+            countdown: 0.0,
+        };
+        let dt = 1.0 / fps as f32;
+        let mut shots = 0;
+        for _ in 0..(duration * fps as f32).round() as usize {
+            if gun.can_shoot() {
+                gun.shoot();
+                shots += 1;
+            }
+            gun.update_countdown(dt);
+        }
+        shots
+    }
+
+    #[test]
+    fn fire_rate_independent_of_framerate() {
+        for gun_type in &[GunType::Pistol, GunType::Shotgun] {
+            let duration = 30.0;
+            let expected = (duration / gun_type.get_time_to_wait()) as i64;
+            for fps in &[30, 60, 144, 165] {
+                let shots = shots_fired(*gun_type, *fps, duration) as i64;
+                assert!(
+                    (shots - expected).abs() <= 1,
+                    "{:?} at {} fps: {} shots, expected {}",
+                    gun_type,
+                    fps,
+                    shots,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn idle_gun_does_not_accumulate_shots() {
+        let mut gun = Gun {
+            gun_type: GunType::Pistol,
+            ammo: 10,
+            countdown: 0.0,
+        };
+        gun.shoot();
+        for _ in 0..100 {
+            gun.update_countdown(0.1);
+        }
+        assert!(gun.can_shoot());
+        gun.shoot();
+        assert!(!gun.can_shoot());
+    }
+
+    #[test]
+    fn long_frame_does_not_allow_a_burst() {
+        let mut gun = Gun {
+            gun_type: GunType::Pistol,
+            ammo: 100,
+            countdown: 0.0,
+        };
+        gun.shoot();
+        gun.update_countdown(0.1);
+        // hiccup of several seconds.
+        gun.update_countdown(5.0);
+
+        let mut shots = 0;
+        for _ in 0..6 {
+            if gun.can_shoot() {
+                gun.shoot();
+                shots += 1;
+            }
+            gun.update_countdown(1.0 / 60.0);
+        }
+        // the extra time is dropped, at most one shot is caught up.
+        assert!(shots <= 2, "{} shots", shots);
+        assert!(gun.countdown > 0.0);
+    }
+}
@@ -1,11 +1,11 @@
 use crate::colors;
-use crate::ecs::Transform;
 use crate::event::GameEvent;
-use crate::gameplay::player::Player;
+use crate::gameplay::player::{GameModeConfig, Player, SpawnProtection, Team};
 use crate::net::snapshot::Deltable;
 use crate::render::particle::ParticleEmitter;
 use crate::resources::Resources;
-use log::info;
+use hecs::Entity;
+use log::{debug, info};
 use serde_derive::{Deserialize, Serialize};
 use shrev::{EventChannel, ReaderId};
 
@@ -43,6 +43,77 @@ impl Deltable for Health {
     }
 }
 
+/// Health removed by a shot.
+const SHOT_DAMAGE: f32 = 1.0;
+
+/// Remove `amount` health from the entity and send a `HealthUpdate`. When the health reaches 0,
+/// a `PlayerDead` is sent for the players and a `Delete` for the other entities. `source` is
+/// the entity responsible for the damage, if any.
+///
+/// Return false if the entity was not damaged, because it has no health, is protected or is in
+/// the same team as `source` without friendly fire.
+pub fn apply_damage(
+    world: &hecs::World,
+    resources: &Resources,
+    entity: Entity,
+    amount: f32,
+    source: Option<Entity>,
+) -> bool {
+    if world.get::<SpawnProtection>(entity).is_ok() {
+        info!("Entity was damaged but is protected");
+        return false;
+    }
+
+    if let Some(source) = source {
+        if source != entity && !friendly_fire(resources) && same_team(world, entity, source) {
+            debug!("Entity {:?} was damaged by a teammate", entity);
+            return false;
+        }
+    }
+
+    let mut health = match world.get_mut::<Health>(entity) {
+        Ok(health) => health,
+        Err(_) => return false,
+    };
+    health.current -= amount;
+    info!(
+        "Entity {:?} took {} damage. current health = {:?}",
+        entity, amount, health.current
+    );
+
+    let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
+    chan.single_write(GameEvent::HealthUpdate {
+        entity,
+        new_health: health.current,
+    });
+    if health.current <= 0.0 {
+        if world.get::<Player>(entity).is_ok() {
+            chan.single_write(GameEvent::PlayerDead {
+                entity,
+                attacker: source,
+            });
+        } else {
+            chan.single_write(GameEvent::Delete(entity));
+        }
+    }
+
+    true
+}
+
+fn friendly_fire(resources: &Resources) -> bool {
+    resources
+        .fetch::<GameModeConfig>()
+        .map(|config| config.friendly_fire)
+        .unwrap_or(true)
+}
+
+fn same_team(world: &hecs::World, a: Entity, b: Entity) -> bool {
+    match (world.get::<Team>(a), world.get::<Team>(b)) {
+        (Ok(team_a), Ok(team_b)) => *team_a == *team_b,
+        _ => false,
+    }
+}
+
 pub struct HealthSystem {
     rdr_id: ReaderId<GameEvent>,
 }
@@ -55,64 +126,143 @@ impl HealthSystem {
     }
 
     pub fn update(&mut self, world: &mut hecs::World, resources: &Resources) {
-        let mut entities_to_delete = vec![];
-        let mut entities_to_spawn = vec![];
+        let mut shots = vec![];
         let mut health_updates = vec![];
-        let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
+        {
+            let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
 
-        for ev in chan.read(&mut self.rdr_id) {
-            match ev {
-                GameEvent::EntityShot { entity, dir } => {
-                    if let Ok(mut health) = world.get_mut::<Health>(*entity) {
-                        health.current -= 1.0;
-                        info!("Entity was shot. current health = {:?}", health.current);
-
-                        health_updates.push(GameEvent::HealthUpdate {
-                            entity: *entity,
-                            new_health: health.current,
-                        });
-
-                        // SHOW SOME BLOOD.
-                        let position = world.get::<Transform>(*entity).unwrap().translation;
-                        entities_to_spawn.push(ParticleEmitter::new(
-                            position,
-                            *dir * 5.0,
-                            100,
-                            colors::RED,
-                            Some(0.5),
-                        ));
-
-                        if health.current <= 0.0 {
-                            if world.get::<Player>(*entity).is_ok() {
-                                entities_to_delete.push(GameEvent::PlayerDead { entity: *entity });
-                            } else {
-                                entities_to_delete.push(GameEvent::Delete(*entity));
-                            }
+            for ev in chan.read(&mut self.rdr_id) {
+                match ev {
+                    GameEvent::EntityShot {
+                        entity,
+                        attacker,
+                        dir,
+                        hit_point,
+                    } => shots.push((*entity, *attacker, *dir, *hit_point)),
+                    GameEvent::PickupHealth { entity, health: h } => {
+                        info!(
+                            "Got pickup health event. for entity {:?} and ammo {}",
+                            entity.to_bits(),
+                            h
+                        );
+                        if let Ok(mut health) = world.get_mut::<Health>(*entity) {
+                            health.current += *h as f32;
+                            health_updates.push(GameEvent::HealthUpdate {
+                                entity: *entity,
+                                new_health: health.current,
+                            });
                         }
                     }
+                    _ => (),
                 }
-                GameEvent::PickupHealth { entity, health: h } => {
-                    info!(
-                        "Got pickup health event. for entity {:?} and ammo {}",
-                        entity.to_bits(),
-                        h
-                    );
-                    if let Ok(mut health) = world.get_mut::<Health>(*entity) {
-                        health.current += *h as f32;
-                        health_updates.push(GameEvent::HealthUpdate {
-                            entity: *entity,
-                            new_health: health.current,
-                        });
-                    }
-                }
-                _ => (),
             }
+
+            chan.drain_vec_write(&mut health_updates);
         }
 
-        chan.drain_vec_write(&mut entities_to_delete);
-        chan.drain_vec_write(&mut health_updates);
-        for e in entities_to_spawn {
-            world.spawn((e,));
+        for (entity, attacker, dir, hit_point) in shots {
+            if apply_damage(world, resources, entity, SHOT_DAMAGE, Some(attacker)) {
+                // SHOW SOME BLOOD where the bullet hit.
+                world.spawn((ParticleEmitter::new(
+                    hit_point,
+                    dir * 5.0,
+                    100,
+                    colors::RED,
+                    Some(0.5),
+                ),));
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_resources() -> (Resources, ReaderId<GameEvent>) {
+        let mut resources = Resources::new();
+        let mut chan = EventChannel::<GameEvent>::new();
+        let rdr_id = chan.register_reader();
+        resources.insert(chan);
+        (resources, rdr_id)
+    }
+
+    #[test]
+    fn apply_damage_until_death() {
+        let (resources, mut rdr_id) = test_resources();
+        let mut world = hecs::World::new();
+        let e = world.spawn((Health {
+            current: 2.0,
+            max: 2.0,
+        },));
+
+        assert!(apply_damage(&world, &resources, e, 1.5, None));
+        assert_eq!(0.5, world.get::<Health>(e).unwrap().current);
+        {
+            let chan = resources.fetch::<EventChannel<GameEvent>>().unwrap();
+            let events: Vec<_> = chan.read(&mut rdr_id).collect();
+            assert_eq!(1, events.len());
+            match events[0] {
+                GameEvent::HealthUpdate { new_health, .. } => assert_eq!(0.5, *new_health),
+                ev => panic!("Expected a health update, got {:?}", ev),
+            }
+        }
+
+        assert!(apply_damage(&world, &resources, e, 1.0, None));
+        let chan = resources.fetch::<EventChannel<GameEvent>>().unwrap();
+        let events: Vec<_> = chan.read(&mut rdr_id).collect();
+        assert_eq!(2, events.len());
+        match events[1] {
+            GameEvent::Delete(deleted) => assert_eq!(e, *deleted),
+            ev => panic!("Expected the entity to be deleted, got {:?}", ev),
+        }
+    }
+
+    #[test]
+    fn apply_damage_to_protected_entity() {
+        let (resources, mut rdr_id) = test_resources();
+        let mut world = hecs::World::new();
+        let e = world.spawn((
+            Health {
+                current: 2.0,
+                max: 2.0,
+            },
+            SpawnProtection(1.0),
+        ));
+        let no_health = world.spawn((SpawnProtection(1.0),));
+
+        assert!(!apply_damage(&world, &resources, e, 1.0, None));
+        assert!(!apply_damage(&world, &resources, no_health, 1.0, None));
+        assert_eq!(2.0, world.get::<Health>(e).unwrap().current);
+        let chan = resources.fetch::<EventChannel<GameEvent>>().unwrap();
+        assert_eq!(0, chan.read(&mut rdr_id).count());
+    }
+
+    #[test]
+    fn apply_damage_between_teammates() {
+        let (mut resources, _) = test_resources();
+        let mut world = hecs::World::new();
+        let health = Health {
+            current: 10.0,
+            max: 10.0,
+        };
+        let e = world.spawn((health, Team(1)));
+        let teammate = world.spawn((health, Team(1)));
+        let enemy = world.spawn((health, Team(2)));
+        let no_team = world.spawn((health,));
+
+        // friendly fire is enabled without a config.
+        assert!(apply_damage(&world, &resources, e, 1.0, Some(teammate)));
+
+        resources.insert(GameModeConfig {
+            friendly_fire: false,
+            ..GameModeConfig::default()
+        });
+        assert!(!apply_damage(&world, &resources, e, 1.0, Some(teammate)));
+        assert!(apply_damage(&world, &resources, e, 1.0, Some(enemy)));
+        assert!(apply_damage(&world, &resources, e, 1.0, Some(no_team)));
+        // self damage (rocket jump...) is not friendly fire.
+        assert!(apply_damage(&world, &resources, e, 1.0, Some(e)));
+        assert_eq!(6.0, world.get::<Health>(e).unwrap().current);
+    }
+}
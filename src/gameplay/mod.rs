@@ -3,4 +3,5 @@ pub mod gun;
 pub mod health;
 pub mod pickup;
 pub mod player;
+pub mod projectile;
 pub mod ui;
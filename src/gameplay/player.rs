@@ -7,9 +7,9 @@
 //! replaced by some configuration file at some point...)
 use crate::camera::{Camera, LookAt};
 use crate::ecs::serialization::SerializedEntity;
-use crate::ecs::Transform;
+use crate::ecs::{reset_previous_transforms, Transform};
 use crate::ecs::{serialization, Name};
-use crate::physics::{BodyToEntity, PhysicWorld, RigidBody};
+use crate::physics::{BodyToEntity, PhysicWorld};
 use crate::resources::Resources;
 use hecs::{Entity, World};
 #[allow(unused_imports)]
@@ -21,6 +21,7 @@ use crate::event::GameEvent;
 use crate::gameplay::gun::GunInventory;
 use crate::gameplay::health::Health;
 use crate::net::snapshot::Deltable;
+use crate::paths::Paths;
 use crate::render::billboard::Billboard;
 use crate::render::Render;
 use crate::transform::{HasChildren, HasParent, LocalTransform};
@@ -66,8 +67,74 @@ impl Default for Player {
     }
 }
 /// YOU!
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct MainPlayer;
 
+/// Entities of the same team cannot damage each other when the friendly fire is disabled in
+/// the `GameModeConfig`. Entities without a team can be damaged by everybody.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Team(pub u8);
+
+/// Rules of the current game mode. Read from `gamemode.ron`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameModeConfig {
+    /// Seconds before a dead player respawns.
+    pub respawn_delay: f32,
+    /// Seconds during which a player that just respawned cannot be damaged. The protection
+    /// stops as soon as the player moves or shoots.
+    pub spawn_protection: f32,
+    #[serde(default)]
+    pub fall_damage: FallDamageConfig,
+    /// When false, the entities of the same `Team` do not damage each other.
+    #[serde(default = "default_friendly_fire")]
+    pub friendly_fire: bool,
+}
+
+fn default_friendly_fire() -> bool {
+    true
+}
+
+impl Default for GameModeConfig {
+    fn default() -> Self {
+        Self {
+            respawn_delay: 5.0,
+            spawn_protection: 2.0,
+            fall_damage: FallDamageConfig::default(),
+            friendly_fire: default_friendly_fire(),
+        }
+    }
+}
+
+/// Damage taken by the players when they land too fast.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FallDamageConfig {
+    /// Landings slower than that, in units per second, are harmless.
+    pub min_speed: f32,
+    /// Damage per unit of speed above `min_speed`.
+    pub damage_per_speed: f32,
+}
+
+impl Default for FallDamageConfig {
+    fn default() -> Self {
+        Self {
+            min_speed: 12.0,
+            damage_per_speed: 1.0,
+        }
+    }
+}
+
+impl FallDamageConfig {
+    /// Damage for a landing at the given downward speed.
+    pub fn damage(&self, speed: f32) -> f32 {
+        ((speed - self.min_speed) * self.damage_per_speed).max(0.0)
+    }
+}
+
+/// The player cannot be damaged while it has this component. Contains the remaining time in
+/// seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct SpawnProtection(pub f32);
+
 impl Deltable for Player {
     type Delta = Player;
 
@@ -100,21 +167,14 @@ pub fn spawn_player(
 ) -> Entity {
     let mut body_to_entity = resources.fetch_mut::<BodyToEntity>().unwrap();
 
-    let player_prefab = std::env::var("ASSET_PATH").unwrap() + "prefab/player.ron";
-
-    let player_prefab = fs::read_to_string(player_prefab).unwrap();
-    let ser_entity: SerializedEntity = ron::de::from_str(&player_prefab).unwrap();
+    let ser_entity = load_player_prefab(&resources.fetch::<Paths>().unwrap());
     let e = crate::ecs::serialization::spawn_entity(world, &ser_entity);
 
     //    let lookat = {
     //        let cam = world.get::<Camera>(e).unwrap();
     //        LookAt(cam.front)
     //    };
-    let idx = {
-        let mut rb = world.get_mut::<RigidBody>(e).unwrap();
-        let transform = world.get::<Transform>(e).unwrap();
-        physics.add_body(&*transform, &mut rb)
-    };
+    physics.register_entity(world, &mut body_to_entity, e);
     let current_gun = {
         let inventory = world
             .get::<GunInventory>(e)
@@ -163,21 +223,19 @@ pub fn spawn_player(
     //        player_health,
     //    ));
 
-    body_to_entity.insert(idx, e);
-
     world.insert_one(e, current_gun).unwrap();
 
     e
 }
 
+fn load_player_prefab(paths: &Paths) -> SerializedEntity {
+    let player_prefab = fs::read_to_string(paths.asset("prefab/player.ron")).unwrap();
+    ron::de::from_str(&player_prefab).unwrap()
+}
+
 /// Spawn the entities that has the sprites (crosshair, gun...)
-pub fn spawn_player_ui(world: &mut World) {
-    let ui_str = fs::read_to_string(&format!(
-        "{}{}",
-        std::env::var("CONFIG_PATH").unwrap(),
-        "ui.ron"
-    ))
-    .unwrap();
+pub fn spawn_player_ui(world: &mut World, paths: &Paths) {
+    let ui_str = fs::read_to_string(paths.config("ui.ron")).unwrap();
     let ui_entities: Vec<serialization::SerializedEntity> = ron::de::from_str(&ui_str).unwrap();
     serialization::add_to_world(world, ui_entities);
 }
@@ -185,22 +243,41 @@ pub fn spawn_player_ui(world: &mut World) {
 /// Monitor/Change state of players.
 pub struct PlayerSystem {
     rdr_id: ReaderId<GameEvent>,
+
+    /// Where the players respawn. Their body is registered again at that position. Taken from
+    /// the player prefab.
+    spawn_position: glam::Vec3,
 }
 
 impl PlayerSystem {
     pub fn new(resources: &mut Resources) -> Self {
         let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
+        let spawn_position = load_player_prefab(&resources.fetch::<Paths>().unwrap())
+            .transform
+            .map(|t| t.translation)
+            .unwrap_or_default();
         Self {
             rdr_id: chan.register_reader(),
+            spawn_position,
         }
     }
 
     /// dt in seconds
-    pub fn update(&mut self, dt: Duration, world: &mut World, resources: &Resources) {
+    pub fn update(
+        &mut self,
+        dt: Duration,
+        world: &mut World,
+        physics: &mut PhysicWorld,
+        resources: &Resources,
+    ) {
         let chan = resources.fetch::<EventChannel<GameEvent>>().unwrap();
+        let config = resources
+            .fetch::<GameModeConfig>()
+            .map(|c| (*c).clone())
+            .unwrap_or_default();
 
         for ev in chan.read(&mut self.rdr_id) {
-            if let GameEvent::PlayerDead { entity } = ev {
+            if let GameEvent::PlayerDead { entity, .. } = ev {
                 let mut p = world
                     .get_mut::<Player>(*entity)
                     .expect("Player entity should have a player component");
@@ -211,7 +288,7 @@ impl PlayerSystem {
                     "Player system will change the player to Spawning: {:?} / {:?}",
                     *p, *r
                 );
-                p.state = PlayerState::Respawn(5.0); // 5 seconds to respawn.
+                p.state = PlayerState::Respawn(config.respawn_delay);
                 r.enabled = false;
             }
         }
@@ -230,11 +307,46 @@ impl PlayerSystem {
             }
         }
 
-        self.respawn_players(world, player_to_respawn);
+        // spawn protection expires after a while.
+        let mut protection_expired = vec![];
+        for (e, protection) in world.query::<&mut SpawnProtection>().iter() {
+            protection.0 -= dt.as_secs_f32();
+            if protection.0 <= 0.0 {
+                protection_expired.push(e);
+            }
+        }
+        for e in protection_expired {
+            let _ = world.remove_one::<SpawnProtection>(e);
+        }
+
+        let mut body_to_entity = resources.fetch_mut::<BodyToEntity>().unwrap();
+        self.respawn_players(world, physics, &mut body_to_entity, &player_to_respawn);
+        if config.spawn_protection > 0.0 {
+            for e in player_to_respawn {
+                world
+                    .insert_one(e, SpawnProtection(config.spawn_protection))
+                    .unwrap();
+            }
+        }
     }
 
-    fn respawn_players(&self, world: &mut World, players: Vec<Entity>) {
-        for player in players {
+    fn respawn_players(
+        &self,
+        world: &mut World,
+        physics: &mut PhysicWorld,
+        body_to_entity: &mut BodyToEntity,
+        players: &[Entity],
+    ) {
+        for &player in players {
+            if let Ok(mut t) = world.get_mut::<Transform>(player) {
+                t.translation = self.spawn_position;
+                t.dirty = true;
+            }
+            // fresh body at the spawn position, without the velocity of the previous life.
+            physics.register_entity(world, body_to_entity, player);
+            // do not render the player between where it died and the spawn.
+            reset_previous_transforms(world, player);
+
             let mut h = world
                 .get_mut::<Health>(player)
                 .expect("Player should have a health component");
@@ -0,0 +1,320 @@
+//! Projectiles (rockets, grenades...). Unlike the hitscan guns, they are simulated by the
+//! physics world and explode when they touch something or when their lifetime is over.
+//!
+//! Use `spawn_projectile` to create one and add `ProjectileSystem` to the loop, after the
+//! contact events have been emitted.
+
+use crate::ecs::Transform;
+use crate::event::GameEvent;
+use crate::gameplay::health::Health;
+use crate::net::snapshot::Networked;
+use crate::physics::{BodyToEntity, BodyType, PhysicWorld, RigidBody, Shape};
+use crate::render::Render;
+use crate::resources::Resources;
+use hecs::{Entity, World};
+#[allow(unused_imports)]
+use log::{debug, info};
+use serde_derive::{Deserialize, Serialize};
+use shrev::{EventChannel, ReaderId};
+use std::time::Duration;
+
+const PROJECTILE_MESH: &str = "_simple_sphere_Sphere";
+const PROJECTILE_HALF_SIZE: f32 = 0.1;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ProjectileKind {
+    /// Flies straight and explodes on contact.
+    Rocket,
+    /// Falls and rolls on the ground, explodes at the end of its lifetime.
+    Grenade,
+}
+
+impl ProjectileKind {
+    /// Seconds before the projectile explodes by itself.
+    pub fn lifetime(self) -> f32 {
+        match self {
+            ProjectileKind::Rocket => 5.0,
+            ProjectileKind::Grenade => 3.0,
+        }
+    }
+
+    /// Radius of the explosion. Every entity with health in it is damaged.
+    pub fn blast_radius(self) -> f32 {
+        match self {
+            ProjectileKind::Rocket => 3.0,
+            ProjectileKind::Grenade => 4.0,
+        }
+    }
+
+    fn explode_on_contact(self) -> bool {
+        match self {
+            ProjectileKind::Rocket => true,
+            ProjectileKind::Grenade => false,
+        }
+    }
+
+    /// Rockets are not affected by gravity so that they fly straight.
+    fn has_gravity(self) -> bool {
+        match self {
+            ProjectileKind::Rocket => false,
+            ProjectileKind::Grenade => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Projectile {
+    pub kind: ProjectileKind,
+    /// Time left before exploding, in seconds.
+    pub lifetime: f32,
+    /// Initial velocity.
+    pub velocity: glam::Vec3,
+    /// Entity that fired the projectile. Contacts with the owner are ignored and the
+    /// explosion damages are attributed to it.
+    pub owner: Option<Entity>,
+}
+
+/// Spawn a projectile at `origin`, moving along `dir` at `speed` (in units per second). The
+/// body is dynamic and uses continuous collision detection so that it does not go through thin
+/// walls.
+///
+/// Set `Projectile::owner` to the shooter afterwards so that the contacts with it are
+/// ignored and the damages are attributed to it.
+pub fn spawn_projectile(
+    world: &mut World,
+    physics: &mut PhysicWorld,
+    resources: &Resources,
+    origin: glam::Vec3,
+    dir: glam::Vec3,
+    speed: f32,
+    kind: ProjectileKind,
+) -> Entity {
+    let velocity = dir.normalize() * speed;
+    let transform = Transform::new(
+        origin,
+        glam::Quat::identity(),
+        glam::Vec3::splat(PROJECTILE_HALF_SIZE),
+    );
+    let rb = RigidBody {
+        mass: 1.0,
+        shape: Shape::AABB(glam::Vec3::splat(PROJECTILE_HALF_SIZE)),
+        ty: BodyType::Dynamic,
+        max_linear_velocity: speed.max(1.0) * 2.0,
+        ccd: true,
+        disable_gravity: !kind.has_gravity(),
+        ..RigidBody::default()
+    };
+    let render = Render {
+        mesh: PROJECTILE_MESH.to_string(),
+        enabled: true,
+    };
+
+    let e = world.spawn((
+        transform,
+        rb,
+        render,
+        Projectile {
+            kind,
+            lifetime: kind.lifetime(),
+            velocity,
+            owner: None,
+        },
+        Networked,
+    ));
+
+    let mut body_to_entity = resources.fetch_mut::<BodyToEntity>().unwrap();
+    if let Some(h) = physics.register_entity(world, &mut body_to_entity, e) {
+        physics.set_linear_velocity(h, velocity);
+    }
+
+    debug!("Spawned projectile {:?} at {:?}", kind, origin);
+    e
+}
+
+/// Update the projectiles lifetime and make them explode.
+pub struct ProjectileSystem {
+    rdr_id: ReaderId<GameEvent>,
+}
+
+impl ProjectileSystem {
+    pub fn new(resources: &mut Resources) -> Self {
+        let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
+        let rdr_id = chan.register_reader();
+        Self { rdr_id }
+    }
+
+    pub fn update(
+        &mut self,
+        world: &World,
+        physics: &PhysicWorld,
+        dt: Duration,
+        resources: &Resources,
+    ) {
+        let mut to_explode = vec![];
+        {
+            let chan = resources.fetch::<EventChannel<GameEvent>>().unwrap();
+            for ev in chan.read(&mut self.rdr_id) {
+                if let GameEvent::Collision { a, b, .. } = ev {
+                    for (projectile_entity, other) in &[(*a, *b), (*b, *a)] {
+                        if let Ok(p) = world.get::<Projectile>(*projectile_entity) {
+                            if p.kind.explode_on_contact() && p.owner != Some(*other) {
+                                to_explode.push(*projectile_entity);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for (e, p) in world.query::<&mut Projectile>().iter() {
+            p.lifetime -= dt.as_secs_f32();
+            if p.lifetime <= 0.0 {
+                to_explode.push(e);
+            }
+        }
+
+        to_explode.sort_by_key(|e| e.to_bits());
+        to_explode.dedup();
+
+        let mut events = vec![];
+        for e in to_explode {
+            self.explode(world, physics, resources, e, &mut events);
+        }
+
+        let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
+        chan.drain_vec_write(&mut events);
+    }
+
+    /// Damage everything in the blast radius and delete the projectile.
+    fn explode(
+        &self,
+        world: &World,
+        physics: &PhysicWorld,
+        resources: &Resources,
+        e: Entity,
+        events: &mut Vec<GameEvent>,
+    ) {
+        let (p, center) = match (world.get::<Projectile>(e), world.get::<Transform>(e)) {
+            (Ok(p), Ok(t)) => (*p, t.translation),
+            _ => return,
+        };
+        info!("Projectile {:?} explodes at {:?}", p.kind, center);
+
+        let body_to_entity = resources.fetch::<BodyToEntity>().unwrap();
+        let touched =
+            physics.entities_in_radius(world, &body_to_entity, center, p.kind.blast_radius());
+        for (entity, _) in touched {
+            if entity == e || world.get::<Health>(entity).is_err() {
+                continue;
+            }
+
+            let hit_point = world
+                .get::<Transform>(entity)
+                .map(|t| t.translation)
+                .unwrap_or(center);
+            let dir = hit_point - center;
+            events.push(GameEvent::EntityShot {
+                entity,
+                attacker: p.owner.unwrap_or(e),
+                dir: if dir.length_squared() > 0.0 {
+                    dir.normalize()
+                } else {
+                    glam::Vec3::unit_y()
+                },
+                hit_point,
+            });
+        }
+
+        events.push(GameEvent::Delete(e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::Paths;
+
+    fn target(
+        world: &mut World,
+        physics: &mut PhysicWorld,
+        resources: &Resources,
+        z: f32,
+    ) -> Entity {
+        let mut transform = Transform::default();
+        transform.translation = glam::vec3(0.0, 0.0, z);
+        let rb = RigidBody {
+            shape: Shape::AABB(glam::vec3(0.5, 0.5, 0.5)),
+            ..RigidBody::default()
+        };
+        let e = world.spawn((
+            transform,
+            rb,
+            Health {
+                current: 10.0,
+                max: 10.0,
+            },
+        ));
+        let mut body_to_entity = resources.fetch_mut::<BodyToEntity>().unwrap();
+        physics.register_entity(world, &mut body_to_entity, e);
+        e
+    }
+
+    #[test]
+    fn rocket_explodes_on_contact() {
+        let mut resources = Resources::new();
+        resources.insert(EventChannel::<GameEvent>::new());
+        resources.insert(BodyToEntity::default());
+        resources.insert(Paths::new("assets/", "config/"));
+        let mut physics = PhysicWorld::new(&mut resources);
+        let mut system = ProjectileSystem::new(&mut resources);
+        let mut rdr_id = resources
+            .fetch_mut::<EventChannel<GameEvent>>()
+            .unwrap()
+            .register_reader();
+
+        let mut world = World::new();
+        let shooter = target(&mut world, &mut physics, &resources, 0.0);
+        let other = target(&mut world, &mut physics, &resources, 10.0);
+        // spawned inside the shooter.
+        let rocket = spawn_projectile(
+            &mut world,
+            &mut physics,
+            &resources,
+            glam::vec3(0.0, 0.0, 0.4),
+            glam::Vec3::unit_z(),
+            20.0,
+            ProjectileKind::Rocket,
+        );
+        world.get_mut::<Projectile>(rocket).unwrap().owner = Some(shooter);
+
+        let dt = Duration::from_secs_f32(physics.timestep());
+        let mut deleted = false;
+        let mut shot = vec![];
+        // much less than the lifetime of the rocket.
+        for _ in 0..60 {
+            physics.step();
+            physics.emit_contact_events(&resources);
+            system.update(&world, &physics, dt, &resources);
+            let chan = resources.fetch::<EventChannel<GameEvent>>().unwrap();
+            for ev in chan.read(&mut rdr_id) {
+                match ev {
+                    GameEvent::Delete(e) => deleted |= *e == rocket,
+                    GameEvent::EntityShot {
+                        entity, attacker, ..
+                    } => shot.push((*entity, *attacker)),
+                    _ => (),
+                }
+            }
+            if deleted {
+                break;
+            }
+        }
+
+        assert!(deleted);
+        // not affected by gravity.
+        let h = world.get::<RigidBody>(rocket).unwrap().handle.unwrap();
+        assert!(physics.get_position(h).unwrap().y().abs() < 1e-3);
+        // the damages come from the shooter, which is too far to be hit.
+        assert_eq!(vec![(other, shooter)], shot);
+    }
+}
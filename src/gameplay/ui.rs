@@ -2,24 +2,102 @@
 //! Health, armor, gun, ammos and so on.
 
 use crate::animation::AnimationController;
-use crate::colors::RgbColor;
+use crate::colors::{self, RgbColor};
 use crate::ecs::serialization::SerializedEntity;
 use crate::event::GameEvent;
 use crate::gameplay::gun::Gun;
 use crate::gameplay::health::Health;
 use crate::gameplay::player::MainPlayer;
+use crate::paths::Paths;
 use crate::render::sprite::{ScreenPosition, SpriteRender};
 use crate::render::text::Text;
 use crate::resources::Resources;
 use log::info;
+use serde_derive::{Deserialize, Serialize};
 use shrev::{EventChannel, ReaderId};
 use std::fs;
 
+/// Which part of the HUD an entity belongs to. Used to show/hide the HUD elements separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HudElement {
+    Crosshair,
+    Gun,
+    Ammo,
+    Health,
+    Armor,
+}
+
+/// Visibility of the HUD elements. Stored in the resources and consulted by the sprite and text
+/// renderers. When it is modified, `GameEvent::UpdateText` needs to be sent so that the text is
+/// rendered again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HudConfig {
+    pub crosshair: bool,
+    pub gun: bool,
+    pub ammo: bool,
+    pub health: bool,
+    pub armor: bool,
+    /// Layer of the text. Sprites with a `RenderLayer` above it are drawn over the text, the
+    /// others are drawn below.
+    #[serde(default)]
+    pub text_layer: i32,
+    /// Look of the crosshair.
+    #[serde(default)]
+    pub crosshair_style: CrosshairStyle,
+}
+
+/// Sprite, size and color of the crosshair. The sprite is loaded from
+/// `assets/sprites/<sprite>.png` (with its `.ron` metadata) the first time it is used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrosshairStyle {
+    pub sprite: String,
+    /// Width and height, as a fraction of the screen size.
+    pub size: f32,
+    /// Multiplied with the sprite color.
+    pub color: RgbColor,
+}
+
+impl Default for CrosshairStyle {
+    fn default() -> Self {
+        Self {
+            sprite: "crosshair".to_string(),
+            size: 0.01,
+            color: colors::WHITE,
+        }
+    }
+}
+
+impl Default for HudConfig {
+    fn default() -> Self {
+        Self {
+            crosshair: true,
+            gun: true,
+            ammo: true,
+            health: true,
+            armor: true,
+            text_layer: 0,
+            crosshair_style: CrosshairStyle::default(),
+        }
+    }
+}
+
+impl HudConfig {
+    pub fn is_visible(&self, element: HudElement) -> bool {
+        match element {
+            HudElement::Crosshair => self.crosshair,
+            HudElement::Gun => self.gun,
+            HudElement::Ammo => self.ammo,
+            HudElement::Health => self.health,
+            HudElement::Armor => self.armor,
+        }
+    }
+}
+
 pub struct UiSystem {
     health_entity: hecs::Entity,
     ammo_entity: hecs::Entity,
     _armor_entity: hecs::Entity,
-    _crosshair_entity: hecs::Entity,
+    crosshair_entity: hecs::Entity,
     //weapon_entity: Option<hecs::Entity>,
     rdr_id: ReaderId<GameEvent>,
 }
@@ -36,19 +114,29 @@ impl UiSystem {
         let ammo_entity = spawn_ammo_counter(world);
         let armor_entity = spawn_armor_counter(world);
 
-        //let weapon_entity = spawn_weapon(world);
-        let crosshair_entity = spawn_crosshair(world);
+        //let weapon_entity = spawn_weapon(world, &resources.fetch::<Paths>().unwrap());
+        let crosshair_entity = spawn_crosshair(
+            world,
+            &resources
+                .fetch::<HudConfig>()
+                .map(|hud| hud.crosshair_style.clone())
+                .unwrap_or_default(),
+        );
         Self {
             health_entity,
             ammo_entity,
             _armor_entity: armor_entity,
             //weapon_entity,
-            _crosshair_entity: crosshair_entity,
+            crosshair_entity,
             rdr_id,
         }
     }
 
     pub fn update(&mut self, world: &mut hecs::World, resources: &mut Resources) {
+        if let Some(hud) = resources.fetch::<HudConfig>() {
+            self.update_crosshair(world, &hud.crosshair_style);
+        }
+
         let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
 
         let mut should_update = false;
@@ -98,6 +186,23 @@ impl UiSystem {
         }
     }
 
+    /// The crosshair style can be modified at runtime from the editor.
+    fn update_crosshair(&self, world: &mut hecs::World, style: &CrosshairStyle) {
+        if let Ok(mut sprite) = world.get_mut::<SpriteRender>(self.crosshair_entity) {
+            if sprite.texture != style.sprite {
+                sprite.texture = style.sprite.clone();
+                sprite.sprite_nb = 0;
+            }
+        }
+        if let Ok(mut pos) = world.get_mut::<ScreenPosition>(self.crosshair_entity) {
+            pos.w = style.size;
+            pos.h = style.size;
+        }
+        if let Ok(mut color) = world.get_mut::<RgbColor>(self.crosshair_entity) {
+            *color = style.color;
+        }
+    }
+
     fn update_ammo(&self, world: &hecs::World) -> bool {
         let mut should_update = false;
         //        if let Some(_) = self.weapon_entity {
@@ -129,6 +234,7 @@ fn spawn_health_counter(world: &mut hecs::World) -> hecs::Entity {
             ..ScreenPosition::default()
         },
         RgbColor { r: 255, g: 0, b: 0 },
+        HudElement::Health,
     ));
 
     e
@@ -156,6 +262,7 @@ fn spawn_ammo_counter(world: &mut hecs::World) -> hecs::Entity {
             g: 177,
             b: 177,
         },
+        HudElement::Ammo,
     ));
 
     e
@@ -173,14 +280,15 @@ fn spawn_armor_counter(world: &mut hecs::World) -> hecs::Entity {
             ..ScreenPosition::default()
         },
         RgbColor { r: 0, g: 0, b: 255 },
+        HudElement::Armor,
     ));
 
     e
 }
 
-fn spawn_weapon(world: &mut hecs::World) -> Option<hecs::Entity> {
+fn spawn_weapon(world: &mut hecs::World, paths: &Paths) -> Option<hecs::Entity> {
     let prefab = if let Some((_, (_, g))) = world.query::<(&MainPlayer, &Gun)>().iter().next() {
-        let prefab_path = g.gun_type.get_prefab_path();
+        let prefab_path = g.gun_type.get_prefab_path(paths);
         let ser: SerializedEntity =
             ron::de::from_str(&fs::read_to_string(&prefab_path).unwrap()).unwrap();
         Some(ser)
@@ -190,6 +298,7 @@ fn spawn_weapon(world: &mut hecs::World) -> Option<hecs::Entity> {
 
     prefab.and_then(|ser| {
         let e = crate::ecs::serialization::spawn_entity(world, &ser);
+        world.insert_one(e, HudElement::Gun).unwrap();
         Some(e)
     })
     //    let mut animations = HashMap::new();
@@ -223,18 +332,20 @@ fn spawn_weapon(world: &mut hecs::World) -> Option<hecs::Entity> {
     //    e
 }
 
-fn spawn_crosshair(world: &mut hecs::World) -> hecs::Entity {
+fn spawn_crosshair(world: &mut hecs::World, style: &CrosshairStyle) -> hecs::Entity {
     let e = world.spawn((
         ScreenPosition {
             x: 0.5,
             y: 0.5,
-            w: 0.01,
-            h: 0.01,
+            w: style.size,
+            h: style.size,
         },
         SpriteRender {
             sprite_nb: 0,
-            texture: String::from("crosshair"),
+            texture: style.sprite.clone(),
         },
+        style.color,
+        HudElement::Crosshair,
     ));
 
     e
@@ -39,6 +39,23 @@ pub fn euler_from_quat(q: glam::Quat) -> (f32, f32, f32) {
     (yaw, pitch, roll)
 }
 
+/// Pitch and yaw of a direction, with the same convention as the `Camera`: pitch is the angle
+/// with the horizontal plane and yaw is the angle from the x axis, around the y axis.
+pub fn pitch_yaw_from_direction(front: glam::Vec3) -> (f32, f32) {
+    let front = front.normalize();
+    let pitch = front.y().max(-1.0).min(1.0).asin();
+    let yaw = front.z().atan2(front.x());
+    (pitch, yaw)
+}
+
+/// Rotation without roll whose forward vector (see `quat_to_direction`) points toward the given
+/// pitch and yaw.
+pub fn quat_from_pitch_yaw(pitch: f32, yaw: f32) -> glam::Quat {
+    // the forward vector of the identity is the z axis, at yaw = PI/2.
+    glam::Quat::from_rotation_y(std::f32::consts::FRAC_PI_2 - yaw)
+        * glam::Quat::from_rotation_x(-pitch)
+}
+
 pub fn quat_to_direction(q: glam::Quat) -> (glam::Vec3, glam::Vec3, glam::Vec3) {
     /*
     forward vector:
@@ -75,3 +92,21 @@ pub fn quat_to_direction(q: glam::Quat) -> (glam::Vec3, glam::Vec3, glam::Vec3)
 
     (front, up, left)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pitch_yaw_round_trip() {
+        for &(pitch, yaw) in &[(0.0, 0.0), (0.3, 1.0), (-0.5, -2.0), (1.2, 3.0)] {
+            let camera = crate::camera::Camera::new(pitch, yaw);
+            let (front, _, _) = quat_to_direction(quat_from_pitch_yaw(pitch, yaw));
+            assert!((front - camera.front).length() < 1e-5);
+
+            let (p, y) = pitch_yaw_from_direction(front);
+            assert!((p - pitch).abs() < 1e-5);
+            assert!((y - yaw).abs() < 1e-5);
+        }
+    }
+}
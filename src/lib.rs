@@ -3,6 +3,7 @@ extern crate bitflags;
 
 pub mod animation;
 pub mod assets;
+pub mod bounding_box;
 pub mod camera;
 pub mod collections;
 pub mod colors;
@@ -14,6 +15,7 @@ pub mod gameplay;
 pub mod geom;
 pub mod input;
 pub mod net;
+pub mod paths;
 pub mod physics;
 pub mod render;
 pub mod resources;
@@ -1,25 +1,33 @@
 use luminance_glfw::{Action, GlfwSurface, Key, Surface, WindowDim, WindowOpt};
 use std::process::exit;
+use std::thread;
 use std::time::{Duration, Instant};
 
-use imgui::{Context, FontConfig, FontGlyphRanges, FontSource};
+use imgui::{Context, FontConfig, FontGlyphRanges, FontSource, ImString};
 #[allow(unused_imports)]
 use log::{debug, error, info};
 use luminance_windowing::CursorMode;
 use r3dtest::animation::AnimationSystem;
+use r3dtest::assets::{self, material::Material};
 use r3dtest::camera::Camera;
+use r3dtest::controller::bot::{spawn_bot, BotController};
 use r3dtest::controller::free::FreeController;
 use r3dtest::controller::{client, Controller, Fps};
+use r3dtest::ecs::save::{load_state, save_state};
 use r3dtest::ecs::WorldLoader;
 use r3dtest::event::Event;
 use r3dtest::gameplay::delete::GarbageCollector;
-use r3dtest::gameplay::gun::GunSystem;
+use r3dtest::gameplay::gun::{GunConfig, GunSystem};
 use r3dtest::gameplay::health::HealthSystem;
 use r3dtest::gameplay::pickup::PickUpSystem;
 use r3dtest::gameplay::player::{
-    spawn_player, update_player_orientations, MainPlayer, PlayerSystem,
+    spawn_player, update_player_orientations, GameModeConfig, MainPlayer, PlayerSystem,
 };
-use r3dtest::gameplay::ui::UiSystem;
+use r3dtest::gameplay::projectile::ProjectileSystem;
+use r3dtest::gameplay::ui::{HudConfig, UiSystem};
+use r3dtest::net::snapshot::mark_networked;
+use r3dtest::paths::Paths;
+use r3dtest::physics::gravity_zone::apply_gravity_zones;
 use r3dtest::physics::{BodyToEntity, PhysicWorld};
 use r3dtest::render::assets::AssetManager;
 use r3dtest::render::debug::update_debug_components;
@@ -33,10 +41,38 @@ use serde_derive::{Deserialize, Serialize};
 use shrev::EventChannel;
 use std::fs::{self};
 
+/// Editor state saved between sessions, relative to the config directory.
+const IMGUI_INI: &str = "imgui.ini";
+/// Quick save file in the config directory (F5 to save, F9 to load).
+const QUICK_SAVE: &str = "quicksave.ron";
+const FREE_CAMERA_STATE: &str = "free_camera.ron";
+/// Maximum number of physics steps per frame. Above that, the simulation slows down instead
+/// of taking longer and longer to catch up.
+const MAX_PHYSICS_STEPS: u32 = 5;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WindowConfig {
     width: u32,
     height: u32,
+
+    /// Maximum number of frames per second. 0 means uncapped.
+    #[serde(default)]
+    frame_cap: u32,
+
+    /// Wait for the screen refresh before swapping the buffers.
+    #[serde(default)]
+    vsync: bool,
+}
+
+impl WindowConfig {
+    /// Minimum duration of a frame to respect the frame cap.
+    fn min_frame_duration(&self) -> Option<Duration> {
+        if self.frame_cap == 0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(1.0 / self.frame_cap as f64))
+        }
+    }
 }
 
 fn main() {
@@ -44,8 +80,8 @@ fn main() {
     pretty_env_logger::init();
 
     let map_name: String = std::env::args().nth(1).unwrap_or("lol.ron".to_string());
-    let window_config =
-        fs::read_to_string(std::env::var("CONFIG_PATH").unwrap() + "config.ron").unwrap();
+    let paths = Paths::from_env();
+    let window_config = fs::read_to_string(paths.config("config.ron")).unwrap();
     let conf: WindowConfig = ron::de::from_str(&window_config).unwrap();
     let surface = GlfwSurface::new(
         WindowDim::Windowed(conf.width, conf.height),
@@ -54,9 +90,16 @@ fn main() {
     );
 
     match surface {
-        Ok(surface) => {
+        Ok(mut surface) => {
+            let swap_interval = if conf.vsync {
+                glfw::SwapInterval::Sync(1)
+            } else {
+                glfw::SwapInterval::None
+            };
+            surface.window.glfw.set_swap_interval(swap_interval);
+
             debug!("Will enter main loop");
-            main_loop(surface, map_name);
+            main_loop(surface, map_name, paths, conf.min_frame_duration());
         }
         Err(e) => {
             error!("Cannot create graphic surface: {}", e);
@@ -66,29 +109,37 @@ fn main() {
     info!("Hello, world!");
 }
 
-fn load_optional_config<T: DeserializeOwned + 'static>(path: &str, resources: &mut Resources) {
-    if let Ok(conf_str) = fs::read_to_string(std::env::var("CONFIG_PATH").unwrap() + path) {
+fn load_optional_config<T: DeserializeOwned + Default + 'static>(
+    path: &str,
+    resources: &mut Resources,
+) {
+    let conf_path = resources.fetch::<Paths>().unwrap().config(path);
+    if let Ok(conf_str) = fs::read_to_string(conf_path) {
         let conf: Result<T, _> = ron::de::from_str(&conf_str);
         if let Ok(conf) = conf {
             resources.insert(conf);
         } else {
-            error!("Found render config but could not deserialize it.");
+            error!("Found {} but could not deserialize it.", path);
         }
     } else {
-        info!("No config for Renderer. Will use default instead");
-        resources.insert(RenderConfig::default());
+        info!("No {} config. Will use default instead", path);
+        resources.insert(T::default());
     }
 }
 
-fn setup_resources() -> Resources {
+fn setup_resources(paths: Paths) -> Resources {
     let mut resources = Resources::default();
+    resources.insert(paths);
     let event_channel: EventChannel<GameEvent> = EventChannel::new();
     resources.insert(event_channel);
     let input = Input::new();
     resources.insert(input);
 
-    // optional renderer config.
+    // optional configs.
+    load_optional_config::<HudConfig>("hud.ron", &mut resources);
     load_optional_config::<RenderConfig>("render.ron", &mut resources);
+    load_optional_config::<GameModeConfig>("gamemode.ron", &mut resources);
+    load_optional_config::<GunConfig>("guns.ron", &mut resources);
 
     resources
 }
@@ -100,17 +151,22 @@ enum ControllerMode {
     Editor,
 }
 
-fn main_loop(mut surface: GlfwSurface, map_name: String) {
-    let mut resources = setup_resources();
+fn main_loop(
+    mut surface: GlfwSurface,
+    map_name: String,
+    paths: Paths,
+    min_frame_duration: Option<Duration>,
+) {
+    let mut resources = setup_resources(paths);
 
     let mut physics = PhysicWorld::new(&mut resources);
 
     // SETUP WORLD.
-    let (mut loader, mut world) = WorldLoader::new(format!(
-        "{}world/{}",
-        std::env::var("ASSET_PATH").unwrap(),
-        map_name
-    ));
+    let world_path = resources
+        .fetch::<Paths>()
+        .unwrap()
+        .asset(&format!("world/{}", map_name));
+    let (mut loader, mut world) = WorldLoader::new(world_path);
     //let mut world = ecs::serialization::deserialize_world(world_str).unwrap();
 
     let mut body_to_entity = BodyToEntity::default();
@@ -121,11 +177,14 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
     }
 
     resources.insert(body_to_entity);
-    let asset_manager = AssetManager::new(&mut surface);
+    let asset_manager = AssetManager::new(&mut surface, &resources.fetch::<Paths>().unwrap());
     resources.insert(asset_manager);
+    // meshes and materials for the PBR renderer.
+    assets::create_asset_managers(&mut surface, &mut resources);
 
-    let player_entity = spawn_player(&mut world, &mut physics, &resources);
+    let mut player_entity = spawn_player(&mut world, &mut physics, &resources);
     world.insert_one(player_entity, MainPlayer).unwrap();
+    mark_networked(&mut world, player_entity);
 
     let mut garbage_collector = GarbageCollector::new(&mut resources);
     let mut health_system = HealthSystem::new(&mut resources);
@@ -133,13 +192,17 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
     let mut renderer = Renderer::new(&mut surface, &mut resources);
     let mut ui_system = UiSystem::new(&mut world, &mut resources);
     let mut player_system = PlayerSystem::new(&mut resources);
-    let mut animation_system = AnimationSystem;
+    let mut animation_system = AnimationSystem::default();
     let pickup_system = PickUpSystem;
     let mut gun_system = GunSystem::new(&mut resources);
+    let mut projectile_system = ProjectileSystem::new(&mut resources);
 
-    let dt = Duration::from_millis(16);
+    let physics_dt = physics.timestep();
+    // time not simulated yet by the physics.
+    let mut physics_time = 0.0;
 
     let client_controller = client::ClientController::get_offline_controller();
+    let mut bot_controller = BotController::default();
     //let mut fps_controller = FpsController::default();
 
     let mut controller_mode = ControllerMode::Player;
@@ -152,6 +215,8 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
         .next()
         .unwrap();
     let mut current_time = Instant::now();
+    // real time between two frames. Used by the systems that need to follow the wall clock.
+    let mut last_frame = Instant::now();
     let mut imgui = Context::create();
     let font_size = 13.0;
 
@@ -166,17 +231,16 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
     }]);
 
     let mut imgui_renderer = imgui_luminance::Renderer::new(&mut surface, &mut imgui);
-    imgui.set_ini_filename(None);
+    // windows layout is saved when the context is dropped.
+    imgui.set_ini_filename(Some(ImString::new(
+        resources.fetch::<Paths>().unwrap().config(IMGUI_INI),
+    )));
 
     let size = surface.size();
     let mut editor = r3dtest::editor::Editor::new(size[0], size[1]);
 
     let free_camera = world.spawn((
-        Transform::new(
-            glam::vec3(0.0, 0.0, 0.0),
-            glam::Quat::identity(),
-            glam::Vec3::one(),
-        ),
+        load_free_camera_transform(&resources.fetch::<Paths>().unwrap()),
         Camera {
             active: false,
             pitch: 0.0,
@@ -191,6 +255,10 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
     ));
 
     'app: loop {
+        let elapsed = last_frame.elapsed();
+        last_frame = Instant::now();
+        let quick_save;
+        let quick_load;
         {
             let mut input = resources.fetch_mut::<Input>().unwrap();
             if let ControllerMode::Editor = controller_mode {
@@ -222,26 +290,70 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
                     free_camera,
                     &world,
                     &mut physics,
+                    &mut resources.fetch_mut::<BodyToEntity>().unwrap(),
                 );
             }
 
-            //            if input.has_key_event_happened(Key::F3, Action::Press) {
-            //                renderer.next_blending_mod_lighting();
-            //            }
+            if input.has_key_event_happened(Key::F3, Action::Press) {
+                // Someone to practice with, a bit further than the player.
+                let position = world
+                    .get::<Transform>(player_entity)
+                    .map(|t| t.translation)
+                    .unwrap_or_default()
+                    + glam::vec3(0.0, 0.0, 10.0);
+                spawn_bot(&mut world, &mut physics, &resources, position);
+            }
+
+            quick_save = input.has_key_event_happened(Key::F5, Action::Press);
+            quick_load = input.has_key_event_happened(Key::F9, Action::Press);
         }
 
+        if quick_save {
+            let path = resources.fetch::<Paths>().unwrap().config(QUICK_SAVE);
+            if let Err(e) = save_state(&world, &physics, loader.entities(), &path) {
+                error!("Cannot save the game = {}", e);
+            }
+        }
+        if quick_load {
+            let path = resources.fetch::<Paths>().unwrap().config(QUICK_SAVE);
+            match load_state(
+                &mut world,
+                &mut physics,
+                &resources,
+                loader.entities(),
+                &path,
+            ) {
+                Ok(loaded) => {
+                    loader.set_entities(loaded.map_entities);
+                    // the saved entities were spawned again.
+                    if let Some((e, _)) = world.query::<&MainPlayer>().iter().next() {
+                        player_entity = e;
+                    }
+                }
+                Err(e) => error!("Cannot load the game = {}", e),
+            }
+        }
+
+        // applied at every physics step.
+        let mut movement_cmds = vec![];
         match controller_mode {
             ControllerMode::Player => {
-                let cmds = client_controller
+                let mut cmds: Vec<_> = client_controller
                     .process_input(&mut world, &mut resources)
                     .drain(..)
                     .map(|ev| (player_entity, Event::Client(ev)))
                     .collect();
+                cmds.extend(bot_controller.process(&world, &physics, &resources));
 
                 //fps_controller.apply_commands(&cmds);
-                controller.apply_inputs(cmds, &mut world, &mut physics, &resources);
-
-                controller.update(&mut world, &mut physics, &resources);
+                let mut other_cmds = vec![];
+                for (e, ev) in cmds {
+                    match ev {
+                        Event::Client(cmd) if cmd.is_movement() => movement_cmds.push((e, cmd)),
+                        ev => other_cmds.push((e, ev)),
+                    }
+                }
+                controller.apply_inputs(other_cmds, &mut world, &mut physics, &resources);
             }
             ControllerMode::Free => {
                 free_controller.process_input(&mut world, &mut resources, free_camera)
@@ -254,37 +366,48 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
         // ----------------------------------------------------
         // PHYSIC SIMULATION
         // ----------------------------------------------------
-        physics.step();
-
-        // Update the positions.
-        for (e, (mut t, rb)) in world.query::<(&mut Transform, &RigidBody)>().iter() {
-            if let Some(h) = rb.handle {
-                if let Some(new_iso) = physics.get_isometry(h) {
-                    if t.translation != new_iso.translation || t.rotation != new_iso.rotation {
-                        t.dirty = true;
-                    }
-                    t.translation = new_iso.translation;
-
-                    // hummm FIXME
-                    if world.get::<MainPlayer>(e).is_err() {
-                        t.rotation = new_iso.rotation;
-                    }
-                }
+        // The physics runs at a fixed rate. The meshes are rendered between the last two
+        // steps so that the movement does not stutter when the frame time varies.
+        physics_time += elapsed.as_secs_f32();
+        let mut steps = 0;
+        while physics_time >= physics_dt {
+            if steps == MAX_PHYSICS_STEPS {
+                // Too late to catch up, drop the remaining time.
+                physics_time = 0.0;
+                break;
             }
+            r3dtest::ecs::save_previous_transforms(&mut world);
+            if let ControllerMode::Player = controller_mode {
+                let cmds = movement_cmds
+                    .iter()
+                    .map(|(e, cmd)| (*e, Event::Client(*cmd)))
+                    .collect();
+                controller.apply_inputs(cmds, &mut world, &mut physics, &resources);
+                controller.update(&mut world, &mut physics, &resources);
+            }
+            apply_gravity_zones(&world, &mut physics);
+            physics.step();
+            physics.emit_contact_events(&resources);
+            update_physics_transforms(&mut world, &physics);
+            physics_time -= physics_dt;
+            steps += 1;
         }
-        // update child components.
+        renderer.set_interpolation(physics_time / physics_dt);
+        // the controllers can move the entities between two physics steps.
         r3dtest::transform::update_transforms(&mut world);
 
-        renderer.update(&mut world, dt, &mut resources);
+        renderer.update(&mut world, elapsed, &mut resources);
 
+        // Projectiles need the contact events from this frame.
+        projectile_system.update(&world, &physics, elapsed, &resources);
         // Update health if somebody has been SHOT.
         health_system.update(&mut world, &resources);
         ui_system.update(&mut world, &mut resources);
-        player_system.update(dt, &mut world, &resources);
+        player_system.update(elapsed, &mut world, &mut physics, &resources);
         animation_system.animate(&mut world);
         update_player_orientations(&mut world);
         update_debug_components(&mut world, &physics);
-        gun_system.update(&mut world, dt, &mut resources);
+        gun_system.update(&mut world, elapsed, &mut resources);
         pickup_system.update(&world, &physics, &mut resources);
         //fps_controller.update(&mut world, &mut physics, dt);
 
@@ -295,10 +418,16 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
         // render the editor.
         let ui = imgui.frame();
         editor.show_components(&ui, &world, &mut resources);
+        editor.show_physics_stats(&ui, physics.stats());
+        editor.show_hud_config(&ui, &resources);
         //ui.show_demo_window(&mut true);
         let draw_data = ui.render();
         imgui_renderer.prepare(&mut surface, draw_data);
 
+        renderer.set_grid_visible(match controller_mode {
+            ControllerMode::Free | ControllerMode::Editor => true,
+            ControllerMode::Player => false,
+        });
         renderer.render(
             &mut surface,
             &world,
@@ -319,12 +448,75 @@ fn main_loop(mut surface: GlfwSurface, map_name: String) {
         physics.process_events(&mut world, &resources);
         // FIXME
         surface.swap_buffers();
-        let now = Instant::now();
-        let frame_duration = now - current_time;
-        if frame_duration < dt {
-            //thread::sleep(dt - frame_duration);
+
+        // Upload the materials to GPU when available. They are loaded in another thread so
+        // they will only be rendered after that.
+        {
+            let mut mat_mgr = resources
+                .fetch_mut::<assets::AssetManager<Material>>()
+                .unwrap();
+            mat_mgr.upload_all(&mut surface);
+        }
+        let frame_duration = Instant::now() - current_time;
+        if let Some(min_frame_duration) = min_frame_duration {
+            if frame_duration < min_frame_duration {
+                thread::sleep(min_frame_duration - frame_duration);
+            }
+        }
+        current_time = Instant::now();
+    }
+
+    // so that we can continue from the same place in the next session.
+    if let Ok(t) = world.get::<Transform>(free_camera) {
+        save_free_camera_transform(&resources.fetch::<Paths>().unwrap(), &t);
+    }
+}
+
+/// Copy the positions of the rigid bodies to their transforms and update the children.
+fn update_physics_transforms(world: &mut hecs::World, physics: &PhysicWorld) {
+    for (e, (mut t, rb)) in world.query::<(&mut Transform, &RigidBody)>().iter() {
+        if let Some(h) = rb.handle {
+            if let Some(new_iso) = physics.get_isometry(h) {
+                if t.translation != new_iso.translation || t.rotation != new_iso.rotation {
+                    t.dirty = true;
+                }
+                t.translation = new_iso.translation;
+
+                // hummm FIXME
+                if world.get::<MainPlayer>(e).is_err() {
+                    t.rotation = new_iso.rotation;
+                }
+            }
         }
-        current_time = now;
+    }
+    // update child components.
+    r3dtest::transform::update_transforms(world);
+}
+
+/// Where the free camera was at the end of the last session. At the origin if there was
+/// no previous session.
+fn load_free_camera_transform(paths: &Paths) -> Transform {
+    let default = Transform::new(
+        glam::vec3(0.0, 0.0, 0.0),
+        glam::Quat::identity(),
+        glam::Vec3::one(),
+    );
+    match fs::read_to_string(paths.config(FREE_CAMERA_STATE)) {
+        Ok(content) => ron::de::from_str(&content).unwrap_or_else(|e| {
+            error!("Cannot read {}: {}", FREE_CAMERA_STATE, e);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+fn save_free_camera_transform(paths: &Paths, transform: &Transform) {
+    let path = paths.config(FREE_CAMERA_STATE);
+    let result = ron::ser::to_string_pretty(transform, ron::ser::PrettyConfig::default())
+        .map_err(|e| e.to_string())
+        .and_then(|content| fs::write(&path, content).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        error!("Cannot save the free camera to {}: {}", path, e);
     }
 }
 
@@ -335,13 +527,13 @@ fn toggle_controller(
     free_camera: hecs::Entity,
     world: &hecs::World,
     physics: &mut PhysicWorld,
+    body_to_entity: &mut BodyToEntity,
 ) {
     let new_mode = match current_controller_mode {
         ControllerMode::Player => {
             *previous_controller_mode = *current_controller_mode;
 
-            let rb = world.get::<RigidBody>(player_entity).unwrap();
-            physics.remove_body(rb.handle.unwrap());
+            physics.unregister_entity(world, body_to_entity, player_entity);
 
             let mut free_cam_c = world.get_mut::<Camera>(free_camera).unwrap();
             free_cam_c.active = true;
@@ -357,9 +549,7 @@ fn toggle_controller(
         ControllerMode::Free => {
             *previous_controller_mode = *current_controller_mode;
 
-            let mut rb = world.get_mut::<RigidBody>(player_entity).unwrap();
-            let t = world.get::<Transform>(player_entity).unwrap();
-            physics.add_body(&t, &mut rb);
+            physics.register_entity(world, body_to_entity, player_entity);
 
             let mut free_cam_c = world.get_mut::<Camera>(free_camera).unwrap();
             let children = world.get::<HasChildren>(player_entity).unwrap();
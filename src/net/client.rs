@@ -41,7 +41,7 @@ impl ClientSystem {
         let (mut tx, rx) = tokio::sync::mpsc::channel::<NetMessage>(100);
 
         rt.spawn(async move {
-            super::start_server(my_adress, copied_deque, rx).await;
+            super::start_server(my_adress, copied_deque, rx, futures::future::pending()).await;
         });
 
         let last_rec_seq_number = 0;
@@ -4,6 +4,7 @@ use crate::net::protocol::NetMessage;
 use futures_util::sink::SinkExt;
 use futures_util::stream::StreamExt;
 
+use std::future::Future;
 use std::net::SocketAddr;
 use tokio::sync::mpsc;
 
@@ -22,11 +23,17 @@ pub mod protocol;
 pub mod server;
 pub mod snapshot;
 
-pub async fn start_server(
+/// Listen to the socket and forward the messages from the main loop. When `shutdown` completes,
+/// the server stops listening. The forwarding stops once all the senders of `to_clients` have
+/// been dropped and the pending messages have been sent.
+pub async fn start_server<F>(
     addr: SocketAddr,
     from_clients: SharedDeque<NetMessage>,
     to_clients: mpsc::Receiver<NetMessage>,
-) {
+    shutdown: F,
+) where
+    F: Future<Output = ()>,
+{
     info!("Will start UDP on {:?}", addr);
     // Two tasks. One will be listening from messages from the queue. The other will be listening
     // from the socket.
@@ -37,7 +44,15 @@ pub async fn start_server(
 
     let (sink, stream) = socket.split();
 
-    let listen_incoming_task = listen_incoming(stream, from_clients);
+    let listen_incoming_task = async {
+        tokio::select! {
+            res = listen_incoming(stream, from_clients) => res,
+            _ = shutdown => {
+                info!("Stop listening to incoming messages");
+                Ok(())
+            }
+        }
+    };
     let forward_messages_task = forward_messages(sink, to_clients);
 
     match futures::future::try_join(listen_incoming_task, forward_messages_task).await {
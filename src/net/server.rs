@@ -6,11 +6,13 @@ use crate::collections::shared_deque::SharedDeque;
 use crate::net::protocol::{DeltaSnapshotInfo, NetMessage, NetMessageContent, Packet};
 use hecs::{Entity, World};
 use std::net::SocketAddr;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
 
 use crate::event::{Event, GameEvent};
 use crate::gameplay::player;
-use crate::net::snapshot::{SnapshotError, Snapshotter};
+use crate::net::snapshot::{mark_networked, SnapshotError, Snapshotter};
 use crate::physics::PhysicWorld;
 use crate::resources::Resources;
 #[allow(unused_imports)]
@@ -46,30 +48,66 @@ pub struct NetworkSystem {
     /// channel to send messages to the clients.
     to_clients: mpsc::Sender<NetMessage>,
 
-    _rt: tokio::runtime::Runtime,
+    rt: tokio::runtime::Runtime,
+
+    /// Task running the socket. Finished after a shutdown.
+    server_task: JoinHandle<()>,
+
+    /// Signal the server task to stop listening.
+    shutdown_tx: oneshot::Sender<()>,
 
     snapshotter: Snapshotter,
 }
 
 impl NetworkSystem {
     /// Create a new network system. This will also open the sockets :)
-    pub fn new(addr: SocketAddr) -> Self {
+    ///
+    /// `snapshot_timeout` is the time after which a client that does not acknowledge the
+    /// snapshots is disconnected. `tick_hz` is the number of calls to `send_state` per second.
+    pub fn new(addr: SocketAddr, snapshot_timeout: Duration, tick_hz: u32) -> Self {
         let rt = tokio::runtime::Runtime::new().unwrap();
 
         let shared_deque = SharedDeque::new(100);
         let copied_deque = shared_deque.clone();
         let (tx, rx) = tokio::sync::mpsc::channel::<NetMessage>(100);
-
-        rt.spawn(async move {
-            super::start_server(addr, copied_deque, rx).await;
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        let server_task = rt.spawn(async move {
+            let shutdown = async {
+                // Also stops if the sender is dropped.
+                let _ = shutdown_rx.await;
+            };
+            super::start_server(addr, copied_deque, rx, shutdown).await;
         });
 
         Self {
             from_clients: shared_deque,
             to_clients: tx,
             my_clients: OptionArray::new(8),
-            _rt: rt,
-            snapshotter: Snapshotter::new(100),
+            rt,
+            server_task,
+            shutdown_tx,
+            snapshotter: Snapshotter::with_timeout(snapshot_timeout, tick_hz),
+        }
+    }
+
+    /// Stop the network tasks. The messages already queued for the clients are sent before the
+    /// socket is closed. This blocks until the server task is finished.
+    pub fn shutdown(self) {
+        let NetworkSystem {
+            to_clients,
+            mut rt,
+            server_task,
+            shutdown_tx,
+            ..
+        } = self;
+
+        info!("Shutting down the network system");
+        let _ = shutdown_tx.send(());
+        // The forwarding task stops when there is no sender left and the queue is empty.
+        drop(to_clients);
+        if let Err(e) = rt.block_on(server_task) {
+            error!("Error while waiting for the server task = {:?}", e);
         }
     }
 
@@ -168,6 +206,7 @@ impl NetworkSystem {
                         // Now we have a new client, let's create a new player entity
                         // from the player template.
                         let entity = player::spawn_player(ecs, physics, resources);
+                        mark_networked(ecs, entity);
                         debug!("Player {} entity is {:?}", i, entity);
 
                         self.my_clients.get_mut(i).unwrap().entity = Some(entity);
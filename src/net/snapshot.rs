@@ -23,13 +23,15 @@ use crate::gameplay::{
 use crate::render::debug::DebugRender;
 use crate::render::{billboard::Billboard, Render};
 use crate::resources::Resources;
+use crate::transform::HasChildren;
 
 use hecs::{Entity, EntityBuilder, World};
 #[allow(unused_imports)]
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 use serde_derive::{Deserialize, Serialize};
 use shrev::EventChannel;
 use std::collections::HashMap;
+use std::time::Duration;
 use thiserror::Error;
 
 pub trait Deltable: Debug {
@@ -60,10 +62,30 @@ pub enum SnapshotError {
     InvalidStateIndex,
 }
 
+/// Marker for the entities that are replicated to the clients. Entities without it (editor
+/// helpers, server-side triggers...) are local-only and never appear in the snapshots.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Networked;
+
+/// Add the `Networked` marker to the entity and its children.
+pub fn mark_networked(world: &mut World, e: Entity) {
+    let children = world
+        .get::<HasChildren>(e)
+        .map(|c| c.children.clone())
+        .unwrap_or_default();
+    if let Err(err) = world.insert_one(e, Networked) {
+        warn!("Cannot mark {:?} as networked = {:?}", e, err);
+    }
+    for child in children {
+        mark_networked(world, child);
+    }
+}
+
 /// Macro to generate the code that will compute delta for each entity. Just add a component name
 /// and a component to the macro to have it included in the state sent to players.
 ///
-/// That works only for component that implements the `Deltable` trait.
+/// That works only for component that implements the `Deltable` trait. Only the entities with
+/// the `Networked` component are included.
 macro_rules! snapshot {
     ($(($name:ident, $component:ty)),+) => {
 
@@ -80,11 +102,12 @@ macro_rules! snapshot {
         }
 
 
+        /// Copy the components of the entities that have the `Networked` marker.
         fn state_from_current(world: &hecs::World) -> State {
 
             let mut state = HashMap::new();
 
-            for (e, _) in world.iter() {
+            for (e, _) in world.query::<&Networked>().iter() {
 
                 let mut entity_state = EntityState::default();
                 $(
@@ -138,8 +161,15 @@ macro_rules! snapshot {
                     }
                 }
 
-                for deltas in snapshot.deltas {
+                for mut deltas in snapshot.deltas {
                     trace!("delta in snapshot = {:?}", deltas);
+                    // The orientation of the main player comes from the local input and is sent
+                    // to the server, so do not apply it twice.
+                    if snapshot.player_entity == deltas.entity {
+                        if let Some(ref mut delta_transform) = deltas.delta_transform {
+                            delta_transform.clear_rotation();
+                        }
+                    }
                     if let Some(e) = self.server_to_local_entity.get(&deltas.entity) {
 
 
@@ -312,13 +342,39 @@ where
 /// When a client hasn't updated its state fast enough and the circular buffer makes
 /// a full round, the client will be considered disconnected. Timeout to disconnection
 /// can be calculated from buffer size and frame duration. (60 fps -> 1 sec timeout =
-/// buffer of size 60).
+/// buffer of size 60). Use `with_timeout` to get the buffer size from the timeout.
 pub struct Snapshotter {
     state_buf: RingBuffer<State>,
     empty_ecs: State,
 }
 
+/// The index of the states is sent to the clients as a u8.
+const MAX_RING_SIZE: usize = 256;
+
+/// Size of the buffer so that a client is disconnected after `timeout` when the state is
+/// saved `tick_hz` times per second.
+fn ring_size_for_timeout(timeout: Duration, tick_hz: u32) -> usize {
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+    let size =
+        ((timeout.as_nanos() * tick_hz as u128 + NANOS_PER_SEC - 1) / NANOS_PER_SEC) as usize;
+    if size > MAX_RING_SIZE {
+        warn!(
+            "Snapshot timeout of {:?} at {} Hz is too long, use {} states instead of {}",
+            timeout, tick_hz, MAX_RING_SIZE, size
+        );
+    }
+    // at least the current and the previous states.
+    size.max(2).min(MAX_RING_SIZE)
+}
+
 impl Snapshotter {
+    /// Create a snapshotter that will consider a client disconnected when its last known
+    /// state is older than `timeout`. `tick_hz` is the number of calls to `set_current` per
+    /// second. The timeout is capped by the maximum buffer size (256 states).
+    pub fn with_timeout(timeout: Duration, tick_hz: u32) -> Self {
+        Self::new(ring_size_for_timeout(timeout, tick_hz))
+    }
+
     pub fn new(ring_size: usize) -> Self {
         let state_buf = RingBuffer::new(ring_size);
         let empty_ecs = HashMap::new();
@@ -416,17 +472,22 @@ pub fn compute_delta(
             to_delete.push(k.to_bits());
         }
     }
+    to_delete.sort();
 
-    // Get all live entities in current
+    // Get all live entities in current. Local-only entities are not in the state so they are
+    // not sent to the clients.
     let mut deltas = Vec::new();
 
-    for (entity, _) in current_world.iter() {
+    for (entity, _) in current_world.query::<&Networked>().iter() {
         let delta_entity = compute_delta_entity(entity, &current, &old);
 
         if !delta_entity.is_empty() {
             deltas.push(delta_entity);
         }
     }
+    // hecs does not guarantee the iteration order. Sort to get the same snapshot for the same
+    // state (easier to debug).
+    deltas.sort_by_key(|d| d.entity);
 
     DeltaSnapshot {
         player_entity,
@@ -449,3 +510,22 @@ where
 {
     new.as_ref().and_then(|c| c.compute_complete())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_size_from_timeout() {
+        assert_eq!(120, ring_size_for_timeout(Duration::from_secs(2), 60));
+        assert_eq!(45, ring_size_for_timeout(Duration::from_millis(1500), 30));
+        assert_eq!(6, ring_size_for_timeout(Duration::from_millis(100), 60));
+        // rounded up so that the timeout is not shorter than requested.
+        assert_eq!(7, ring_size_for_timeout(Duration::from_millis(110), 60));
+        assert_eq!(2, ring_size_for_timeout(Duration::from_millis(0), 60));
+        assert_eq!(
+            MAX_RING_SIZE,
+            ring_size_for_timeout(Duration::from_secs(10), 60)
+        );
+    }
+}
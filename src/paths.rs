@@ -0,0 +1,60 @@
+//! Where to find the assets and the configuration files. The paths are read once when the
+//! game starts (from the environment or given explicitely) and stored as a resource so that
+//! the rest of the engine does not need to look at the environment variables.
+//!
+//! ```
+//! use r3dtest::paths::Paths;
+//!
+//! let paths = Paths::new("assets/", "config/");
+//! assert_eq!("assets/prefab/player.ron", paths.asset("prefab/player.ron"));
+//! ```
+
+/// Base directories for the assets and the configuration files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    asset_path: String,
+    config_path: String,
+}
+
+impl Paths {
+    pub fn new<A: Into<String>, C: Into<String>>(asset_path: A, config_path: C) -> Self {
+        Self {
+            asset_path: with_trailing_slash(asset_path.into()),
+            config_path: with_trailing_slash(config_path.into()),
+        }
+    }
+
+    /// Read the paths from the `ASSET_PATH` and `CONFIG_PATH` environment variables. Default to
+    /// `./assets/` and `./config/` when not set.
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("ASSET_PATH").unwrap_or_else(|_| "./assets/".to_owned()),
+            std::env::var("CONFIG_PATH").unwrap_or_else(|_| "./config/".to_owned()),
+        )
+    }
+
+    pub fn asset_path(&self) -> &str {
+        &self.asset_path
+    }
+
+    pub fn config_path(&self) -> &str {
+        &self.config_path
+    }
+
+    /// Path of a file relative to the asset directory.
+    pub fn asset(&self, relative: &str) -> String {
+        format!("{}{}", self.asset_path, relative)
+    }
+
+    /// Path of a file relative to the config directory.
+    pub fn config(&self, relative: &str) -> String {
+        format!("{}{}", self.config_path, relative)
+    }
+}
+
+fn with_trailing_slash(mut path: String) -> String {
+    if !path.ends_with('/') {
+        path.push('/');
+    }
+    path
+}
@@ -0,0 +1,153 @@
+//! Gravity zones change the gravity of the dynamic bodies inside them (low gravity room,
+//! updraft...). A zone is an entity with a `GravityZone` and a sensor `RigidBody`, so that the
+//! other bodies can go through it. The bodies outside of all the zones use the gravity of the
+//! physic world.
+//!
+//! nphysics clears the forces at each step so `apply_gravity_zones` needs to be called before
+//! every `PhysicWorld::step`.
+
+use super::{BodyIndex, PhysicWorld, RigidBody};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GravityZone {
+    /// Gravity of the bodies overlapping the zone. Zero to make them float.
+    pub gravity: glam::Vec3,
+    /// When a body is in several zones, the zone with the highest priority is used.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// Replace the world gravity by the gravity of the zone for each dynamic body that overlaps a
+/// zone. A body in several zones uses the one with the highest priority. Between zones of the
+/// same priority, the choice is arbitrary.
+pub fn apply_gravity_zones(world: &hecs::World, physics: &mut PhysicWorld) {
+    let mut zones: Vec<_> = world
+        .query::<(&GravityZone, &RigidBody)>()
+        .iter()
+        .filter_map(|(_, (zone, rb))| rb.handle.map(|h| (zone.priority, zone.gravity, h)))
+        .collect();
+    zones.sort_by_key(|(priority, _, _)| std::cmp::Reverse(*priority));
+
+    let world_gravity = physics.gravity();
+    let mut in_zone: HashSet<BodyIndex> = HashSet::new();
+    for (_, gravity, h) in zones {
+        let overlaps = match physics.overlaps(h, false, true, 0.0) {
+            Some(overlaps) => overlaps,
+            None => continue,
+        };
+
+        for (body, _, _) in overlaps {
+            if in_zone.insert(body) {
+                physics.add_acceleration(body, gravity - world_gravity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Transform;
+    use crate::event::GameEvent;
+    use crate::physics::{BodyType, PhysicConfig, Shape};
+    use shrev::EventChannel;
+
+    fn test_world() -> PhysicWorld {
+        let mut chan: EventChannel<GameEvent> = EventChannel::new();
+        let rdr_id = chan.register_reader();
+        PhysicWorld::from_config(
+            rdr_id,
+            PhysicConfig {
+                grav: -9.8,
+                friction: 0.0,
+            },
+        )
+    }
+
+    fn falling_body(physics: &mut PhysicWorld, position: glam::Vec3) -> BodyIndex {
+        let mut rb = RigidBody {
+            shape: Shape::AABB(glam::vec3(0.5, 0.5, 0.5)),
+            ty: BodyType::Dynamic,
+            max_linear_velocity: 100.0,
+            ..RigidBody::default()
+        };
+        let mut transform = Transform::default();
+        transform.translation = position;
+        physics.add_body(&transform, &mut rb)
+    }
+
+    #[test]
+    fn float_in_zero_gravity_zone() {
+        let mut world = hecs::World::new();
+        let mut physics = test_world();
+
+        let mut zone = RigidBody {
+            shape: Shape::AABB(glam::vec3(5.0, 5.0, 5.0)),
+            ty: BodyType::Static,
+            sensor: true,
+            ..RigidBody::default()
+        };
+        physics.add_body(&Transform::default(), &mut zone);
+        world.spawn((
+            zone,
+            GravityZone {
+                gravity: glam::Vec3::zero(),
+                priority: 0,
+            },
+        ));
+
+        let floating = falling_body(&mut physics, glam::Vec3::zero());
+        let falling = falling_body(&mut physics, glam::vec3(0.0, 0.0, 50.0));
+
+        // update the broad phase.
+        physics.step();
+        for _ in 0..60 {
+            apply_gravity_zones(&world, &mut physics);
+            physics.step();
+        }
+
+        // only the first step was done with the gravity.
+        let velocity = physics.get_linear_velocity(floating).unwrap();
+        assert!(velocity.length() < 0.5, "velocity = {:?}", velocity);
+        assert!(physics.get_position(floating).unwrap().y() > -1.0);
+
+        assert!(physics.get_linear_velocity(falling).unwrap().y() < -5.0);
+    }
+
+    #[test]
+    fn use_the_zone_with_the_highest_priority() {
+        let mut world = hecs::World::new();
+        let mut physics = test_world();
+
+        for (gravity, priority) in &[(glam::vec3(0.0, 9.8, 0.0), 0), (glam::Vec3::zero(), 1)] {
+            let mut zone = RigidBody {
+                shape: Shape::AABB(glam::vec3(5.0, 5.0, 5.0)),
+                ty: BodyType::Static,
+                sensor: true,
+                ..RigidBody::default()
+            };
+            physics.add_body(&Transform::default(), &mut zone);
+            world.spawn((
+                zone,
+                GravityZone {
+                    gravity: *gravity,
+                    priority: *priority,
+                },
+            ));
+        }
+
+        let floating = falling_body(&mut physics, glam::Vec3::zero());
+
+        physics.step();
+        for _ in 0..60 {
+            apply_gravity_zones(&world, &mut physics);
+            physics.step();
+        }
+
+        // The zone that pushes up has the lowest priority so the body floats.
+        let velocity = physics.get_linear_velocity(floating).unwrap();
+        assert!(velocity.length() < 0.5, "velocity = {:?}", velocity);
+    }
+}
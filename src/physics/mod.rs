@@ -1,6 +1,6 @@
 use hecs::Entity;
 #[allow(unused_imports)]
-use log::{debug, info, trace};
+use log::{debug, info, trace, warn};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 extern crate nalgebra as na;
@@ -9,15 +9,18 @@ use crate::ecs::Transform;
 use nalgebra::{Isometry3, UnitQuaternion};
 
 use crate::event::GameEvent;
+use crate::paths::Paths;
 use crate::resources::Resources;
 use na::Point3;
 use na::Vector3;
-use ncollide3d::pipeline::CollisionGroups;
+use ncollide3d::bounding_volume::AABB;
+use ncollide3d::pipeline::{CollisionGroups, ContactEvent};
 use ncollide3d::query::Ray;
 use ncollide3d::shape::{Cuboid, ShapeHandle};
-use nphysics3d::algebra::{Force3, ForceType};
+use nphysics3d::algebra::{Force3, ForceType, Velocity3};
 use nphysics3d::force_generator::DefaultForceGeneratorSet;
 use nphysics3d::joint::DefaultJointConstraintSet;
+use nphysics3d::material::{BasicMaterial, MaterialHandle};
 use nphysics3d::object::{
     BodyPartHandle, BodyStatus, ColliderDesc, DefaultBodyHandle, DefaultBodySet,
     DefaultColliderHandle, DefaultColliderSet, RigidBodyDesc,
@@ -26,6 +29,8 @@ use nphysics3d::world::{DefaultGeometricalWorld, DefaultMechanicalWorld};
 use shrev::{EventChannel, ReaderId};
 use std::fs;
 
+pub mod gravity_zone;
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
 pub enum Shape {
     // half-width. Center of box is position of rigidbody.
@@ -42,6 +47,7 @@ pub enum BodyType {
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
 struct PhysicConfig {
     grav: f32,
+    /// Friction of the bodies that do not have their own. 0.5 is the nphysics default.
     friction: f32,
 }
 
@@ -50,12 +56,32 @@ pub struct RigidBody {
     pub mass: f32,
     pub shape: Shape,
     pub ty: BodyType,
+    /// Maximum speed of the body. nphysics clamps the velocity to this value at each step and
+    /// `PhysicWorld::add_velocity_change` won't apply any change when the body is at this speed.
     #[serde(default)]
     pub max_linear_velocity: f32,
     #[serde(default)]
     pub max_angular_velocity: f32,
     #[serde(default)]
     pub linear_damping: f32,
+    /// Enable continuous collision detection. Fast bodies (projectiles...) need it to avoid going
+    /// through thin walls. Off by default as it is more expensive.
+    #[serde(default)]
+    pub ccd: bool,
+    /// Friction of the collider surface (low for ice, high for rubber). When not set, the
+    /// global friction from `physic.ron` is used.
+    #[serde(default)]
+    pub friction: Option<f32>,
+
+    /// Sensors detect the bodies inside them but do not block them (trigger volumes, gravity
+    /// zones...). They are ignored by the raycasts and the contact queries of the other bodies.
+    #[serde(default)]
+    pub sensor: bool,
+
+    /// The body is not pulled down by the gravity of the world (rockets...). The gravity zones
+    /// still add their difference with the world gravity.
+    #[serde(default)]
+    pub disable_gravity: bool,
 
     #[serde(skip)]
     pub handle: Option<BodyIndex>,
@@ -70,10 +96,65 @@ impl Default for RigidBody {
             max_angular_velocity: 0.0,
             max_linear_velocity: 0.0,
             linear_damping: 0.0,
+            ccd: false,
+            friction: None,
+            sensor: false,
+            disable_gravity: false,
             handle: None,
         }
     }
 }
+/// Hits closer than that to the target of a line of sight query do not block the view.
+const LINE_OF_SIGHT_TOLERANCE: f32 = 1e-4;
+
+/// Maximum distance of `PhysicWorld::raycast`. Should be larger than the maps.
+pub const DEFAULT_RAYCAST_DISTANCE: f32 = 1000.0;
+
+/// Smallest half extent of a shape. nphysics does not like bodies without volume.
+const MIN_HALF_EXTENT: f32 = 0.01;
+
+impl RigidBody {
+    /// Fix the values that would make the simulation misbehave (a dynamic body without mass, a
+    /// shape with negative or zero extents...). They can come from a hand-written map so a
+    /// warning is logged instead of crashing. Return true if something was changed.
+    pub fn sanitize(&mut self) -> bool {
+        let mut changed = false;
+
+        if self.ty == BodyType::Dynamic && !(self.mass.is_finite() && self.mass > 0.0) {
+            warn!(
+                "Dynamic body has an invalid mass ({}), will use 1.0 instead",
+                self.mass
+            );
+            self.mass = 1.0;
+            changed = true;
+        }
+
+        let Shape::AABB(half_extents) = self.shape;
+        let fix = |v: f32| {
+            if v.is_finite() {
+                v.abs().max(MIN_HALF_EXTENT)
+            } else {
+                MIN_HALF_EXTENT
+            }
+        };
+        let sanitized = glam::vec3(
+            fix(half_extents.x()),
+            fix(half_extents.y()),
+            fix(half_extents.z()),
+        );
+        if sanitized != half_extents {
+            warn!(
+                "Body has invalid half extents {:?}, will use {:?} instead",
+                half_extents, sanitized
+            );
+            self.shape = Shape::AABB(sanitized);
+            changed = true;
+        }
+
+        changed
+    }
+}
+
 #[derive(Default)]
 pub struct BodyToEntity(HashMap<BodyIndex, Entity>);
 
@@ -94,6 +175,37 @@ impl BodyToEntity {
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
 pub struct BodyIndex(DefaultBodyHandle, DefaultColliderHandle);
 
+/// Result of a raycast against a collider.
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+    /// Time of impact, in units of the ray direction.
+    pub toi: f32,
+    /// Body (and collider) that was hit. The collider tells which part of the body was hit.
+    pub body: BodyIndex,
+    /// World position of the intersection.
+    pub point: glam::Vec3,
+    /// Normal of the collider surface at the intersection.
+    pub normal: glam::Vec3,
+}
+
+/// Some counters about the physic world. Useful to check that bodies/colliders are not leaking
+/// and to debug the simulation.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PhysicsStats {
+    /// Number of time `step` was called since the world was created.
+    pub steps: usize,
+    /// Number of substeps run during the last step (only > 0 when CCD kicks in).
+    pub substeps: usize,
+    /// Number of bodies in the world.
+    pub bodies: usize,
+    /// Number of bodies that are not sleeping.
+    pub active_bodies: usize,
+    /// Number of colliders in the world.
+    pub colliders: usize,
+    /// Number of contacts pair with at least one contact point.
+    pub contacts: usize,
+}
+
 pub struct PhysicWorld {
     mechanical_world: DefaultMechanicalWorld<f32>,
     geometrical_world: DefaultGeometricalWorld<f32>,
@@ -104,18 +216,30 @@ pub struct PhysicWorld {
     //force_generators: DefaultForceGeneratorSet<f32, DefaultBodySet<f32>>,
     //ground_handle: BodyIndex,
     rdr_id: ReaderId<GameEvent>,
+
+    /// Number of time the world has been stepped.
+    steps: usize,
+
+    /// Friction used when the rigid body does not specify one.
+    default_friction: f32,
 }
 
 impl PhysicWorld {
     pub fn new(resources: &mut Resources) -> Self {
         let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
         let rdr_id = chan.register_reader();
+        let paths = resources.fetch::<Paths>().unwrap();
 
-        let conf_str =
-            fs::read_to_string(std::env::var("CONFIG_PATH").unwrap() + "physic.ron").unwrap();
+        let conf_str = fs::read_to_string(paths.config("physic.ron")).unwrap();
         let conf: PhysicConfig = ron::de::from_str(&conf_str).unwrap();
 
-        let mechanical_world = DefaultMechanicalWorld::new(Vector3::new(0., conf.grav, 0.));
+        Self::from_config(rdr_id, conf)
+    }
+
+    fn from_config(rdr_id: ReaderId<GameEvent>, conf: PhysicConfig) -> Self {
+        let mut mechanical_world = DefaultMechanicalWorld::new(Vector3::new(0., conf.grav, 0.));
+        // needed to get the number of substeps in the stats.
+        mechanical_world.counters.enable();
         let geometrical_world = DefaultGeometricalWorld::new();
 
         let bodies = DefaultBodySet::new();
@@ -129,6 +253,8 @@ impl PhysicWorld {
             bodies,
             colliders,
             rdr_id,
+            steps: 0,
+            default_friction: conf.friction,
             //joint_constraints,
             //force_generators,
         }
@@ -141,20 +267,19 @@ impl PhysicWorld {
         let chan = resources.fetch::<EventChannel<GameEvent>>().unwrap();
         for ev in chan.read(&mut self.rdr_id) {
             if let GameEvent::RbUpdate(e) = ev {
-                if let (Ok(t), Ok(mut rb)) =
-                    (world.get::<Transform>(*e), world.get_mut::<RigidBody>(*e))
-                {
-                    let mut body_to_entity = resources.fetch_mut::<BodyToEntity>().unwrap();
-
-                    if let Some(h) = rb.handle {
-                        body_to_entity.remove(&h);
-                    }
-                    self.update_rigidbody_component(&t, &mut rb);
-                }
+                let mut body_to_entity = resources.fetch_mut::<BodyToEntity>().unwrap();
+                self.register_entity(world, &mut body_to_entity, *e);
+                // the body can be somewhere else now.
+                crate::ecs::reset_previous_transforms(world, *e);
             }
         }
     }
 
+    /// Duration of a call to `step`, in seconds.
+    pub fn timestep(&self) -> f32 {
+        self.mechanical_world.timestep()
+    }
+
     pub fn step(&mut self) {
         // FIXME figure that out. needs to be stored in the world...
         let mut joint = DefaultJointConstraintSet::new();
@@ -167,9 +292,71 @@ impl PhysicWorld {
             &mut joint,
             &mut force_generators,
         );
+        self.steps += 1;
+    }
+
+    /// Send a `GameEvent::Collision` for each contact that started during the last step. Needs
+    /// to be called after `step`, before the next one.
+    pub fn emit_contact_events(&self, resources: &Resources) {
+        let body_to_entity = resources.fetch::<BodyToEntity>().unwrap();
+        let mut events = vec![];
+        for ev in self.geometrical_world.contact_events() {
+            if let ContactEvent::Started(h1, h2) = ev {
+                let entities = (
+                    self.collider_to_entity(*h1, &body_to_entity),
+                    self.collider_to_entity(*h2, &body_to_entity),
+                );
+                let contact = self
+                    .geometrical_world
+                    .contact_pair(&self.colliders, *h1, *h2, true)
+                    .and_then(|(_, _, _, _, _, manifold)| manifold.deepest_contact())
+                    .map(|c| (c.contact.normal, c.contact.depth));
+
+                if let ((Some(a), Some(b)), Some((normal, depth))) = (entities, contact) {
+                    events.push(GameEvent::Collision {
+                        a,
+                        b,
+                        normal: glam::vec3(normal.x, normal.y, normal.z),
+                        depth,
+                    });
+                }
+            }
+        }
+
+        if !events.is_empty() {
+            let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
+            chan.drain_vec_write(&mut events);
+        }
+    }
+
+    fn collider_to_entity(
+        &self,
+        h: DefaultColliderHandle,
+        body_to_entity: &BodyToEntity,
+    ) -> Option<Entity> {
+        self.colliders
+            .get(h)
+            .and_then(|c| body_to_entity.get(&BodyIndex(c.body(), h)))
+            .copied()
+    }
+
+    /// Return some counters about the current state of the physic world.
+    pub fn stats(&self) -> PhysicsStats {
+        PhysicsStats {
+            steps: self.steps,
+            substeps: self.mechanical_world.counters.ccd.num_substeps,
+            bodies: self.bodies.iter().count(),
+            active_bodies: self.bodies.iter().filter(|(_, b)| b.is_active()).count(),
+            colliders: self.colliders.iter().count(),
+            contacts: self
+                .geometrical_world
+                .contact_pairs(&self.colliders, true)
+                .count(),
+        }
     }
 
     pub fn add_body(&mut self, transform: &Transform, body_component: &mut RigidBody) -> BodyIndex {
+        body_component.sanitize();
         // Shape is a cuboid :) for now TODO modify that
         info!("Will add body to physic world = {:?}", body_component);
         let shape_handle: ShapeHandle<f32> = match body_component.shape {
@@ -183,6 +370,7 @@ impl PhysicWorld {
             .position(transform.to_isometry())
             .set_max_angular_velocity(body_component.max_angular_velocity)
             .set_max_linear_velocity(body_component.max_linear_velocity)
+            .set_gravity_enabled(!body_component.disable_gravity)
             .set_status(match body_component.ty {
                 BodyType::Static => BodyStatus::Static,
                 BodyType::Dynamic => BodyStatus::Dynamic,
@@ -193,8 +381,12 @@ impl PhysicWorld {
         let rb_handle = self.bodies.insert(rb);
 
         // Build the collider.
+        let friction = body_component.friction.unwrap_or(self.default_friction);
         let co = ColliderDesc::new(shape_handle)
             .density(1.0)
+            .material(MaterialHandle::new(BasicMaterial::new(0.0, friction)))
+            .ccd_enabled(body_component.ccd)
+            .sensor(body_component.sensor)
             .build(BodyPartHandle(rb_handle, 0));
         // Insert the collider to the body set.
         let collider_handle = self.colliders.insert(co);
@@ -202,22 +394,44 @@ impl PhysicWorld {
         BodyIndex(rb_handle, collider_handle)
     }
 
-    /// call that when the component has been updated and the physics system needs to be synchronised.
-    /// Not the most beautiful :D
-    pub fn update_rigidbody_component(
+    pub fn remove_body(&mut self, h: BodyIndex) {
+        self.bodies.remove(h.0);
+        self.colliders.remove(h.1);
+    }
+
+    /// Add the body of an entity (from its `RigidBody` and `Transform` components) to the
+    /// physic world. If the entity already has a body, it is removed first so that an entity
+    /// never has more than one body. The new handle is stored in the component and in
+    /// `BodyToEntity`.
+    pub fn register_entity(
         &mut self,
-        transform: &Transform,
-        body_component: &mut RigidBody,
-    ) -> BodyIndex {
-        if let Some(h) = body_component.handle {
-            self.remove_body(h);
-        }
-        self.add_body(transform, body_component)
+        world: &hecs::World,
+        body_to_entity: &mut BodyToEntity,
+        e: Entity,
+    ) -> Option<BodyIndex> {
+        self.unregister_entity(world, body_to_entity, e);
+
+        let transform = world.get::<Transform>(e).ok()?;
+        let mut rb = world.get_mut::<RigidBody>(e).ok()?;
+        let h = self.add_body(&transform, &mut rb);
+        body_to_entity.insert(h, e);
+        Some(h)
     }
 
-    pub fn remove_body(&mut self, h: BodyIndex) {
-        self.bodies.remove(h.0);
-        // TODO check if need to remove collider.
+    /// Remove the body of an entity from the physic world, if it has one. The entity keeps its
+    /// `RigidBody` component so that it can be registered again later.
+    pub fn unregister_entity(
+        &mut self,
+        world: &hecs::World,
+        body_to_entity: &mut BodyToEntity,
+        e: Entity,
+    ) {
+        if let Ok(mut rb) = world.get_mut::<RigidBody>(e) {
+            if let Some(h) = rb.handle.take() {
+                self.remove_body(h);
+                body_to_entity.remove(&h);
+            }
+        }
     }
 
     pub fn get_pos(&self, body_index: BodyIndex) -> Option<glam::Vec3> {
@@ -257,26 +471,50 @@ impl PhysicWorld {
     }
 
     /// Directly add a velocity change :) instead of using an acceleration
+    ///
+    /// The velocity change is not applied if the body is already at its `max_linear_velocity`
+    /// (from the `RigidBody` component). nphysics also clamps the velocity to this value during
+    /// the integration so the body will never go faster than that, but skipping the force here
+    /// avoids accumulating velocity changes that will be discarded anyway.
     pub fn add_velocity_change(&mut self, h: BodyIndex, force: glam::Vec3) {
-        if let Some(body) = self.bodies.get_mut(h.0) {
-            let current_speed = body.part(0).map(|part| part.velocity().linear.magnitude());
-
-            if let Some(speed) = current_speed {
-                if speed < 20.0 {
-                    body.apply_force(
-                        0,
-                        &Force3::new(
-                            Vector3::new(force.x(), force.y(), force.z()),
-                            Vector3::new(0., 0., 0.),
-                        ),
-                        ForceType::VelocityChange,
-                        true,
-                    );
-                }
+        if let Some(rb) = self.bodies.rigid_body_mut(h.0) {
+            let speed = rb.velocity().linear.magnitude();
+            if speed < rb.max_linear_velocity() {
+                rb.apply_force(
+                    0,
+                    &Force3::new(
+                        Vector3::new(force.x(), force.y(), force.z()),
+                        Vector3::new(0., 0., 0.),
+                    ),
+                    ForceType::VelocityChange,
+                    true,
+                );
             }
         }
     }
 
+    /// Add an acceleration to the body for the next step, on top of the gravity. Unlike
+    /// `add_velocity_change`, it does not depend on the mass nor on the max velocity.
+    pub fn add_acceleration(&mut self, h: BodyIndex, acceleration: glam::Vec3) {
+        if let Some(rb) = self.bodies.rigid_body_mut(h.0) {
+            rb.apply_force(
+                0,
+                &Force3::new(
+                    Vector3::new(acceleration.x(), acceleration.y(), acceleration.z()),
+                    Vector3::new(0., 0., 0.),
+                ),
+                ForceType::AccelerationChange,
+                true,
+            );
+        }
+    }
+
+    /// Gravity applied to the dynamic bodies outside of the gravity zones.
+    pub fn gravity(&self) -> glam::Vec3 {
+        let g = self.mechanical_world.gravity;
+        glam::vec3(g.x, g.y, g.z)
+    }
+
     pub fn set_linear_velocity(&mut self, h: BodyIndex, new_velocity: glam::Vec3) {
         if let Some(rb) = self.bodies.rigid_body_mut(h.0) {
             rb.set_linear_velocity(Vector3::new(
@@ -295,8 +533,8 @@ impl PhysicWorld {
             rb.set_position(pos);
         }
     }
-    pub fn get_linear_velocity(&mut self, h: BodyIndex) -> Option<glam::Vec3> {
-        if let Some(rb) = self.bodies.rigid_body_mut(h.0) {
+    pub fn get_linear_velocity(&self, h: BodyIndex) -> Option<glam::Vec3> {
+        if let Some(rb) = self.bodies.rigid_body(h.0) {
             let v = rb.velocity().linear;
             Some(glam::vec3(v.x, v.y, v.z))
         } else {
@@ -321,12 +559,22 @@ impl PhysicWorld {
             ));
         }
     }
-    // Set the friction. That's necessary to avoid sliding during movemnet. when the player is walking,
-    // friction is high. When jumping it is a bit lower.
-    pub fn set_friction(&mut self, h: BodyIndex, friction: f32) {
+
+    /// Move the body to a new position and reset its velocity, otherwise the body will keep the
+    /// speed it had before being moved. The respawn registers a new body instead.
+    pub fn teleport(&mut self, h: BodyIndex, new_position: glam::Vec3) {
+        self.set_position(h, new_position);
+        if let Some(rb) = self.bodies.rigid_body_mut(h.0) {
+            rb.set_velocity(Velocity3::zero());
+        }
+    }
+
+    // Set the linear damping. That's necessary to avoid sliding during movemnet. when the player is walking,
+    // damping is high. When jumping it is a bit lower.
+    pub fn set_linear_damping(&mut self, h: BodyIndex, damping: f32) {
         if let Some(body) = self.bodies.get_mut(h.0) {
             if let Some(rb) = body.downcast_mut::<nphysics3d::object::RigidBody<f32>>() {
-                rb.set_linear_damping(friction);
+                rb.set_linear_damping(damping);
             }
         }
     }
@@ -347,64 +595,188 @@ impl PhysicWorld {
         }
     }
 
+    /// Contacts between the body and the static bodies, with a prediction of 1.0. Return the
+    /// contact normal and depth.
     pub fn contact_with(&self, h: BodyIndex) -> Option<Vec<(glam::Vec3, f32)>> {
-        if let Some(coll) = self.colliders.get(h.1) {
-            let body = self.bodies.rigid_body(coll.body()).unwrap();
-            let shape = coll.shape().aabb(&body.position());
-            Some(
-                self.geometrical_world
-                    .interferences_with_aabb(&self.colliders, &shape, &CollisionGroups::default())
-                    .filter(|(c, _)| *c != h.1)
-                    .filter(|(_, obj)| self.bodies.get(obj.body()).unwrap().is_static())
-                    .filter_map(|(_, obj)| {
-                        ncollide3d::query::contact(
-                            &body.position(),
-                            coll.shape(),
-                            obj.position(),
-                            obj.shape(),
-                            1.0,
-                        )
-                    })
-                    .map(|contact| {
-                        (
-                            glam::vec3(contact.normal.x, contact.normal.y, contact.normal.z),
-                            contact.depth,
-                        )
-                    })
-                    .collect(),
-            )
-        } else {
-            None
-        }
+        self.overlaps(h, true, false, 1.0).map(|overlaps| {
+            overlaps
+                .into_iter()
+                .map(|(_, normal, depth)| (normal, depth))
+                .collect()
+        })
     }
 
+    /// Bodies in contact with the given body, with the contact normal and depth. Kinematic
+    /// bodies are counted as dynamic. The candidates are the bodies whose bounding box
+    /// intersects the bounding box of `h`; `margin` is the distance under which two of them
+    /// are considered in contact (the depth is then negative). Sensors are never returned, even
+    /// when `h` is itself a sensor (see `gravity_zone`). None if the body does not exist.
+    pub fn overlaps(
+        &self,
+        h: BodyIndex,
+        include_static: bool,
+        include_dynamic: bool,
+        margin: f32,
+    ) -> Option<Vec<(BodyIndex, glam::Vec3, f32)>> {
+        let coll = self.colliders.get(h.1)?;
+        let body = self.bodies.rigid_body(coll.body())?;
+        let shape = coll.shape().aabb(&body.position());
+        Some(
+            self.geometrical_world
+                .interferences_with_aabb(&self.colliders, &shape, &CollisionGroups::default())
+                .filter(|(c, obj)| *c != h.1 && !obj.is_sensor())
+                .filter(|(_, obj)| {
+                    let is_static = self.bodies.get(obj.body()).unwrap().is_static();
+                    if is_static {
+                        include_static
+                    } else {
+                        include_dynamic
+                    }
+                })
+                .filter_map(|(c, obj)| {
+                    ncollide3d::query::contact(
+                        &body.position(),
+                        coll.shape(),
+                        obj.position(),
+                        obj.shape(),
+                        margin,
+                    )
+                    .map(|contact| (BodyIndex(obj.body(), c), contact))
+                })
+                .map(|(other, contact)| {
+                    (
+                        other,
+                        glam::vec3(contact.normal.x, contact.normal.y, contact.normal.z),
+                        contact.depth,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Bodies hit by the ray, except `h` and the sensors, up to `DEFAULT_RAYCAST_DISTANCE`. See
+    /// `raycast_with_max_distance`.
     pub fn raycast(
         &self,
         h: BodyIndex,
         center_offset: glam::Vec3,
         d: glam::Vec3,
-    ) -> Vec<(f32, BodyIndex)> {
+    ) -> Vec<RaycastHit> {
+        self.raycast_with_max_distance(h, center_offset, d, DEFAULT_RAYCAST_DISTANCE)
+    }
+
+    /// Bodies hit by the ray that starts at `center_offset` and goes along `d`, except `h`.
+    /// Nothing further than `max_distance` is returned. Sensors are not solid so the ray goes
+    /// through them. The time of impact of the hits is in units of `d`, not of distance. They
+    /// are not sorted.
+    pub fn raycast_with_max_distance(
+        &self,
+        h: BodyIndex,
+        center_offset: glam::Vec3,
+        d: glam::Vec3,
+        max_distance: f32,
+    ) -> Vec<RaycastHit> {
+        self.raycast_except(&[h], center_offset, d, max_distance)
+    }
+
+    /// Same as `raycast_with_max_distance`, but all the bodies in `ignore` are skipped.
+    fn raycast_except(
+        &self,
+        ignore: &[BodyIndex],
+        center_offset: glam::Vec3,
+        d: glam::Vec3,
+        max_distance: f32,
+    ) -> Vec<RaycastHit> {
+        let length = d.length();
+        if length < std::f32::EPSILON {
+            return vec![];
+        }
         let groups = CollisionGroups::default();
 
         let ray = Ray::new(
             Point3::new(center_offset.x(), center_offset.y(), center_offset.z()),
             Vector3::new(d.x(), d.y(), d.z()),
         );
-        // FIXME have a nice value for max toi.
-        let interference =
-            self.geometrical_world
-                .interferences_with_ray(&self.colliders, &ray, 1000.0, &groups);
+        let interference = self.geometrical_world.interferences_with_ray(
+            &self.colliders,
+            &ray,
+            max_distance / length,
+            &groups,
+        );
         // (Objects::CollisionObjectHandle, &'a Objects::CollisionObject, RayIntersection<N>)
         let mut results = vec![];
         for (a, b, c) in interference {
             let body_handle = b.body();
-            if body_handle != h.0 {
-                results.push((c.toi, BodyIndex(body_handle, a)));
+            if ignore.iter().all(|h| h.0 != body_handle) && !b.is_sensor() {
+                let point = ray.point_at(c.toi);
+                results.push(RaycastHit {
+                    toi: c.toi,
+                    body: BodyIndex(body_handle, a),
+                    point: glam::vec3(point.x, point.y, point.z),
+                    normal: glam::vec3(c.normal.x, c.normal.y, c.normal.z),
+                });
             }
         }
         results
     }
 
+    /// True if nothing blocks the segment between `from` and `to`. The bodies in `ignore`
+    /// (usually the one that is looking and the one that is looked at) are not taken into
+    /// account.
+    pub fn has_line_of_sight(
+        &self,
+        from: glam::Vec3,
+        to: glam::Vec3,
+        ignore: &[BodyIndex],
+    ) -> bool {
+        let direction = to - from;
+        if direction.length_squared() < std::f32::EPSILON {
+            return true;
+        }
+
+        // toi is in units of direction, so the target is at toi = 1.
+        self.raycast_except(ignore, from, direction, direction.length())
+            .iter()
+            .all(|hit| hit.toi >= 1.0 - LINE_OF_SIGHT_TOLERANCE)
+    }
+
+    /// All the entities whose position is within `radius` of `center`, with their distance to
+    /// the center. The broad phase is used to find the candidates so the bodies far away are not
+    /// checked. Closest entities first.
+    pub fn entities_in_radius(
+        &self,
+        world: &hecs::World,
+        body_to_entity: &BodyToEntity,
+        center: glam::Vec3,
+        radius: f32,
+    ) -> Vec<(Entity, f32)> {
+        let half_extents = Vector3::repeat(radius);
+        let center_point = Point3::new(center.x(), center.y(), center.z());
+        let aabb = AABB::new(center_point - half_extents, center_point + half_extents);
+
+        let mut entities: Vec<(Entity, f32)> = self
+            .geometrical_world
+            .interferences_with_aabb(&self.colliders, &aabb, &CollisionGroups::default())
+            .filter_map(|(h, collider)| body_to_entity.get(&BodyIndex(collider.body(), h)))
+            .filter_map(|e| {
+                world
+                    .get::<Transform>(*e)
+                    .ok()
+                    .map(|t| (*e, (t.translation - center).length()))
+            })
+            .filter(|(_, distance)| *distance <= radius)
+            .collect();
+
+        entities.sort_by(|(e1, d1), (e2, d2)| {
+            d1.partial_cmp(d2)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(e1.to_bits().cmp(&e2.to_bits()))
+        });
+        // a body can have several colliders.
+        entities.dedup_by_key(|(e, _)| *e);
+        entities
+    }
+
     /// Check if the AABBs of the two bodies are overlapping. If yes, return true, else return
     /// false. If body index is not in physics world, return false.
     pub fn check_aabb_collision(&self, a: BodyIndex, b: BodyIndex) -> bool {
@@ -413,3 +785,380 @@ impl PhysicWorld {
             .is_some()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_world() -> PhysicWorld {
+        let mut chan: EventChannel<GameEvent> = EventChannel::new();
+        let rdr_id = chan.register_reader();
+        PhysicWorld::from_config(
+            rdr_id,
+            PhysicConfig {
+                grav: -9.8,
+                friction: 0.0,
+            },
+        )
+    }
+
+    #[test]
+    fn teleport_reset_velocity() {
+        let mut physics = test_world();
+        let mut rb = RigidBody {
+            ty: BodyType::Dynamic,
+            max_linear_velocity: 100.0,
+            ..RigidBody::default()
+        };
+        let h = physics.add_body(&Transform::default(), &mut rb);
+
+        // fall for a while.
+        for _ in 0..30 {
+            physics.step();
+        }
+        assert!(physics.get_linear_velocity(h).unwrap().length() > 0.0);
+
+        physics.teleport(h, glam::vec3(0.0, 10.0, 0.0));
+        assert_eq!(glam::Vec3::zero(), physics.get_linear_velocity(h).unwrap());
+        assert_eq!(glam::vec3(0.0, 10.0, 0.0), physics.get_position(h).unwrap());
+        let rb = physics.bodies.rigid_body(h.0).unwrap();
+        assert_eq!(0.0, rb.velocity().angular.norm());
+    }
+
+    #[test]
+    fn rb_update_keeps_the_entity() {
+        let mut resources = Resources::new();
+        let mut chan: EventChannel<GameEvent> = EventChannel::new();
+        let rdr_id = chan.register_reader();
+        resources.insert(chan);
+        resources.insert(BodyToEntity::default());
+        let mut physics = PhysicWorld::from_config(
+            rdr_id,
+            PhysicConfig {
+                grav: -9.8,
+                friction: 0.0,
+            },
+        );
+
+        let mut world = hecs::World::new();
+        let e = world.spawn((Transform::default(), RigidBody::default()));
+        crate::ecs::save_previous_transforms(&mut world);
+        physics.register_entity(
+            &world,
+            &mut resources.fetch_mut::<BodyToEntity>().unwrap(),
+            e,
+        );
+        let old_h = world.get::<RigidBody>(e).unwrap().handle.unwrap();
+
+        resources
+            .fetch_mut::<EventChannel<GameEvent>>()
+            .unwrap()
+            .single_write(GameEvent::RbUpdate(e));
+        physics.process_events(&mut world, &resources);
+
+        let h = world.get::<RigidBody>(e).unwrap().handle.unwrap();
+        let body_to_entity = resources.fetch::<BodyToEntity>().unwrap();
+        assert_eq!(Some(&e), body_to_entity.get(&h));
+        assert_eq!(None, body_to_entity.get(&old_h));
+        // not interpolated from the old position.
+        assert!(world.get::<crate::ecs::PreviousTransform>(e).is_err());
+        assert_eq!(1, physics.stats().bodies);
+    }
+
+    fn friction(physics: &PhysicWorld, h: BodyIndex) -> f32 {
+        physics
+            .colliders
+            .get(h.1)
+            .unwrap()
+            .material()
+            .downcast_ref::<BasicMaterial<f32>>()
+            .unwrap()
+            .friction
+    }
+
+    #[test]
+    fn body_friction() {
+        let mut resources = Resources::new();
+        resources.insert(EventChannel::<GameEvent>::new());
+        resources.insert(Paths::new("assets/", "config/"));
+        let mut physics = PhysicWorld::new(&mut resources);
+
+        let mut default = RigidBody::default();
+        let default = physics.add_body(&Transform::default(), &mut default);
+        let mut ice = RigidBody {
+            friction: Some(0.05),
+            ..RigidBody::default()
+        };
+        let ice = physics.add_body(&Transform::default(), &mut ice);
+
+        // same as before the friction could be configured.
+        assert_eq!(0.5, friction(&physics, default));
+        assert_eq!(0.05, friction(&physics, ice));
+    }
+
+    #[test]
+    fn velocity_change_respect_max_velocity() {
+        let mut physics = test_world();
+        let mut slow = RigidBody {
+            ty: BodyType::Dynamic,
+            max_linear_velocity: 1.0,
+            ..RigidBody::default()
+        };
+        let mut fast = RigidBody {
+            ty: BodyType::Dynamic,
+            max_linear_velocity: 100.0,
+            ..RigidBody::default()
+        };
+        let mut far_away = Transform::default();
+        far_away.translation = glam::vec3(0.0, 0.0, 50.0);
+        let slow = physics.add_body(&Transform::default(), &mut slow);
+        let fast = physics.add_body(&far_away, &mut fast);
+
+        for _ in 0..10 {
+            physics.add_velocity_change(slow, glam::vec3(5.0, 0.0, 0.0));
+            physics.add_velocity_change(fast, glam::vec3(5.0, 0.0, 0.0));
+            physics.step();
+        }
+
+        assert!(physics.get_linear_velocity(slow).unwrap().length() <= 1.0 + 0.0001);
+        assert!(physics.get_linear_velocity(fast).unwrap().length() > 20.0);
+    }
+
+    fn fire_at_wall(ccd: bool) -> f32 {
+        let mut physics = test_world();
+        let mut wall = RigidBody {
+            shape: Shape::AABB(glam::vec3(0.05, 10.0, 10.0)),
+            ty: BodyType::Static,
+            ..RigidBody::default()
+        };
+        let mut wall_transform = Transform::default();
+        wall_transform.translation = glam::vec3(5.0, 0.0, 0.0);
+        physics.add_body(&wall_transform, &mut wall);
+
+        let mut bullet = RigidBody {
+            shape: Shape::AABB(glam::vec3(0.1, 0.1, 0.1)),
+            ty: BodyType::Dynamic,
+            max_linear_velocity: 1000.0,
+            ccd,
+            ..RigidBody::default()
+        };
+        let bullet = physics.add_body(&Transform::default(), &mut bullet);
+        physics.set_linear_velocity(bullet, glam::vec3(500.0, 0.0, 0.0));
+
+        for _ in 0..10 {
+            physics.step();
+        }
+
+        physics.get_position(bullet).unwrap().x()
+    }
+
+    #[test]
+    fn ccd_stops_fast_body() {
+        assert!(fire_at_wall(true) < 5.0);
+    }
+
+    #[test]
+    fn register_entity_twice_keeps_one_body() {
+        let mut physics = test_world();
+        let mut world = hecs::World::new();
+        let mut body_to_entity = BodyToEntity::default();
+        let e = world.spawn((Transform::default(), RigidBody::default()));
+
+        let mut previous = None;
+        for _ in 0..2 {
+            let h = physics
+                .register_entity(&world, &mut body_to_entity, e)
+                .unwrap();
+            physics.step();
+            assert_ne!(previous, Some(h));
+            assert_eq!(Some(h), world.get::<RigidBody>(e).unwrap().handle);
+            assert_eq!(Some(&e), body_to_entity.get(&h));
+            assert_eq!(1, physics.stats().bodies);
+            assert_eq!(1, physics.stats().colliders);
+
+            physics.unregister_entity(&world, &mut body_to_entity, e);
+            physics.step();
+            assert!(world.get::<RigidBody>(e).unwrap().handle.is_none());
+            assert!(body_to_entity.get(&h).is_none());
+            assert_eq!(0, physics.stats().bodies);
+            assert_eq!(0, physics.stats().colliders);
+            previous = Some(h);
+        }
+
+        // registering an entity that already has a body replaces it.
+        let first = physics.register_entity(&world, &mut body_to_entity, e);
+        let second = physics.register_entity(&world, &mut body_to_entity, e);
+        assert_ne!(first, second);
+        assert!(body_to_entity.get(&first.unwrap()).is_none());
+        assert_eq!(1, physics.stats().bodies);
+        assert_eq!(1, physics.stats().colliders);
+    }
+
+    #[test]
+    fn entities_in_radius_sorted_by_distance() {
+        let mut physics = test_world();
+        let mut world = hecs::World::new();
+        let mut body_to_entity = BodyToEntity::default();
+        for x in &[10.0, 3.0, 0.0, -4.0] {
+            let mut t = Transform::default();
+            t.translation = glam::vec3(*x, 0.0, 0.0);
+            let mut rb = RigidBody::default();
+            let h = physics.add_body(&t, &mut rb);
+            let e = world.spawn((t,));
+            body_to_entity.insert(h, e);
+        }
+        // update the broad phase.
+        physics.step();
+
+        let found = physics.entities_in_radius(&world, &body_to_entity, glam::Vec3::zero(), 5.0);
+        let distances: Vec<f32> = found.iter().map(|(_, d)| *d).collect();
+        assert_eq!(vec![0.0, 3.0, 4.0], distances);
+        for (e, _) in found {
+            assert!(world.get::<Transform>(e).unwrap().translation.x() < 10.0);
+        }
+    }
+
+    #[test]
+    fn wall_blocks_line_of_sight() {
+        let mut physics = test_world();
+        let mut looker = RigidBody::default();
+        let looker = physics.add_body(&Transform::default(), &mut looker);
+
+        // wall in the plane z = 5, from x = -1 to x = 1.
+        let mut wall = RigidBody {
+            shape: Shape::AABB(glam::vec3(1.0, 1.0, 0.1)),
+            ..RigidBody::default()
+        };
+        let mut wall_transform = Transform::default();
+        wall_transform.translation = glam::vec3(0.0, 0.0, 5.0);
+        physics.add_body(&wall_transform, &mut wall);
+        // update the broad phase.
+        physics.step();
+
+        let from = glam::Vec3::zero();
+        assert!(!physics.has_line_of_sight(from, glam::vec3(0.0, 0.0, 10.0), &[looker]));
+        // in front of the wall.
+        assert!(physics.has_line_of_sight(from, glam::vec3(0.0, 0.0, 4.0), &[looker]));
+        // next to the wall.
+        assert!(physics.has_line_of_sight(from, glam::vec3(5.0, 0.0, 10.0), &[looker]));
+    }
+
+    #[test]
+    fn raycast_max_distance() {
+        let mut physics = test_world();
+        let mut looker = RigidBody::default();
+        let looker = physics.add_body(&Transform::default(), &mut looker);
+
+        let mut wall = RigidBody {
+            shape: Shape::AABB(glam::vec3(1.0, 1.0, 0.1)),
+            ..RigidBody::default()
+        };
+        let mut wall_transform = Transform::default();
+        wall_transform.translation = glam::vec3(0.0, 0.0, 5.0);
+        physics.add_body(&wall_transform, &mut wall);
+        physics.step();
+
+        let from = glam::Vec3::zero();
+        let short = glam::vec3(0.0, 0.0, 0.5);
+        assert!(physics
+            .raycast_with_max_distance(looker, from, short, 4.0)
+            .is_empty());
+        let hits = physics.raycast_with_max_distance(looker, from, short, 6.0);
+        assert_eq!(1, hits.len());
+        // the time of impact is in units of the direction.
+        assert!((hits[0].toi - 9.8).abs() < 1e-4);
+        assert_eq!(1, physics.raycast(looker, from, short).len());
+    }
+
+    #[test]
+    fn overlaps_filter_body_types() {
+        let mut physics = test_world();
+        let mut trigger = RigidBody {
+            ty: BodyType::Dynamic,
+            max_linear_velocity: 10.0,
+            ..RigidBody::default()
+        };
+        let trigger = physics.add_body(&Transform::default(), &mut trigger);
+
+        let mut other = RigidBody {
+            ty: BodyType::Dynamic,
+            max_linear_velocity: 10.0,
+            ..RigidBody::default()
+        };
+        let mut other_transform = Transform::default();
+        other_transform.translation = glam::vec3(1.5, 0.0, 0.0);
+        let other = physics.add_body(&other_transform, &mut other);
+
+        let mut floor = RigidBody {
+            shape: Shape::AABB(glam::vec3(10.0, 0.5, 10.0)),
+            ..RigidBody::default()
+        };
+        let mut floor_transform = Transform::default();
+        floor_transform.translation = glam::vec3(0.0, -1.4, 0.0);
+        let floor = physics.add_body(&floor_transform, &mut floor);
+        // update the broad phase.
+        physics.step();
+
+        let bodies = |overlaps: Vec<(BodyIndex, glam::Vec3, f32)>| -> Vec<BodyIndex> {
+            overlaps.into_iter().map(|(b, _, _)| b).collect()
+        };
+        let statics = physics.overlaps(trigger, true, false, 0.0).unwrap();
+        assert_eq!(vec![floor], bodies(statics));
+        let dynamics = physics.overlaps(trigger, false, true, 0.0).unwrap();
+        assert_eq!(vec![other], bodies(dynamics));
+        assert_eq!(2, physics.overlaps(trigger, true, true, 0.0).unwrap().len());
+
+        // Same as before.
+        assert_eq!(1, physics.contact_with(trigger).unwrap().len());
+    }
+
+    #[test]
+    fn sensors_are_skipped() {
+        let mut physics = test_world();
+        let mut looker = RigidBody::default();
+        let looker = physics.add_body(&Transform::default(), &mut looker);
+
+        let mut sensor = RigidBody {
+            shape: Shape::AABB(glam::vec3(1.0, 1.0, 1.0)),
+            sensor: true,
+            ..RigidBody::default()
+        };
+        let mut sensor_transform = Transform::default();
+        sensor_transform.translation = glam::vec3(0.0, 0.0, 1.5);
+        physics.add_body(&sensor_transform, &mut sensor);
+        physics.step();
+
+        let from = glam::Vec3::zero();
+        let forward = glam::vec3(0.0, 0.0, 1.0);
+        assert!(physics.raycast(looker, from, forward).is_empty());
+        assert!(physics.has_line_of_sight(from, glam::vec3(0.0, 0.0, 5.0), &[looker]));
+        assert!(physics
+            .overlaps(looker, true, true, 0.0)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn sanitize_bad_body_from_map() {
+        let mut rb: RigidBody = ron::de::from_str(
+            "(mass: -2.0, shape: AABB((-1.0, 0.0, 0.5)), ty: Dynamic, max_linear_velocity: 10.0)",
+        )
+        .unwrap();
+
+        let mut physics = test_world();
+        let h = physics.add_body(&Transform::default(), &mut rb);
+        assert_eq!(1.0, rb.mass);
+        let Shape::AABB(half_extents) = rb.shape;
+        assert_eq!(glam::vec3(1.0, MIN_HALF_EXTENT, 0.5), half_extents);
+
+        // the simulation still works.
+        for _ in 0..10 {
+            physics.step();
+        }
+        let position = physics.get_position(h).unwrap();
+        assert!(position.y().is_finite() && position.y() < 0.0);
+
+        // nothing to fix on a valid body.
+        assert!(!rb.sanitize());
+    }
+}
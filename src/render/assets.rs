@@ -1,3 +1,4 @@
+use crate::paths::Paths;
 use crate::render::sprite::Metadata;
 use luminance::context::GraphicsContext;
 use luminance::pixel::NormRGBA8UI;
@@ -23,27 +24,13 @@ pub struct AssetManager {
 }
 
 impl AssetManager {
-    pub fn new(surface: &mut GlfwSurface) -> Self {
+    pub fn new(surface: &mut GlfwSurface, paths: &Paths) -> Self {
         let mut sprites = HashMap::new();
 
-        let crosshair = load_texture(
-            surface,
-            std::env::var("ASSET_PATH").unwrap() + "sprites/crosshair.png",
-        );
-        let shotgun_tex = load_texture(
-            surface,
-            std::env::var("ASSET_PATH").unwrap() + "sprites/shotgun.png",
-        );
-        let pistol_tex = load_texture(
-            surface,
-            std::env::var("ASSET_PATH").unwrap() + "sprites/pistol.png",
-        );
-        let soldier_tex = load_texture(
-            surface,
-            std::env::var("ASSET_PATH").unwrap() + "sprites/soldier.png",
-        );
+        let shotgun_tex = load_texture(surface, paths.asset("sprites/shotgun.png"));
+        let pistol_tex = load_texture(surface, paths.asset("sprites/pistol.png"));
+        let soldier_tex = load_texture(surface, paths.asset("sprites/soldier.png"));
 
-        sprites.insert("crosshair".to_string(), crosshair);
         sprites.insert("shotgun".to_string(), shotgun_tex);
         sprites.insert("soldier".to_string(), soldier_tex);
         sprites.insert("pistol".to_string(), pistol_tex);
@@ -51,15 +38,34 @@ impl AssetManager {
         let meshes = load_models(
             surface,
             &[
-                std::env::var("ASSET_PATH").unwrap() + "models/monkey.obj",
-                std::env::var("ASSET_PATH").unwrap() + "models/axis.obj",
-                std::env::var("ASSET_PATH").unwrap() + "models/cube.obj",
-                std::env::var("ASSET_PATH").unwrap() + "models/ramp.obj",
-                std::env::var("ASSET_PATH").unwrap() + "models/arena.obj",
+                paths.asset("models/monkey.obj"),
+                paths.asset("models/axis.obj"),
+                paths.asset("models/cube.obj"),
+                paths.asset("models/ramp.obj"),
+                paths.asset("models/arena.obj"),
             ],
         );
         Self { sprites, meshes }
     }
+
+    /// Load `sprites/<name>.png` and its metadata in the sprite cache if it is not already
+    /// there. This is used for the sprites that are chosen in the configuration, such as the
+    /// crosshair.
+    pub fn load_sprite(
+        &mut self,
+        surface: &mut GlfwSurface,
+        paths: &Paths,
+        name: &str,
+    ) -> Result<(), Error> {
+        if self.sprites.contains_key(name) {
+            return Ok(());
+        }
+
+        let sprite = try_load_texture(surface, paths.asset(&format!("sprites/{}.png", name)))?;
+        info!("Loaded sprite {}", name);
+        self.sprites.insert(name.to_string(), sprite);
+        Ok(())
+    }
 }
 
 type VertexIndex = u32;
@@ -71,6 +77,9 @@ pub enum Error {
 
     #[error("Error while loading obj file = {0}")]
     LoadObjError(String),
+
+    #[error("Error while loading sprite = {0}")]
+    LoadSpriteError(String),
 }
 #[derive(Debug)]
 pub struct Obj {
@@ -101,59 +110,69 @@ impl Obj {
             content
         };
 
+        Self::parse(file_content)
+    }
+
+    /// Build the mesh from the content of an OBJ file. The parser splits the faces with more
+    /// than 3 vertices (quads, convex polygons) in triangles, as a fan around the last vertex.
+    pub fn parse(file_content: String) -> Result<Self, Error> {
         let obj_set =
             obj::parse(file_content).map_err(|e| Error::LoadObjError(format!("{:?}", e)))?;
         let objects = obj_set.objects;
 
         info!("{} objects", objects.len());
-        for obj in &objects {
-            info!("name -> {}, geom -> {}", obj.name, obj.geometry.len());
-        }
         verify!(objects.len() >= 1).ok_or(Error::LoadObjError(
             "expecting at least one object".to_owned(),
         ))?;
 
-        let object = objects.into_iter().next().unwrap();
-        verify!(object.geometry.len() == 1).ok_or(Error::LoadObjError(
-            "expecting a single geometry".to_owned(),
-        ))?;
-
-        let geometry = object.geometry.into_iter().next().unwrap();
-        info!("Loading {}", object.name);
-        info!("{} vertices", object.vertices.len());
-        info!("{} shapes", geometry.shapes.len());
-
-        // build up vertices; for this to work, we remove duplicated vertices by putting them in a
-        // map associating the vertex with its ID
-        let mut vertex_cache: HashMap<obj::VTNIndex, VertexIndex> = HashMap::new();
+        // All the objects are merged in the same mesh.
         let mut vertices: Vec<super::Vertex> = Vec::new();
         let mut indices: Vec<VertexIndex> = Vec::new();
 
-        for shape in geometry.shapes {
-            if let obj::Primitive::Triangle(a, b, c) = shape.primitive {
-                for key in &[a, b, c] {
-                    if let Some(vertex_index) = vertex_cache.get(key) {
-                        indices.push(*vertex_index);
-                    } else {
-                        let p = object.vertices[key.0];
-                        let position =
-                            super::VertexPosition::new([p.x as f32, p.y as f32, p.z as f32]);
-                        let n = object.normals[key.2.ok_or(Error::LoadObjError(
-                            "Missing normal for a vertex".to_owned(),
-                        ))?];
-                        let normal = super::VertexNormal::new([n.x as f32, n.y as f32, n.z as f32]);
-                        let vertex = super::Vertex { position, normal };
-                        let vertex_index = vertices.len() as VertexIndex;
-
-                        vertex_cache.insert(*key, vertex_index);
-                        vertices.push(vertex);
-                        indices.push(vertex_index);
+        for object in objects {
+            info!("Loading {}", object.name);
+            info!("{} vertices", object.vertices.len());
+            info!("{} geometries", object.geometry.len());
+
+            // build up vertices; for this to work, we remove duplicated vertices by putting them
+            // in a map associating the vertex with its ID. The indices of the OBJ are relative
+            // to the object so the map cannot be shared between objects.
+            let mut vertex_cache: HashMap<obj::VTNIndex, VertexIndex> = HashMap::new();
+
+            let shapes = object.geometry.iter().flat_map(|g| g.shapes.iter());
+            for (i, shape) in shapes.enumerate() {
+                if let obj::Primitive::Triangle(a, b, c) = shape.primitive {
+                    for key in &[a, b, c] {
+                        if let Some(vertex_index) = vertex_cache.get(key) {
+                            indices.push(*vertex_index);
+                        } else {
+                            let p = object.vertices[key.0];
+                            let position =
+                                super::VertexPosition::new([p.x as f32, p.y as f32, p.z as f32]);
+                            let n = object.normals[key.2.ok_or_else(|| {
+                                Error::LoadObjError(format!(
+                                    "missing normal for vertex {} of face {} in {}",
+                                    key.0 + 1,
+                                    i,
+                                    object.name
+                                ))
+                            })?];
+                            let normal =
+                                super::VertexNormal::new([n.x as f32, n.y as f32, n.z as f32]);
+                            let vertex = super::Vertex { position, normal };
+                            let vertex_index = vertices.len() as VertexIndex;
+
+                            vertex_cache.insert(*key, vertex_index);
+                            vertices.push(vertex);
+                            indices.push(vertex_index);
+                        }
                     }
+                } else {
+                    return Err(Error::LoadObjError(format!(
+                        "unsupported shape {} in {}: {:?}, only faces are supported",
+                        i, object.name, shape.primitive
+                    )));
                 }
-            } else {
-                return Err(Error::LoadObjError(
-                    "unsupported non-triangle shape".to_owned(),
-                ));
             }
         }
 
@@ -189,20 +208,28 @@ fn load_texture<P: AsRef<Path>>(
     surface: &mut GlfwSurface,
     path: P,
 ) -> (Texture<Dim2, NormRGBA8UI>, Metadata) {
+    try_load_texture(surface, path).unwrap()
+}
+
+fn try_load_texture<P: AsRef<Path>>(
+    surface: &mut GlfwSurface,
+    path: P,
+) -> Result<(Texture<Dim2, NormRGBA8UI>, Metadata), Error> {
     let mut metadata_path = path.as_ref().to_path_buf();
 
     // first the texture.
-
-    let image = read_image(path).unwrap();
+    let image = read_image(&path).ok_or_else(|| {
+        Error::LoadSpriteError(format!("Cannot read image {}", path.as_ref().display()))
+    })?;
     let tex = load_from_disk(surface, image);
 
     // then the metadata.
     metadata_path.set_extension("ron");
-    println!("Metadata path = {:?}", metadata_path);
-    let metadata: Metadata =
-        ron::de::from_str(&fs::read_to_string(metadata_path).unwrap()).unwrap();
+    debug!("Metadata path = {:?}", metadata_path);
+    let metadata: Metadata = ron::de::from_str(&fs::read_to_string(&metadata_path)?)
+        .map_err(|e| Error::LoadSpriteError(format!("{:?}: {}", metadata_path, e)))?;
 
-    (tex, metadata)
+    Ok((tex, metadata))
 }
 
 fn load_from_disk(surface: &mut GlfwSurface, img: image::RgbaImage) -> Texture<Dim2, NormRGBA8UI> {
@@ -220,3 +247,61 @@ fn load_from_disk(surface: &mut GlfwSurface, img: image::RgbaImage) -> Texture<D
 
     tex
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_quad() {
+        let content = "o quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+f 1//1 2//1 3//1 4//1
+";
+        let obj = Obj::parse(content.to_owned()).unwrap();
+        // two triangles sharing two vertices.
+        assert_eq!(4, obj.vertices.len());
+        assert_eq!(6, obj.indices.len());
+    }
+
+    #[test]
+    fn load_all_objects() {
+        let content = "o first
+v 0 0 0
+v 1 0 0
+v 1 1 0
+vn 0 0 1
+f 1//1 2//1 3//1
+o second
+v 0 0 1
+v 1 0 1
+v 1 1 1
+v 0 1 1
+vn 0 0 1
+f 4//2 5//2 6//2
+f 4//2 6//2 7//2
+";
+        let obj = Obj::parse(content.to_owned()).unwrap();
+        assert_eq!(7, obj.vertices.len());
+        assert_eq!(9, obj.indices.len());
+        // the second object does not reuse the vertices of the first one.
+        assert!(obj.indices[3..].iter().all(|i| *i >= 3));
+    }
+
+    #[test]
+    fn load_lines() {
+        let content = "o line
+v 0 0 0
+v 1 0 0
+l 1 2
+";
+        match Obj::parse(content.to_owned()) {
+            Err(Error::LoadObjError(msg)) => assert!(msg.contains("shape 0 in line")),
+            other => panic!("Expected an error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -1,7 +1,6 @@
 //! Sprites that will always face the player. It uses the perspective projection matrix
 
-use crate::camera::Camera;
-use crate::ecs::Transform;
+use crate::ecs::{render_transform, PreviousTransform, Transform};
 use crate::gameplay::player::MainPlayer;
 use crate::net::snapshot::Deltable;
 use crate::render::assets::SpriteCache;
@@ -102,63 +101,52 @@ impl BillboardRenderer {
         world: &World,
         sprite_cache: &SpriteCache,
         shaders: &Shaders,
+        interpolation: f32,
     ) where
         S: GraphicsContext,
     {
-        let camera_pos = {
-            world
-                .query::<(&Camera, &Transform)>()
+        // the view is interpolated like the rendered entities.
+        let camera_position = view.inverse().w_axis().truncate();
+
+        shd_gate.shade(&shaders.billboard_program, |iface, mut rdr_gate| {
+            iface.projection.update(projection.to_cols_array_2d());
+            iface.view.update(view.to_cols_array_2d());
+            iface.camera_position.update(camera_position.into());
+            for (e, (transform, previous, billboard)) in world
+                .query::<(&Transform, Option<&PreviousTransform>, &Billboard)>()
                 .iter()
-                .filter_map(
-                    |(_, (c, t))| {
-                        if c.active {
-                            Some(t.translation)
-                        } else {
-                            None
-                        }
-                    },
-                )
-                .next()
-        };
-
-        if let Some(camera_position) = camera_pos {
-            shd_gate.shade(&shaders.billboard_program, |iface, mut rdr_gate| {
-                iface.projection.update(projection.to_cols_array_2d());
-                iface.view.update(view.to_cols_array_2d());
-                iface.camera_position.update(camera_position.into());
-                for (e, (transform, billboard)) in world.query::<(&Transform, &Billboard)>().iter()
-                {
-                    if world.get::<MainPlayer>(e).is_ok() {
-                        continue;
-                    }
-
-                    if !billboard.enabled {
-                        continue;
-                    }
-
-                    let assets = sprite_cache.get(&billboard.texture).unwrap();
-                    let texture = pipeline.bind_texture(&assets.0);
-                    let metadata = &assets.1;
-
-                    let sprite_idx = if billboard.sprite_nb >= metadata.sprites.len() {
-                        0
-                    } else {
-                        billboard.sprite_nb
-                    };
-                    iface.tex.update(&texture);
-                    iface
-                        .sprite_coord
-                        .update(assets.1.sprites.get(sprite_idx).unwrap().as_array());
-                    iface.spritesheet_dimensions.update(assets.1.dim_as_array());
-
-                    let model = transform.to_model();
-                    iface.center.update(transform.translation.into());
-                    iface.model.update(model.to_cols_array_2d());
-                    rdr_gate.render(&RenderState::default(), |mut tess_gate| {
-                        tess_gate.render(self.tess.slice(..));
-                    });
+            {
+                let transform = render_transform(transform, previous, interpolation);
+                if world.get::<MainPlayer>(e).is_ok() {
+                    continue;
                 }
-            });
-        }
+
+                if !billboard.enabled {
+                    continue;
+                }
+
+                let assets = sprite_cache.get(&billboard.texture).unwrap();
+                let texture = pipeline.bind_texture(&assets.0);
+                let metadata = &assets.1;
+
+                let sprite_idx = if billboard.sprite_nb >= metadata.sprites.len() {
+                    0
+                } else {
+                    billboard.sprite_nb
+                };
+                iface.tex.update(&texture);
+                iface
+                    .sprite_coord
+                    .update(assets.1.sprites.get(sprite_idx).unwrap().as_array());
+                iface.spritesheet_dimensions.update(assets.1.dim_as_array());
+
+                let model = transform.to_model();
+                iface.center.update(transform.translation.into());
+                iface.model.update(model.to_cols_array_2d());
+                rdr_gate.render(&RenderState::default(), |mut tess_gate| {
+                    tess_gate.render(self.tess.slice(..));
+                });
+            }
+        });
     }
 }
@@ -1,6 +1,7 @@
 //! Debug rendering - for example for AABB colliders...
 
-use crate::ecs::Transform;
+use crate::colors::RgbColor;
+use crate::ecs::{render_transform, PreviousTransform, Transform};
 use crate::gameplay::player::MainPlayer;
 use crate::net::snapshot::Deltable;
 use crate::physics::{PhysicWorld, RigidBody, Shape};
@@ -8,7 +9,7 @@ use crate::render::shaders::Shaders;
 use glam::{Mat4, Vec3};
 use hecs::World;
 #[allow(unused_imports)]
-use log::{debug, info};
+use log::{debug, error, info};
 use luminance::context::GraphicsContext;
 use luminance::linear::M44;
 use luminance::pipeline::ShadingGate;
@@ -23,6 +24,8 @@ use serde_derive::{Deserialize, Serialize};
 pub enum VertexSemantics {
     #[sem(name = "position", repr = "[f32; 3]", wrapper = "VertexPosition")]
     Position,
+    #[sem(name = "color", repr = "[f32; 3]", wrapper = "VertexColor")]
+    Color,
 }
 #[allow(dead_code)]
 #[derive(Vertex, Debug)]
@@ -31,6 +34,85 @@ pub struct Vertex {
     position: VertexPosition,
 }
 
+/// Vertex of the batched debug lines. The color is per vertex so that all the lines can be
+/// drawn at once.
+#[allow(dead_code)]
+#[derive(Vertex, Debug)]
+#[vertex(sem = "VertexSemantics")]
+pub struct LineVertex {
+    position: VertexPosition,
+    color: VertexColor,
+}
+
+impl LineVertex {
+    fn from_point(position: Vec3, color: RgbColor) -> Self {
+        Self {
+            position: VertexPosition::new(position.into()),
+            color: VertexColor::new(color.to_normalized()),
+        }
+    }
+}
+
+/// Color of the colliders.
+const AABB_COLOR: RgbColor = RgbColor::new(57, 255, 20);
+
+/// Initial number of vertices of the debug lines buffer. It grows when more lines are queued.
+const LINES_INITIAL_CAPACITY: usize = 1024;
+
+/// Number of segments of each circle drawn by `draw_sphere`.
+const SPHERE_SEGMENTS: usize = 24;
+
+/// Ground grid displayed in the free and editor modes, in the plane y = 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridConfig {
+    pub enabled: bool,
+    /// Distance between two minor lines.
+    pub spacing: f32,
+    /// Number of minor cells between two major lines. 0 means no major lines.
+    pub major_every: u32,
+    /// Number of minor cells between the center and the border of the grid.
+    pub extent: u32,
+    /// Center the grid below the camera instead of the origin. The grid stays aligned with
+    /// the major lines so it does not look like it moves with the camera.
+    pub follow_camera: bool,
+    pub minor_color: RgbColor,
+    pub major_color: RgbColor,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            spacing: 1.0,
+            major_every: 10,
+            extent: 50,
+            follow_camera: false,
+            minor_color: RgbColor::new(90, 90, 90),
+            major_color: RgbColor::new(160, 160, 160),
+        }
+    }
+}
+
+impl GridConfig {
+    fn major_spacing(&self) -> f32 {
+        self.spacing * self.major_every.max(1) as f32
+    }
+
+    /// Position of the center of the grid.
+    fn center(&self, camera_position: Vec3) -> Vec3 {
+        if self.follow_camera {
+            let step = self.major_spacing();
+            glam::vec3(
+                (camera_position.x() / step).round() * step,
+                0.0,
+                (camera_position.z() / step).round() * step,
+            )
+        } else {
+            Vec3::zero()
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum DebugRender {
     None,
@@ -111,96 +193,261 @@ pub struct ShaderInterface {
 
     #[uniform(unbound)]
     pub model: Uniform<M44>,
+
+    #[uniform(unbound)]
+    pub color: Uniform<[f32; 3]>,
 }
 
-fn get_cube<S>(surface: &mut S) -> Tess
+#[derive(UniformInterface)]
+pub struct LinesShaderInterface {
+    #[uniform(unbound)]
+    pub projection: Uniform<M44>,
+
+    #[uniform(unbound)]
+    pub view: Uniform<M44>,
+}
+
+/// Lines of a grid of `2 * extent` unit cells centered on the origin, in the plane y = 0.
+/// Returns the minor and major lines, with their color. Empty sets of lines are skipped.
+fn get_grid<S>(surface: &mut S, config: &GridConfig) -> Vec<(Tess, RgbColor)>
 where
     S: GraphicsContext,
 {
-    let mut vertices = vec![
-        //front
-        VertexPosition::new([-1.0, 1.0, 1.0]),
-        VertexPosition::new([1.0, 1.0, 1.0]),
-        VertexPosition::new([1.0, 1.0, 1.0]),
-        VertexPosition::new([1.0, -1.0, 1.0]),
-        VertexPosition::new([1.0, -1.0, 1.0]),
-        VertexPosition::new([-1.0, -1.0, 1.0]),
-        VertexPosition::new([-1.0, -1.0, 1.0]),
-        VertexPosition::new([-1.0, 1.0, 1.0]),
-        //right
-        VertexPosition::new([1.0, 1.0, 1.0]),
-        VertexPosition::new([1.0, 1.0, -1.0]),
-        VertexPosition::new([1.0, 1., -1.0]),
-        VertexPosition::new([1.0, -1.0, -1.0]),
-        VertexPosition::new([1.0, -1.0, -1.0]),
-        VertexPosition::new([1.0, -1.0, 1.0]),
-        //back
-        VertexPosition::new([1.0, 1.0, -1.0]),
-        VertexPosition::new([-1.0, 1.0, -1.0]),
-        VertexPosition::new([-1.0, -1.0, -1.0]),
-        VertexPosition::new([1.0, -1., -1.0]),
-        VertexPosition::new([-1.0, -1.0, -1.0]),
-        VertexPosition::new([-1.0, 1.0, -1.0]),
-        //left
-        VertexPosition::new([-1.0, 1.0, -1.0]),
-        VertexPosition::new([-1.0, 1.0, 1.0]),
-        VertexPosition::new([-1.0, -1.0, 1.]),
-        VertexPosition::new([-1.0, -1.0, -1.0]),
-    ];
+    let mut minor = vec![];
+    let mut major = vec![];
+    let extent = config.extent as i32;
+    let max = extent as f32;
+    for i in -extent..=extent {
+        let lines = if config.major_every > 0 && i % config.major_every as i32 == 0 {
+            &mut major
+        } else {
+            &mut minor
+        };
+        let i = i as f32;
+        lines.push(Vertex {
+            position: VertexPosition::new([-max, 0.0, i]),
+        });
+        lines.push(Vertex {
+            position: VertexPosition::new([max, 0.0, i]),
+        });
+        lines.push(Vertex {
+            position: VertexPosition::new([i, 0.0, -max]),
+        });
+        lines.push(Vertex {
+            position: VertexPosition::new([i, 0.0, max]),
+        });
+    }
 
+    vec![(minor, config.minor_color), (major, config.major_color)]
+        .into_iter()
+        .filter(|(lines, _)| !lines.is_empty())
+        .map(|(lines, color)| {
+            let tess = TessBuilder::new(surface)
+                .add_vertices(lines)
+                .set_mode(Mode::Line)
+                .build()
+                .unwrap();
+            (tess, color)
+        })
+        .collect()
+}
+
+/// Buffer of `capacity` line vertices, updated every frame.
+fn get_lines_buffer<S>(surface: &mut S, capacity: usize) -> Tess
+where
+    S: GraphicsContext,
+{
+    let vertices = (0..capacity)
+        .map(|_| LineVertex::from_point(Vec3::zero(), AABB_COLOR))
+        .collect::<Vec<_>>();
     TessBuilder::new(surface)
-        .add_vertices(
-            vertices
-                .drain(..)
-                .map(|p| Vertex { position: p })
-                .collect::<Vec<Vertex>>(),
-        )
+        .add_vertices(vertices)
         .set_mode(Mode::Line)
         .build()
         .unwrap()
 }
 
 pub struct DebugRenderer {
-    tess: Tess,
+    grid_config: GridConfig,
+    /// Lines of the grid. Empty if the grid is disabled.
+    grid: Vec<(Tess, RgbColor)>,
+
+    /// Lines queued with `draw_line`, `draw_box` and `draw_sphere` since the last frame.
+    lines: Vec<LineVertex>,
+    /// GPU buffer of the lines. Only the first `lines_count` vertices are drawn.
+    lines_tess: Tess,
+    lines_capacity: usize,
+    lines_count: usize,
 }
 impl DebugRenderer {
-    pub fn new(surface: &mut GlfwSurface) -> Self {
-        let tess = get_cube(surface);
-        Self { tess }
+    pub fn new(surface: &mut GlfwSurface, grid_config: GridConfig) -> Self {
+        let grid = if grid_config.enabled {
+            get_grid(surface, &grid_config)
+        } else {
+            vec![]
+        };
+        let lines_tess = get_lines_buffer(surface, LINES_INITIAL_CAPACITY);
+        Self {
+            grid_config,
+            grid,
+            lines: vec![],
+            lines_tess,
+            lines_capacity: LINES_INITIAL_CAPACITY,
+            lines_count: 0,
+        }
     }
 
-    pub fn render<S>(
+    /// Queue a line for the next frame.
+    pub fn draw_line(&mut self, start: Vec3, end: Vec3, color: RgbColor) {
+        self.lines.push(LineVertex::from_point(start, color));
+        self.lines.push(LineVertex::from_point(end, color));
+    }
+
+    /// Queue the 12 edges of an oriented box for the next frame.
+    pub fn draw_box(
+        &mut self,
+        center: Vec3,
+        half_extents: Vec3,
+        rotation: glam::Quat,
+        color: RgbColor,
+    ) {
+        let corner =
+            |x: f32, y: f32, z: f32| center + rotation * (half_extents * glam::vec3(x, y, z));
+        for &(a, b) in &[(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)] {
+            // edges along x, y and z.
+            self.draw_line(corner(-1.0, a, b), corner(1.0, a, b), color);
+            self.draw_line(corner(a, -1.0, b), corner(a, 1.0, b), color);
+            self.draw_line(corner(a, b, -1.0), corner(a, b, 1.0), color);
+        }
+    }
+
+    /// Queue the boxes of the colliders (`DebugRender` components) for the next frame, at the
+    /// same interpolated position as the meshes. The main player is skipped as it would hide
+    /// the view.
+    pub fn draw_colliders(&mut self, world: &World, interpolation: f32) {
+        for (e, (t, previous, debug_render)) in world
+            .query::<(&Transform, Option<&PreviousTransform>, &DebugRender)>()
+            .iter()
+        {
+            if world.get::<MainPlayer>(e).is_ok() {
+                continue;
+            }
+            let t = render_transform(t, previous, interpolation);
+            if let DebugRender::Aabb(aabb) = debug_render {
+                self.draw_box(t.translation, *aabb, t.rotation, AABB_COLOR);
+            }
+        }
+    }
+
+    /// Queue a wireframe sphere (one circle per axis) for the next frame.
+    pub fn draw_sphere(&mut self, center: Vec3, radius: f32, color: RgbColor) {
+        let step = 2.0 * std::f32::consts::PI / SPHERE_SEGMENTS as f32;
+        for i in 0..SPHERE_SEGMENTS {
+            let (s0, c0) = (i as f32 * step).sin_cos();
+            let (s1, c1) = ((i + 1) as f32 * step).sin_cos();
+            for &axis in &[0, 1, 2] {
+                let point = |s: f32, c: f32| {
+                    let p = match axis {
+                        0 => glam::vec3(0.0, c, s),
+                        1 => glam::vec3(c, 0.0, s),
+                        _ => glam::vec3(c, s, 0.0),
+                    };
+                    center + p * radius
+                };
+                self.draw_line(point(s0, c0), point(s1, c1), color);
+            }
+        }
+    }
+
+    /// Copy the queued lines to the GPU buffer. The buffer is recreated if it is too small.
+    pub fn upload_lines(&mut self, surface: &mut GlfwSurface) {
+        if self.lines.len() > self.lines_capacity {
+            self.lines_capacity = self.lines.len().next_power_of_two();
+            debug!(
+                "Grow debug lines buffer to {} vertices",
+                self.lines_capacity
+            );
+            self.lines_tess = get_lines_buffer(surface, self.lines_capacity);
+        }
+
+        self.lines_count = 0;
+        if self.lines.is_empty() {
+            return;
+        }
+        match self.lines_tess.as_slice_mut::<LineVertex>() {
+            Ok(mut buffer) => {
+                for (dst, src) in buffer.iter_mut().zip(self.lines.drain(..)) {
+                    *dst = src;
+                    self.lines_count += 1;
+                }
+            }
+            Err(e) => error!("Cannot update the debug lines = {:?}", e),
+        }
+    }
+
+    /// Forget the queued lines without drawing them.
+    pub fn clear_lines(&mut self) {
+        self.lines.clear();
+        self.lines_count = 0;
+    }
+
+    /// Draw the ground grid. The lines are hidden by the geometry.
+    pub fn render_grid<S>(
         &self,
         projection: &Mat4,
         view: &Mat4,
         shd_gate: &mut ShadingGate<S>,
-        world: &World,
         shaders: &Shaders,
     ) where
         S: GraphicsContext,
     {
+        if self.grid.is_empty() {
+            return;
+        }
+        let camera_position = view.inverse().w_axis().truncate();
+        let model = glam::Mat4::from_scale_rotation_translation(
+            Vec3::splat(self.grid_config.spacing),
+            glam::Quat::identity(),
+            self.grid_config.center(camera_position),
+        );
+
         shd_gate.shade(&shaders.debug_program, |iface, mut rdr_gate| {
             iface.projection.update(projection.to_cols_array_2d());
             iface.view.update(view.to_cols_array_2d());
-            for (e, (t, debug_render)) in world.query::<(&Transform, &DebugRender)>().iter() {
-                if world.get::<MainPlayer>(e).is_ok() {
-                    continue;
-                }
-                if let DebugRender::Aabb(aabb) = debug_render {
-                    let model = glam::Mat4::from_scale_rotation_translation(
-                        *aabb,
-                        t.rotation,
-                        t.translation,
-                    );
-                    iface.model.update(model.to_cols_array_2d());
-                    rdr_gate.render(
-                        &RenderState::default().set_depth_test(None),
-                        |mut tess_gate| {
-                            tess_gate.render(self.tess.slice(..));
-                        },
-                    );
-                }
+            iface.model.update(model.to_cols_array_2d());
+            for (tess, color) in &self.grid {
+                iface.color.update(color.to_normalized());
+                rdr_gate.render(&RenderState::default(), |mut tess_gate| {
+                    tess_gate.render(tess.slice(..));
+                });
             }
         });
     }
+
+    /// Draw the lines uploaded by `upload_lines`, colliders included, in a single draw call.
+    /// The lines are cleared afterward.
+    pub fn render<S>(
+        &mut self,
+        projection: &Mat4,
+        view: &Mat4,
+        shd_gate: &mut ShadingGate<S>,
+        shaders: &Shaders,
+    ) where
+        S: GraphicsContext,
+    {
+        if self.lines_count > 0 {
+            let (lines_tess, lines_count) = (&self.lines_tess, self.lines_count);
+            shd_gate.shade(&shaders.debug_lines_program, |iface, mut rdr_gate| {
+                iface.projection.update(projection.to_cols_array_2d());
+                iface.view.update(view.to_cols_array_2d());
+                rdr_gate.render(
+                    &RenderState::default().set_depth_test(None),
+                    |mut tess_gate| {
+                        tess_gate.render(lines_tess.slice(..lines_count));
+                    },
+                );
+            });
+            self.lines_count = 0;
+        }
+    }
 }
@@ -90,6 +90,27 @@ pub struct DirectionalLight {
     pub intensity: f32,
 }
 
+impl DirectionalLight {
+    /// Azimuth (rotation around the Y axis, 0 is toward +Z) and elevation of the direction, in
+    /// radians.
+    pub fn angles(&self) -> (f32, f32) {
+        if self.direction.length_squared() < std::f32::EPSILON {
+            return (0.0, 0.0);
+        }
+        let d = self.direction.normalize();
+        (d.x().atan2(d.z()), d.y().max(-1.0).min(1.0).asin())
+    }
+
+    /// Set the direction from its azimuth and elevation. See `angles`.
+    pub fn set_angles(&mut self, azimuth: f32, elevation: f32) {
+        self.direction = glam::vec3(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            elevation.cos() * azimuth.cos(),
+        );
+    }
+}
+
 #[derive(UniformInterface)]
 pub struct DirectionalShaderInterface {
     /// the diffuse texture.
@@ -0,0 +1,147 @@
+//! Image based lighting. When an environment map is loaded, the ambient light of the PBR shader
+//! comes from it instead of the flat `AmbientLight` color: an irradiance cubemap gives the
+//! diffuse part and a prefiltered cubemap gives the specular reflections.
+//!
+//! Each cubemap is a directory with one square image per face: `px.png`, `nx.png`, `py.png`,
+//! `ny.png`, `pz.png` and `nz.png`. luminance cannot upload the mipmap levels one by one, so the
+//! levels of the specular cubemap are generated when loading it. The shader picks the level
+//! from the roughness.
+use crate::paths::Paths;
+#[allow(unused_imports)]
+use log::{debug, info};
+use luminance::pixel::NormRGB8UI;
+use luminance::texture::{
+    CubeFace, Cubemap, GenMipmaps, MagFilter, MinFilter, Sampler, Texture, TextureError, Wrap,
+};
+use luminance_glfw::GlfwSurface;
+use serde_derive::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+const FACES: [(&str, CubeFace); 6] = [
+    ("px", CubeFace::PositiveX),
+    ("nx", CubeFace::NegativeX),
+    ("py", CubeFace::PositiveY),
+    ("ny", CubeFace::NegativeY),
+    ("pz", CubeFace::PositiveZ),
+    ("nz", CubeFace::NegativeZ),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvironmentConfig {
+    /// Directory of the irradiance cubemap, relative to the assets.
+    pub irradiance: String,
+    /// Directory of the prefiltered specular cubemap, relative to the assets.
+    pub specular: String,
+    /// Multiplier of the light coming from the environment.
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Error)]
+pub enum EnvironmentError {
+    #[error("Cannot load {0}: {1}")]
+    ImageError(String, image::ImageError),
+
+    #[error("{0} is {1}x{2}, the faces should be square and have the same size")]
+    InvalidSize(String, u32, u32),
+
+    #[error("Cannot create the cubemap: {0:?}")]
+    TextureError(TextureError),
+}
+
+pub struct EnvironmentMap {
+    pub irradiance: Texture<Cubemap, NormRGB8UI>,
+    pub specular: Texture<Cubemap, NormRGB8UI>,
+    /// Index of the last mipmap level of the specular cubemap, used for the roughest surfaces.
+    pub max_specular_level: f32,
+    pub intensity: f32,
+}
+
+impl EnvironmentMap {
+    pub fn load(
+        surface: &mut GlfwSurface,
+        paths: &Paths,
+        config: &EnvironmentConfig,
+    ) -> Result<Self, EnvironmentError> {
+        let (irradiance, _) = load_cubemap(surface, &paths.asset(&config.irradiance), false)?;
+        let (specular, levels) = load_cubemap(surface, &paths.asset(&config.specular), true)?;
+        info!(
+            "Loaded environment map {} / {}",
+            config.irradiance, config.specular
+        );
+
+        Ok(Self {
+            irradiance,
+            specular,
+            max_specular_level: levels as f32,
+            intensity: config.intensity,
+        })
+    }
+}
+
+/// Load the six faces of a cubemap. Return the cubemap and its number of mipmap levels, not
+/// counting the base level.
+fn load_cubemap(
+    surface: &mut GlfwSurface,
+    directory: &str,
+    mipmaps: bool,
+) -> Result<(Texture<Cubemap, NormRGB8UI>, usize), EnvironmentError> {
+    let mut faces = vec![];
+    let mut size = None;
+    for (name, face) in FACES.iter() {
+        let path = Path::new(directory).join(format!("{}.png", name));
+        let path_str = path.display().to_string();
+        let img = image::open(&path)
+            .map_err(|e| EnvironmentError::ImageError(path_str.clone(), e))?
+            .to_rgb();
+        let (width, height) = img.dimensions();
+        if width != height || size.map(|s| s != width).unwrap_or(false) {
+            return Err(EnvironmentError::InvalidSize(path_str, width, height));
+        }
+        size = Some(width);
+        faces.push((*face, img.into_raw()));
+    }
+    let size = size.unwrap_or(1);
+
+    let levels = if mipmaps {
+        (size as f32).log2().floor() as usize
+    } else {
+        0
+    };
+    let mut sampler = Sampler::default();
+    sampler.wrap_r = Wrap::ClampToEdge;
+    sampler.wrap_s = Wrap::ClampToEdge;
+    sampler.wrap_t = Wrap::ClampToEdge;
+    sampler.mag_filter = MagFilter::Linear;
+    sampler.min_filter = if mipmaps {
+        MinFilter::LinearMipmapLinear
+    } else {
+        MinFilter::Linear
+    };
+
+    let texture =
+        Texture::new(surface, size, levels, sampler).map_err(EnvironmentError::TextureError)?;
+    let nb_faces = faces.len();
+    for (i, (face, texels)) in faces.iter().enumerate() {
+        // the levels can only be generated once all the faces are there.
+        let gen_mipmaps = if mipmaps && i + 1 == nb_faces {
+            GenMipmaps::Yes
+        } else {
+            GenMipmaps::No
+        };
+        texture
+            .upload_part_raw(gen_mipmaps, ([0, 0], *face), size, texels)
+            .map_err(EnvironmentError::TextureError)?;
+    }
+    debug!(
+        "Cubemap {} is {}x{}, {} levels",
+        directory, size, size, levels
+    );
+
+    Ok((texture, levels))
+}
@@ -1,6 +1,5 @@
 //! Material for the PBR rendering.
 
-use crate::render::mesh::scene::Assets;
 use crate::render::mesh::shaders::ShaderFlags;
 use crate::render::mesh::texture::Texture;
 use crate::render::mesh::ImportData;
@@ -152,7 +151,6 @@ impl Material {
         surface: &mut GlfwSurface,
         material: &gltf::Material,
         import: &ImportData,
-        assets: &mut Assets,
     ) -> Self {
         let pbr_stuff = material.pbr_metallic_roughness();
         let base_color = pbr_stuff.base_color_factor();
@@ -220,8 +218,6 @@ impl Material {
             0.0
         };
 
-        assets.shaders.add_shader(shader_flags);
-
         Self {
             base_color,
             metallic_roughness_values: [metallic, roughness],
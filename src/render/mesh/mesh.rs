@@ -1,5 +1,9 @@
-use super::{primitive::Primitive, ImportData};
+use super::{
+    primitive::{ImportReport, Primitive},
+    ImportData,
+};
 use crate::render::mesh::scene::Assets;
+use log::{debug, warn};
 use luminance_glfw::GlfwSurface;
 
 /// Nodes of a scene can have a mesh. A mesh is made of multiple primitives.
@@ -15,10 +19,21 @@ impl Mesh {
         import_data: &ImportData,
         assets: &mut Assets,
     ) -> Self {
+        let mut report = ImportReport::default();
         let primitives = mesh
             .primitives()
-            .map(|p| Primitive::from_gltf(surface, p, import_data, assets))
+            .map(|p| Primitive::from_gltf(surface, p, import_data, assets, &mut report))
             .collect();
+
+        let name = mesh.name().unwrap_or("<unnamed>");
+        if report.has_fixes() {
+            warn!(
+                "Mesh {}: fixed {} normals and {} texture coordinates ({} vertices)",
+                name, report.fixed_normals, report.fixed_tex_coords, report.vertices
+            );
+        } else {
+            debug!("Mesh {}: {} vertices", name, report.vertices);
+        }
         Self { primitives }
     }
 }
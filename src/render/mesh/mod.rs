@@ -4,10 +4,10 @@ use luminance::linear::M44;
 use luminance::pipeline::{BoundTexture, Pipeline, ShadingGate};
 use luminance::pixel::NormUnsigned;
 use luminance::shader::program::{Program, ProgramInterface, Uniform};
-use luminance::texture::Dim2;
+use luminance::texture::{Cubemap, Dim2};
 use luminance_derive::{Semantics, UniformInterface, Vertex};
 
-pub mod deferred;
+pub mod environment;
 pub mod import;
 pub mod material;
 pub mod mesh;
@@ -16,15 +16,18 @@ pub mod scene;
 mod shaders;
 pub mod texture;
 use crate::assets::material::Material;
-use crate::assets::mesh::MaterialId;
+use crate::assets::material::DEFAULT_MATERIAL;
+use crate::assets::mesh::{MaterialId, DEFAULT_MESH};
 use crate::assets::{AssetManager, Handle};
-use crate::ecs::Transform;
+use crate::ecs::{render_transform, PreviousTransform, Transform};
 use crate::event::GameEvent;
+use crate::paths::Paths;
 use crate::render::lighting::{AmbientLight, DirectionalLight};
+use crate::render::mesh::environment::EnvironmentMap;
 use crate::render::mesh::mesh::Mesh;
 use crate::render::mesh::primitive::Primitive;
 use crate::render::mesh::shaders::PbrShaders;
-use crate::render::Render;
+use crate::render::{Render, RenderLayer};
 use crate::resources::Resources;
 use luminance::context::GraphicsContext;
 use luminance::render_state::RenderState;
@@ -32,9 +35,12 @@ use luminance::tess::{Tess, TessSlice};
 use luminance_glfw::GlfwSurface;
 pub use shaders::ShaderFlags;
 use shrev::EventChannel;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::rc::Rc;
 
+/// Primitives to draw, grouped by layer and material.
+type SortedPrimitives = BTreeMap<(RenderLayer, MaterialId), Vec<(Rc<Tess>, Transform)>>;
+
 type ImportData = (
     gltf::Document,
     Vec<gltf::buffer::Data>,
@@ -159,6 +165,16 @@ pub struct PbrShaderInterface {
     pub u_ambient_light_color: Uniform<[f32; 3]>,
     #[uniform(name = "u_AmbientLightIntensity", unbound)]
     pub u_ambient_light_intensity: Uniform<f32>,
+
+    // image based lighting, replaces the ambient light when there is an environment map.
+    #[uniform(name = "u_IrradianceSampler", unbound)]
+    pub u_irradiance_sampler: Uniform<&'static BoundTexture<'static, Cubemap, NormUnsigned>>,
+    #[uniform(name = "u_SpecularEnvSampler", unbound)]
+    pub u_specular_env_sampler: Uniform<&'static BoundTexture<'static, Cubemap, NormUnsigned>>,
+    #[uniform(name = "u_MaxSpecularLevel", unbound)]
+    pub u_max_specular_level: Uniform<f32>,
+    #[uniform(name = "u_EnvironmentIntensity", unbound)]
+    pub u_environment_intensity: Uniform<f32>,
 }
 
 pub struct PbrRenderer {
@@ -166,13 +182,21 @@ pub struct PbrRenderer {
     shaders: PbrShaders,
 
     default_material_handle: Handle,
+
+    /// Mesh to display when the mesh of an entity cannot be loaded.
+    default_mesh_handle: Handle,
+
+    /// Image based lighting. The flat ambient light is used when there is none.
+    environment: Option<EnvironmentMap>,
 }
 
 impl PbrRenderer {
-    pub fn new() -> Self {
+    pub fn new(paths: &Paths, environment: Option<EnvironmentMap>) -> Self {
         Self {
-            shaders: PbrShaders::new(),
-            default_material_handle: Handle("default_material".to_owned()),
+            shaders: PbrShaders::new(paths),
+            default_material_handle: Handle(DEFAULT_MATERIAL.to_owned()),
+            default_mesh_handle: Handle(DEFAULT_MESH.to_owned()),
+            environment,
         }
     }
     pub fn render<S>(
@@ -183,30 +207,48 @@ impl PbrRenderer {
         view: &glam::Mat4,
         world: &hecs::World,
         resources: &Resources,
+        interpolation: f32,
     ) where
         S: GraphicsContext,
     {
-        let camera_entity =
-            crate::camera::find_main_camera(world).expect("World should have a main camera");
-        let camera_position = world.get::<Transform>(camera_entity).unwrap().translation;
+        // the view is interpolated like the meshes.
+        let camera_position = view.inverse().w_axis().truncate();
         // Do I need to rebuild that everyframe?
-        let mut sorted_primitives: HashMap<MaterialId, Vec<(Rc<Tess>, Transform)>> =
-            HashMap::with_capacity(10);
+        // BTreeMap and entities sorted by id so that the draw order is the same between runs.
+        // Layer first so that the layers are drawn in order.
+        let mut sorted_primitives: SortedPrimitives = BTreeMap::new();
 
         let mut mesh_manager = resources.fetch_mut::<AssetManager<Mesh>>().unwrap();
-        for (_, (t, render)) in world.query::<(&Transform, &Render)>().iter() {
-            match mesh_manager.get(&Handle(render.mesh.clone())) {
+        let mut query = world.query::<(
+            &Transform,
+            Option<&PreviousTransform>,
+            &Render,
+            Option<&RenderLayer>,
+        )>();
+        let mut to_render: Vec<_> = query.iter().collect();
+        to_render.sort_by_key(|(e, _)| e.to_bits());
+        for (_, (t, previous, render, layer)) in to_render {
+            let t = render_transform(t, previous, interpolation);
+            let layer = layer.copied().unwrap_or_default();
+            let mesh_asset = mesh_manager.get(&Handle(render.mesh.clone())).map(|asset| {
+                if asset.is_error() {
+                    mesh_manager.get(&self.default_mesh_handle).unwrap_or(asset)
+                } else {
+                    asset
+                }
+            });
+            match mesh_asset {
                 Some(asset) => asset.execute(|m| {
                     for p in m.primitives.iter() {
-                        if sorted_primitives.contains_key(&p.material) {
+                        let key = (layer, p.material.clone());
+                        if sorted_primitives.contains_key(&key) {
                             sorted_primitives
-                                .get_mut(&p.material)
+                                .get_mut(&key)
                                 .unwrap()
-                                .push((Rc::clone(&p.tess), *t))
+                                .push((Rc::clone(&p.tess), t))
                         } else {
                             // TODO maybe don't do that. Keep keys populated and just reset the vec at the end of the frame?
-                            sorted_primitives
-                                .insert(p.material.clone(), vec![(Rc::clone(&p.tess), *t)]);
+                            sorted_primitives.insert(key, vec![(Rc::clone(&p.tess), t)]);
                         }
                     }
                 }),
@@ -217,7 +259,12 @@ impl PbrRenderer {
         }
 
         let mut material_manager = resources.fetch_mut::<AssetManager<Material>>().unwrap();
-        for (material_id, primitives) in sorted_primitives {
+        for ((layer, material_id), primitives) in sorted_primitives {
+            let render_state = if layer.is_overlay() {
+                RenderState::default().set_depth_test(None)
+            } else {
+                RenderState::default()
+            };
             let material_handle_str = material_id
                 .map(|m| m.clone())
                 .unwrap_or(self.default_material_handle.0.clone());
@@ -239,8 +286,19 @@ impl PbrRenderer {
             };
 
             material_asset.execute(|material| {
-                self.shaders.add_shader(material.shader_flags);
-                let shader = self.shaders.shaders.get(&material.shader_flags).unwrap();
+                let shader_flags = if self.environment.is_some() {
+                    material.shader_flags | ShaderFlags::HAS_ENVIRONMENT_MAP
+                } else {
+                    material.shader_flags
+                };
+                let shader = match self
+                    .shaders
+                    .resolve(shader_flags)
+                    .and_then(|flags| self.shaders.shaders.get(&flags))
+                {
+                    Some(shader) => shader,
+                    None => return,
+                };
 
                 shd_gate.shade(&shader, |iface, mut rdr_gate| {
                     // Now bind all uniforms.
@@ -264,6 +322,21 @@ impl PbrRenderer {
                     iface
                         .u_metallic_roughness_values
                         .update(material.metallic_roughness_values);
+                    // Bound before the material textures and kept until the end of the draw
+                    // calls so that the texture units are not reused.
+                    let environment = self.environment.as_ref().map(|env| {
+                        (
+                            pipeline.bind_texture(&env.irradiance),
+                            pipeline.bind_texture(&env.specular),
+                            env,
+                        )
+                    });
+                    if let Some((irradiance, specular, env)) = &environment {
+                        iface.u_irradiance_sampler.update(irradiance);
+                        iface.u_specular_env_sampler.update(specular);
+                        iface.u_max_specular_level.update(env.max_specular_level);
+                        iface.u_environment_intensity.update(env.intensity);
+                    }
                     self.bind_textures(pipeline, &iface, material);
                     if let Some((_, light)) = world.query::<&AmbientLight>().iter().next() {
                         iface
@@ -276,7 +349,7 @@ impl PbrRenderer {
                     }
                     for (tess, t) in &primitives {
                         iface.model.update(t.to_model().to_cols_array_2d());
-                        rdr_gate.render(&RenderState::default(), |mut tess_gate| {
+                        rdr_gate.render(&render_state, |mut tess_gate| {
                             tess_gate.render(&**tess);
                         });
                     }
@@ -5,10 +5,60 @@ use super::{
 use crate::render::mesh::material::Material;
 use crate::render::mesh::scene::{Assets, MaterialId};
 use crate::render::mesh::ImportData;
+#[allow(unused_imports)]
+use log::{debug, info, warn};
 use luminance::tess::{Mode, Tess, TessBuilder};
 use luminance_glfw::GlfwSurface;
 use std::rc::Rc;
 
+/// Used instead of the normals that cannot be normalized.
+const DEFAULT_NORMAL: [f32; 3] = [0.0, 1.0, 0.0];
+/// Tolerance on the length of the imported normals.
+const NORMAL_LENGTH_TOLERANCE: f32 = 1e-3;
+
+/// Number of vertex attributes that were invalid in the imported file and had to be fixed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportReport {
+    pub vertices: usize,
+    pub fixed_normals: usize,
+    pub fixed_tex_coords: usize,
+}
+
+impl ImportReport {
+    pub fn has_fixes(&self) -> bool {
+        self.fixed_normals > 0 || self.fixed_tex_coords > 0
+    }
+}
+
+/// Normalize the normal. Zero-length or non-finite normals are replaced by `DEFAULT_NORMAL`.
+/// Return true if the normal was modified.
+fn sanitize_normal(normal: &mut [f32; 3]) -> bool {
+    let length = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+    if !length.is_finite() || length < std::f32::EPSILON {
+        *normal = DEFAULT_NORMAL;
+        true
+    } else if (length - 1.0).abs() > NORMAL_LENGTH_TOLERANCE {
+        for c in normal.iter_mut() {
+            *c /= length;
+        }
+        true
+    } else {
+        false
+    }
+}
+
+/// Replace the NaN or infinite coordinates by 0. Return true if the coordinates were modified.
+fn sanitize_tex_coord(uv: &mut [f32; 2]) -> bool {
+    let mut fixed = false;
+    for c in uv.iter_mut() {
+        if !c.is_finite() {
+            *c = 0.0;
+            fixed = true;
+        }
+    }
+    fixed
+}
+
 /// Smallest unit in gltf. Contains the vertices,
 pub struct Primitive {
     pub tess: Rc<Tess>,
@@ -21,6 +71,7 @@ impl Primitive {
         primitive: gltf::Primitive,
         import_data: &ImportData,
         assets: &mut Assets,
+        report: &mut ImportReport,
     ) -> Self {
         let buffers = &import_data.1;
         let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
@@ -32,9 +83,13 @@ impl Primitive {
                 ..Vertex::default()
             })
             .collect::<Vec<_>>();
+        report.vertices += vertices.len();
 
         if let Some(normals) = reader.read_normals() {
-            for (i, normal) in normals.enumerate() {
+            for (i, mut normal) in normals.enumerate() {
+                if sanitize_normal(&mut normal) {
+                    report.fixed_normals += 1;
+                }
                 vertices[i].normal = VertexNormal::new(normal);
             }
         }
@@ -57,7 +112,10 @@ impl Primitive {
             if set > 1 {
                 break; //only supports mesh and primitive UV
             }
-            for (i, uv) in texture_coords.into_f32().enumerate() {
+            for (i, mut uv) in texture_coords.into_f32().enumerate() {
+                if sanitize_tex_coord(&mut uv) {
+                    report.fixed_tex_coords += 1;
+                }
                 match set {
                     0 => vertices[i].tex_coord_0 = VertexTexCoord0::new(uv),
                     1 => vertices[i].tex_coord_1 = VertexTexCoord1::new(uv),
@@ -84,8 +142,7 @@ impl Primitive {
         let material = primitive.material().name().map(|n| n.to_string());
         // Load material if not yet present.
         if !assets.materials.contains_key(&material) {
-            let new_material =
-                Material::from_gltf(surface, &primitive.material(), import_data, assets);
+            let new_material = Material::from_gltf(surface, &primitive.material(), import_data);
 
             assets.materials.insert(
                 primitive.material().name().map(|n| n.to_string()),
@@ -106,3 +163,41 @@ impl Primitive {
         Self { tess, material }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fix_normals() {
+        let mut normal = [0.0, 2.0, 0.0];
+        assert!(sanitize_normal(&mut normal));
+        assert_eq!([0.0, 1.0, 0.0], normal);
+
+        let mut normal = [0.0, 0.0, 0.0];
+        assert!(sanitize_normal(&mut normal));
+        assert_eq!(DEFAULT_NORMAL, normal);
+
+        let mut normal = [std::f32::NAN, 1.0, 0.0];
+        assert!(sanitize_normal(&mut normal));
+        assert_eq!(DEFAULT_NORMAL, normal);
+
+        let mut normal = [1.0, 0.0, 0.0];
+        assert!(!sanitize_normal(&mut normal));
+        assert_eq!([1.0, 0.0, 0.0], normal);
+    }
+
+    #[test]
+    fn fix_tex_coords() {
+        let mut uv = [std::f32::NAN, 0.5];
+        assert!(sanitize_tex_coord(&mut uv));
+        assert_eq!([0.0, 0.5], uv);
+
+        let mut uv = [std::f32::INFINITY, std::f32::NEG_INFINITY];
+        assert!(sanitize_tex_coord(&mut uv));
+        assert_eq!([0.0, 0.0], uv);
+
+        let mut uv = [0.25, 1.0];
+        assert!(!sanitize_tex_coord(&mut uv));
+    }
+}
@@ -1,20 +1,20 @@
 //! A scene is made of nodes, which are made of meshes..
 //! Nodes have their own transform but they can also have children nodes.
+//!
+//! The scene is not rendered directly. Its node hierarchy is converted to entities with
+//! `Scene::spawn_entities` and rendered by the PBR renderer.
 
-use crate::ecs::Transform;
-use crate::render::lighting::{AmbientLight, DirectionalLight};
+use crate::assets::material::Material as MaterialAsset;
+use crate::assets::{AssetManager, Handle};
+use crate::ecs::{Name, Transform};
 use crate::render::mesh::material::Material;
 use crate::render::mesh::mesh::Mesh;
-use crate::render::mesh::shaders::PbrShaders;
 use crate::render::mesh::ImportData;
-use crate::render::mesh::PbrShaderInterface;
 use crate::render::Render;
-use luminance::context::GraphicsContext;
-use luminance::pipeline::{Pipeline, ShadingGate};
-use luminance::render_state::RenderState;
-use luminance::shader::program::ProgramInterface;
+use crate::transform::{HasChildren, HasParent, LocalTransform};
+use hecs::EntityBuilder;
 use luminance_glfw::GlfwSurface;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub type MeshId = String;
 pub type MaterialId = Option<String>; // None is the default material.
@@ -25,29 +25,38 @@ pub struct Scene {
 }
 
 /// All the assets for the scene :)
-/// meshes, shaders, materials.
+/// meshes, materials. The shaders are the ones of the PBR renderer.
 pub struct Assets {
-    pub shaders: PbrShaders,
     pub materials: HashMap<MaterialId, Material>,
     pub meshes: HashMap<MeshId, Mesh>,
 }
 
-impl Default for Assets {
-    fn default() -> Self {
+impl Assets {
+    pub fn new() -> Self {
         let mut materials = HashMap::new();
         materials.insert(None, Material::default());
         Self {
-            shaders: PbrShaders::new(),
             materials,
             meshes: HashMap::new(),
         }
     }
 }
 
-impl Scene {
-    pub fn check_updates(&mut self) {
-        self.assets.shaders.update();
+/// `name` if it is not taken, otherwise `name` followed by the first free number.
+fn unique_name<F>(name: &str, is_taken: F) -> String
+where
+    F: Fn(&str) -> bool,
+{
+    if !is_taken(name) {
+        return name.to_string();
     }
+    (1..)
+        .map(|i| format!("{}_{}", name, i))
+        .find(|n| !is_taken(n))
+        .unwrap()
+}
+
+impl Scene {
     //    pub fn add_fake_material(&mut self, surface: &mut GlfwSurface) {
     //        //        let material = Material::from_textures(
     //        //            surface,
@@ -77,7 +86,7 @@ impl Scene {
     //    }
 
     pub fn from_gltf(surface: &mut GlfwSurface, scene: &gltf::Scene, data: &ImportData) -> Self {
-        let mut assets = Assets::default();
+        let mut assets = Assets::new();
         let nodes = scene
             .nodes()
             .map(|node| Node::from_gltf(surface, &node, data, &mut assets))
@@ -85,183 +94,80 @@ impl Scene {
         Self { nodes, assets }
     }
 
-    pub fn render<S>(
-        &self,
-        pipeline: &Pipeline,
-        shd_gate: &mut ShadingGate<S>,
-        projection: &glam::Mat4,
-        view: &glam::Mat4,
-        world: &hecs::World,
-        camera_position: glam::Vec3,
-    ) where
-        S: GraphicsContext,
-    {
-        // TODO Need to update the internal graph of the renderer. Need to make an internal graph
-        // where things are sorted by material. Then, every frame tag the transforms that are changed
-        // as dirty to update the graph.
-        for (_, (t, r)) in world.query::<(&Transform, &Render)>().iter() {
-            if let Some(mesh) = self.assets.meshes.get(&r.mesh) {
-                for primitive in mesh.primitives.iter() {
-                    let material = self
-                        .assets
-                        .materials
-                        .get(&primitive.material)
-                        .unwrap_or(self.assets.materials.get(&None).unwrap());
-                    //let material = self.assets.materials.get(&primitive.material).unwrap();
-                    let shader = self
-                        .assets
-                        .shaders
-                        .shaders
-                        .get(&material.shader_flags)
-                        .unwrap();
-
-                    shd_gate.shade(&shader, |iface, mut rdr_gate| {
-                        iface.view.update(view.to_cols_array_2d());
-                        iface.projection.update(projection.to_cols_array_2d());
-                        iface.model.update(t.to_model().to_cols_array_2d());
-                        iface.u_camera.update(camera_position.into());
-
-                        self.bind_textures(pipeline, &iface, &material);
-                        if let Some((_, light)) = world.query::<&DirectionalLight>().iter().next() {
-                            iface.u_light_color.update(light.color.to_normalized());
-                            iface.u_light_direction.update(light.direction.into());
-                        } else {
-                            iface.u_light_color.update([1.0, 1.0, 1.0]);
-                            iface.u_light_direction.update([0.0, -1.0, 1.0]);
-                        }
-
-                        if let Some((_, light)) = world.query::<&AmbientLight>().iter().next() {
-                            iface
-                                .u_ambient_light_color
-                                .update(light.color.to_normalized());
-                            iface.u_ambient_light_intensity.update(light.intensity);
-                        } else {
-                            iface.u_ambient_light_color.update([1.0, 1.0, 1.0]);
-                            iface.u_ambient_light_intensity.update(0.3);
-                        }
-                        material.apply_uniforms(&iface);
-
-                        rdr_gate.render(&RenderState::default(), |mut tess_gate| {
-                            tess_gate.render(&(*primitive.tess));
-                        });
-                    });
-                }
+    /// Create the entities for the node hierarchy. The meshes and materials are moved to the
+    /// asset managers so that the entities are rendered by the PBR renderer like any other
+    /// entity. The primitives without material use the default material.
+    ///
+    /// The assets keep the name they have in the gltf file. When an asset with the same name
+    /// already exists, a number is added to the name instead of replacing it. Same for the
+    /// name of the root entity.
+    ///
+    /// All the nodes are attached to a new root entity, which is returned.
+    pub fn spawn_entities(
+        self,
+        world: &mut hecs::World,
+        mesh_manager: &mut AssetManager<Mesh>,
+        material_manager: &mut AssetManager<MaterialAsset>,
+        name: String,
+    ) -> hecs::Entity {
+        let mut material_ids = HashMap::new();
+        for (material_id, material) in self.assets.materials {
+            if let Some(material_id) = material_id {
+                let new_id = unique_name(&material_id, |n| {
+                    material_manager.get(&Handle(n.to_string())).is_some()
+                });
+                material_manager.insert(&new_id, MaterialAsset::from_imported(material));
+                material_ids.insert(material_id, new_id);
             }
         }
-    }
-
-    /// Need to do a big exhaustive match instead of using if lets here. If using if let, the binding
-    /// is overriden in the next if let.
-    fn bind_textures(
-        &self,
-        pipeline: &Pipeline,
-        iface: &ProgramInterface<PbrShaderInterface>,
-        material: &Material,
-    ) {
-        match (
-            &material.albedo_texture,
-            material.color_texture_coord_set,
-            &material.normal_texture,
-            material.normal_texture_coord_set,
-            material.normal_scale,
-            &material.roughness_metallic_texture,
-            material.roughness_metallic_texture_coord_set,
-        ) {
-            (Some(color_tex), Some(color_coord), None, None, None, None, None) => {
-                let color_tex = pipeline.bind_texture(&color_tex.texture);
-                iface.u_base_color_sampler.update(&color_tex);
-                iface.u_base_color_tex_coord.update(color_coord);
-            }
-            (
-                Some(color_tex),
-                Some(color_coord),
-                Some(normal_tex),
-                Some(normal_coord),
-                Some(normal_scale),
-                None,
-                None,
-            ) => {
-                let color_tex = pipeline.bind_texture(&color_tex.texture);
-                let normal_tex = pipeline.bind_texture(&normal_tex.texture);
-                iface.u_base_color_sampler.update(&color_tex);
-                iface.u_base_color_tex_coord.update(color_coord);
-                iface.u_normal_sampler.update(&normal_tex);
-                iface.u_normal_tex_coord.update(normal_coord);
-                iface.u_normal_scale.update(normal_scale);
-            }
-            (
-                Some(color_tex),
-                Some(color_coord),
-                Some(normal_tex),
-                Some(normal_coord),
-                Some(normal_scale),
-                Some(rm_tex),
-                Some(rm_coord),
-            ) => {
-                let color_tex = pipeline.bind_texture(&color_tex.texture);
-                let normal_tex = pipeline.bind_texture(&normal_tex.texture);
-                let rm_tex = pipeline.bind_texture(&rm_tex.texture);
-                iface.u_base_color_sampler.update(&color_tex);
-                iface.u_base_color_tex_coord.update(color_coord);
-                iface.u_normal_sampler.update(&normal_tex);
-                iface.u_normal_tex_coord.update(normal_coord);
-                iface.u_normal_scale.update(normal_scale);
-                iface.u_metallic_roughness_sampler.update(&rm_tex);
-                iface.u_metallic_roughness_tex_coord.update(rm_coord);
-            }
-            (
-                Some(color_tex),
-                Some(color_coord),
-                None,
-                None,
-                None,
-                Some(rm_tex),
-                Some(rm_coord),
-            ) => {
-                let color_tex = pipeline.bind_texture(&color_tex.texture);
-                let rm_tex = pipeline.bind_texture(&rm_tex.texture);
-                iface.u_base_color_sampler.update(&color_tex);
-                iface.u_base_color_tex_coord.update(color_coord);
 
-                iface.u_metallic_roughness_sampler.update(&rm_tex);
-                iface.u_metallic_roughness_tex_coord.update(rm_coord);
-            }
-            (None, None, Some(normal_tex), Some(normal_coord), Some(normal_scale), None, None) => {
-                let normal_tex = pipeline.bind_texture(&normal_tex.texture);
-                iface.u_normal_sampler.update(&normal_tex);
-                iface.u_normal_tex_coord.update(normal_coord);
-                iface.u_normal_scale.update(normal_scale);
+        let mut mesh_ids = HashMap::new();
+        for (mesh_id, mut mesh) in self.assets.meshes {
+            for primitive in &mut mesh.primitives {
+                primitive.material = primitive
+                    .material
+                    .as_ref()
+                    .and_then(|m| material_ids.get(m).cloned());
             }
-            (
-                None,
-                None,
-                Some(normal_tex),
-                Some(normal_coord),
-                Some(normal_scale),
-                Some(rm_tex),
-                Some(rm_coord),
-            ) => {
-                let normal_tex = pipeline.bind_texture(&normal_tex.texture);
-                let rm_tex = pipeline.bind_texture(&rm_tex.texture);
-                iface.u_normal_sampler.update(&normal_tex);
-                iface.u_normal_tex_coord.update(normal_coord);
-                iface.u_normal_scale.update(normal_scale);
-                iface.u_metallic_roughness_sampler.update(&rm_tex);
-                iface.u_metallic_roughness_tex_coord.update(rm_coord);
-            }
-            (None, None, None, None, None, Some(rm_tex), Some(rm_coord)) => {
-                let rm_tex = pipeline.bind_texture(&rm_tex.texture);
-                iface.u_metallic_roughness_sampler.update(&rm_tex);
-                iface.u_metallic_roughness_tex_coord.update(rm_coord);
-            }
-            _ => (),
+            let new_id = unique_name(&mesh_id, |n| {
+                mesh_manager.get(&Handle(n.to_string())).is_some()
+            });
+            mesh_manager.insert(&new_id, mesh);
+            mesh_ids.insert(mesh_id, new_id);
         }
+
+        let names: HashSet<String> = world
+            .query::<&Name>()
+            .iter()
+            .map(|(_, n)| n.0.clone())
+            .collect();
+        let name = unique_name(&name, |n| names.contains(n));
+
+        let root = world.spawn((
+            Transform::new(
+                glam::Vec3::zero(),
+                glam::Quat::identity(),
+                glam::Vec3::one(),
+            ),
+            Name(name),
+        ));
+        let children = self
+            .nodes
+            .into_iter()
+            .map(|node| node.spawn_entity(world, root, &mesh_ids))
+            .collect();
+        world
+            .insert_one(root, HasChildren { children })
+            .expect("Root entity should exist.");
+
+        root
     }
 }
 
 pub struct Node {
     pub transform: Transform,
     pub mesh_id: Option<MeshId>,
+    pub children: Vec<Node>,
 }
 
 impl Node {
@@ -295,6 +201,112 @@ impl Node {
             dirty: true,
         };
 
-        Self { transform, mesh_id }
+        let children = node
+            .children()
+            .map(|child| Node::from_gltf(surface, &child, data, assets))
+            .collect();
+
+        Self {
+            transform,
+            mesh_id,
+            children,
+        }
+    }
+
+    /// `mesh_ids` are the names of the meshes in the mesh asset manager.
+    fn spawn_entity(
+        self,
+        world: &mut hecs::World,
+        parent: hecs::Entity,
+        mesh_ids: &HashMap<MeshId, MeshId>,
+    ) -> hecs::Entity {
+        let mut builder = EntityBuilder::new();
+        let local_transform: LocalTransform = self.transform.into();
+        builder.add(local_transform);
+        builder.add(Transform::default()); // local transform will adjust that.
+        if let Some(mesh_id) = self.mesh_id {
+            builder.add(Render {
+                mesh: mesh_ids.get(&mesh_id).cloned().unwrap_or(mesh_id),
+                enabled: true,
+            });
+        }
+        builder.add(HasParent { entity: parent });
+        let entity = world.spawn(builder.build());
+
+        if !self.children.is_empty() {
+            let children = self
+                .children
+                .into_iter()
+                .map(|child| child.spawn_entity(world, entity, mesh_ids))
+                .collect();
+            world
+                .insert_one(entity, HasChildren { children })
+                .expect("Entity was just created.");
+        }
+
+        entity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::{Asset, Loader};
+
+    struct NoLoader;
+
+    impl<T> Loader<T> for NoLoader {
+        fn load(&mut self, _asset_name: &str) -> Asset<T> {
+            Asset::new()
+        }
+    }
+
+    #[test]
+    fn spawn_entities() {
+        let mut mesh_manager = AssetManager::<Mesh>::from_loader(Box::new(NoLoader));
+        let mut material_manager = AssetManager::<MaterialAsset>::from_loader(Box::new(NoLoader));
+        mesh_manager.insert("cube", Mesh::default());
+        material_manager.insert("metal", MaterialAsset::default());
+        let mut world = hecs::World::new();
+        world.spawn((Name("scene".to_string()),));
+
+        let mut assets = Assets::new();
+        assets.meshes.insert("cube".to_string(), Mesh::default());
+        assets
+            .materials
+            .insert(Some("metal".to_string()), Material::default());
+        let child = Node {
+            transform: Transform::default(),
+            mesh_id: None,
+            children: vec![],
+        };
+        let scene = Scene {
+            nodes: vec![Node {
+                transform: Transform::default(),
+                mesh_id: Some("cube".to_string()),
+                children: vec![child],
+            }],
+            assets,
+        };
+        let root = scene.spawn_entities(
+            &mut world,
+            &mut mesh_manager,
+            &mut material_manager,
+            "scene".to_string(),
+        );
+
+        // the names were taken so the imported ones are renamed.
+        assert_eq!("scene_1", world.get::<Name>(root).unwrap().0);
+        assert!(mesh_manager.is_loaded(&Handle("cube_1".to_string())));
+        assert!(material_manager.is_loaded(&Handle("metal_1".to_string())));
+
+        let children = world.get::<HasChildren>(root).unwrap().children.clone();
+        assert_eq!(1, children.len());
+        let node = children[0];
+        assert_eq!(root, world.get::<HasParent>(node).unwrap().entity);
+        assert_eq!("cube_1", world.get::<Render>(node).unwrap().mesh);
+        let grandchildren = world.get::<HasChildren>(node).unwrap().children.clone();
+        assert_eq!(1, grandchildren.len());
+        assert!(world.get::<Render>(grandchildren[0]).is_err());
     }
 }
@@ -2,11 +2,16 @@
 //! Shader file is used, but depending whether a material has some texture, different
 //! part of the shader will be used. This is done by using defines in the shader files.
 
+use crate::paths::Paths;
 use crate::render::mesh::PbrShaderInterface;
+use crate::render::shaders::{preprocess, ShaderError};
+#[allow(unused_imports)]
+use log::{error, info};
 use luminance::shader::program::Program;
 use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::path::Path;
 use std::sync::mpsc::Receiver;
 
 bitflags! {
@@ -15,6 +20,8 @@ bitflags! {
         const HAS_COLOR_TEXTURE = 0b0000001;
         const HAS_NORMAL_TEXTURE = 0b0000010;
         const HAS_ROUGHNESS_METALLIC_MAP = 0b0000100;
+        /// Set by the renderer, not the material, when image based lighting is available.
+        const HAS_ENVIRONMENT_MAP = 0b0001000;
     }
 }
 
@@ -41,6 +48,10 @@ impl ShaderFlags {
             defines.push("HAS_ROUGHNESS_METALLIC_MAP".to_string());
         }
 
+        if self.contains(ShaderFlags::HAS_ENVIRONMENT_MAP) {
+            defines.push("HAS_ENVIRONMENT_MAP".to_string());
+        }
+
         defines
     }
 }
@@ -50,51 +61,91 @@ impl ShaderFlags {
 pub struct PbrShaders {
     pub shaders: HashMap<ShaderFlags, Program<super::VertexSemantics, (), PbrShaderInterface>>,
 
+    /// Files included by the PBR shaders, relative to the shaders directory.
+    includes: Vec<String>,
+    /// Flags whose program does not compile. They are tried again after a reload.
+    failed: HashSet<ShaderFlags>,
     rx: Receiver<Result<notify::Event, notify::Error>>,
     _watcher: RecommendedWatcher,
-}
-
-fn get_program_path(program_name: &str) -> String {
-    format!("{}{}", std::env::var("ASSET_PATH").unwrap(), program_name)
+    paths: Paths,
 }
 
 impl PbrShaders {
-    pub fn new() -> Self {
+    pub fn new(paths: &Paths) -> Self {
         let (tx, rx) = std::sync::mpsc::channel();
 
         let mut watcher: RecommendedWatcher =
             Watcher::new_immediate(move |res| tx.send(res).unwrap()).unwrap();
 
+        // The whole shaders directory is watched as the PBR shaders can include files from it.
         watcher
-            .watch(get_program_path("shaders/pbr"), RecursiveMode::Recursive)
+            .watch(paths.asset("shaders/"), RecursiveMode::Recursive)
             .unwrap();
 
         Self {
             shaders: HashMap::default(),
+            includes: vec![],
+            failed: HashSet::new(),
             rx,
             _watcher: watcher,
+            paths: paths.clone(),
         }
     }
     /// Will compile the shaders with the given flags and store it. If it already exists, this
-    /// is a no-op
-    pub fn add_shader(&mut self, flags: ShaderFlags) {
+    /// is a no-op. The flags that do not compile are remembered until the next reload.
+    pub fn add_shader(&mut self, flags: ShaderFlags) -> Result<(), ShaderError> {
         if self.shaders.contains_key(&flags) {
-            return;
-        } else {
-            let shader = PbrShaders::load_with_defines(flags.to_defines());
-            self.shaders.insert(flags, shader);
+            return Ok(());
+        }
+
+        match PbrShaders::load_with_defines(&self.paths, flags.to_defines()) {
+            Ok((shader, includes)) => {
+                self.includes = includes;
+                self.shaders.insert(flags, shader);
+                Ok(())
+            }
+            Err(e) => {
+                self.failed.insert(flags);
+                Err(e)
+            }
+        }
+    }
+
+    /// Flags of the program to use for the given flags, compiled the first time it is needed.
+    /// When it does not compile, the error is logged once and the program without flags (or
+    /// any other compiled one) is used instead. None if no program compiles.
+    ///
+    /// The program is then taken from `shaders`, so that `self` is not borrowed mutably while
+    /// shading.
+    pub fn resolve(&mut self, flags: ShaderFlags) -> Option<ShaderFlags> {
+        for f in &[flags, ShaderFlags::empty()] {
+            if self.failed.contains(f) {
+                continue;
+            }
+            match self.add_shader(*f) {
+                Ok(()) => return Some(*f),
+                Err(e) => error!("{} with {:?}, using another program", e, f),
+            }
         }
+
+        self.shaders.keys().next().copied()
     }
 
     fn load_with_defines(
+        paths: &Paths,
         defines: Vec<String>,
-    ) -> Program<super::VertexSemantics, (), PbrShaderInterface> {
+    ) -> Result<(super::DeferredSceneProgram, Vec<String>), ShaderError> {
+        let vs_path = paths.asset("shaders/pbr/pbr_vs.glsl");
+        let fs_path = paths.asset("shaders/pbr/pbr_fs.glsl");
         let vs =
-            fs::read_to_string(std::env::var("ASSET_PATH").unwrap() + "shaders/pbr/pbr_vs.glsl")
-                .expect("Could not load the PBR vertex shader");
+            fs::read_to_string(&vs_path).map_err(|e| ShaderError::IoError(vs_path.clone(), e))?;
         let fs =
-            fs::read_to_string(std::env::var("ASSET_PATH").unwrap() + "shaders/pbr/pbr_fs.glsl")
-                .expect("Could not load the PBR fragment shader");
+            fs::read_to_string(&fs_path).map_err(|e| ShaderError::IoError(fs_path.clone(), e))?;
+        let mut includes = vec![];
+        let vs = preprocess(paths, &vs, &mut includes)?;
+        let mut fs_includes = vec![];
+        let fs = preprocess(paths, &fs, &mut fs_includes)?;
+        includes.extend(fs_includes);
 
         let mut final_fs = String::new();
         for d in defines {
@@ -104,26 +155,48 @@ impl PbrShaders {
         }
         final_fs.push_str(&fs);
 
-        Program::from_strings(None, &vs, None, &final_fs)
-            .unwrap()
-            .ignore_warnings()
+        let program = Program::from_strings(None, &vs, None, &final_fs)
+            .map_err(|e| ShaderError::CompilationError(vs_path, fs_path, e))?
+            .ignore_warnings();
+        Ok((program, includes))
     }
+
+    /// Compile all the shaders again. A shader that does not compile anymore keeps its
+    /// previous program. The ones that did not compile will be tried again when used.
     pub fn reload(&mut self) {
+        self.failed.clear();
         for (k, v) in &mut self.shaders {
-            let new_shader = PbrShaders::load_with_defines(k.to_defines());
-            *v = new_shader;
+            match PbrShaders::load_with_defines(&self.paths, k.to_defines()) {
+                Ok((new_shader, includes)) => {
+                    *v = new_shader;
+                    self.includes = includes;
+                }
+                Err(e) => error!("{} with {:?}, keeping the previous program", e, k),
+            }
         }
     }
 
+    /// True if the file is one of the PBR shaders or is included by them.
+    fn depends_on(&self, path: &Path) -> bool {
+        path.parent()
+            .map(|p| p.ends_with("shaders/pbr"))
+            .unwrap_or(false)
+            || self
+                .includes
+                .iter()
+                .any(|i| path.ends_with(Path::new("shaders").join(i)))
+    }
+
     pub fn update(&mut self) {
         let mut should_reload = false;
-        for res in &self.rx.try_recv() {
-            match res {
-                Ok(Event {
-                    kind: EventKind::Modify(..),
-                    ..
-                }) => should_reload = true,
-                _ => (),
+        while let Ok(res) = self.rx.try_recv() {
+            if let Ok(Event {
+                kind: EventKind::Modify(..),
+                paths,
+                ..
+            }) = res
+            {
+                should_reload |= paths.iter().any(|p| self.depends_on(p));
             }
         }
 
@@ -16,23 +16,28 @@ pub mod shaders;
 pub mod skybox;
 pub mod sprite;
 pub mod text;
+pub mod texture;
+pub mod tracer;
 use crate::camera::Camera;
 use crate::colors::RgbColor;
-use crate::ecs::Transform;
+use crate::ecs::{render_transform, PreviousTransform, Transform};
 use crate::editor::Editor;
 use crate::event::GameEvent;
 use crate::gameplay::player::{MainPlayer, Player, PlayerState};
+use crate::gameplay::ui::HudConfig;
 use crate::net::snapshot::Deltable;
+use crate::paths::Paths;
 use crate::render::assets::AssetManager;
 use crate::render::billboard::BillboardRenderer;
-use crate::render::debug::DebugRenderer;
-use crate::render::mesh::deferred::DeferredRenderer;
+use crate::render::debug::{DebugRenderer, GridConfig};
+use crate::render::mesh::environment::{EnvironmentConfig, EnvironmentMap};
 use crate::render::mesh::PbrRenderer;
 use crate::render::particle::ParticleSystem;
 use crate::render::shaders::Shaders;
 use crate::render::skybox::SkyboxRenderer;
 use crate::render::sprite::SpriteRenderer;
 use crate::render::text::TextRenderer;
+use crate::render::tracer::TracerRenderer;
 use crate::resources::Resources;
 use glyph_brush::{GlyphBrush, GlyphBrushBuilder};
 use hecs::World;
@@ -54,6 +59,18 @@ pub struct Render {
     pub enabled: bool,
 }
 
+/// Order in which the entities are drawn. Layers are drawn in increasing order and entities
+/// without this component are in layer 0. Positive layers are drawn without depth test so that
+/// they are always visible on top of the lower layers (markers, selection highlights...).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RenderLayer(pub i32);
+
+impl RenderLayer {
+    pub fn is_overlay(self) -> bool {
+        self.0 > 0
+    }
+}
+
 impl Deltable for Render {
     type Delta = Render;
 
@@ -119,9 +136,9 @@ pub struct Renderer {
     text_renderer: TextRenderer,
     _billboard_renderer: BillboardRenderer,
     debug_renderer: DebugRenderer,
+    tracer_renderer: TracerRenderer,
     particle_renderer: ParticleSystem,
     _skybox_renderer: SkyboxRenderer,
-    //deferred_pbr_renderer: DeferredRenderer,
     pbr_renderer: PbrRenderer,
     backbuffer: Framebuffer<Dim2, (), ()>,
     // offscreen_buffer: OffscreenBuffer,
@@ -135,17 +152,68 @@ pub struct Renderer {
     rdr_id: ReaderId<GameEvent>,
 
     debug: bool,
+    /// The grid is only useful when moving freely in the scene.
+    show_grid: bool,
+    /// Position between the previous and current physics states at which the meshes are
+    /// rendered. See `set_interpolation`.
+    interpolation: f32,
+    /// Last crosshair sprite that was loaded, or that failed to load. The sprite is only
+    /// loaded again when the HUD config uses another one.
+    crosshair_sprite: Option<String>,
+    config: RenderConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RenderConfig {
     sky_color: RgbColor,
+
+    /// Maximum number of textures/meshes to upload to the GPU per frame. No limit if not set.
+    /// 0 is treated as 1.
+    #[serde(default)]
+    pub upload_budget: Option<usize>,
+
+    /// Ground grid of the free and editor modes.
+    #[serde(default)]
+    pub grid: GridConfig,
+
+    /// The subsystems can be disabled to find which one is causing a performance or visual
+    /// issue. All of them are enabled by default.
+    #[serde(default = "enabled")]
+    pub enable_particles: bool,
+    #[serde(default = "enabled")]
+    pub enable_text: bool,
+    #[serde(default = "enabled")]
+    pub enable_sprites: bool,
+    /// Colliders, debug lines and ground grid.
+    #[serde(default = "enabled")]
+    pub enable_debug: bool,
+
+    /// Image based lighting for the PBR materials. Flat ambient light if not set.
+    #[serde(default)]
+    pub environment: Option<EnvironmentConfig>,
+
+    /// Anisotropic filtering level of the material textures that do not set their own in their
+    /// sampler.
+    #[serde(default)]
+    pub anisotropy: Option<f32>,
+}
+
+fn enabled() -> bool {
+    true
 }
 
 impl Default for RenderConfig {
     fn default() -> Self {
         Self {
             sky_color: RgbColor::new(0, 0, 0),
+            upload_budget: None,
+            grid: GridConfig::default(),
+            enable_particles: true,
+            enable_text: true,
+            enable_sprites: true,
+            enable_debug: true,
+            environment: None,
+            anisotropy: None,
         }
     }
 }
@@ -156,21 +224,31 @@ impl Renderer {
             .fetch::<RenderConfig>()
             .and_then(|f| Some((*f).clone()))
             .unwrap_or_default();
+        let paths = resources.fetch::<Paths>().unwrap().clone();
         let mut glyph_brush = GlyphBrushBuilder::using_font_bytes(DEJA_VU).build();
-        //let deferred_pbr_renderer = DeferredRenderer::new(surface);
-        let pbr_renderer = PbrRenderer::new();
+        let environment = render_config.environment.as_ref().and_then(|config| {
+            match EnvironmentMap::load(surface, &paths, config) {
+                Ok(environment) => Some(environment),
+                Err(e) => {
+                    error!("Cannot load the environment map: {}", e);
+                    None
+                }
+            }
+        });
+        let pbr_renderer = PbrRenderer::new(&paths, environment);
         let particle_renderer = ParticleSystem::new(surface);
         let sprite_renderer = SpriteRenderer::new(surface);
         let billboard_renderer = BillboardRenderer::new(surface);
         let text_renderer = TextRenderer::new(surface, &mut glyph_brush);
-        let debug_renderer = DebugRenderer::new(surface);
+        let debug_renderer = DebugRenderer::new(surface, render_config.grid.clone());
+        let tracer_renderer = TracerRenderer::new(surface);
         let skybox_renderer = SkyboxRenderer::new(surface, render_config.sky_color);
         let backbuffer = surface.back_buffer().unwrap();
         let rdr_id = {
             let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
             chan.register_reader()
         };
-        let shaders = Shaders::new();
+        let shaders = Shaders::new(&paths);
 
         let projection = glam::Mat4::perspective_rh_gl(
             FOVY,
@@ -191,7 +269,7 @@ impl Renderer {
             _billboard_renderer: billboard_renderer,
             text_renderer,
             debug_renderer,
-            //deferred_pbr_renderer,
+            tracer_renderer,
             pbr_renderer,
             _skybox_renderer: skybox_renderer,
             backbuffer,
@@ -201,19 +279,30 @@ impl Renderer {
             glyph_brush,
             rdr_id,
             debug: true,
+            show_grid: false,
+            interpolation: 1.0,
+            crosshair_sprite: None,
+            config: render_config,
         }
     }
 
     // Update every frame. :)
     pub fn update(&mut self, world: &mut World, dt: Duration, resources: &mut Resources) {
         self.update_view_matrix(world);
+        // even when they are not drawn, so that the finished emitters are deleted.
         self.particle_renderer
             .update(world, dt.as_secs_f32(), resources);
+        tracer::update_tracers(world, dt.as_secs_f32());
     }
 
     pub fn update_view_matrix(&mut self, world: &World) {
-        for (_, (t, c)) in world.query::<(&Transform, &Camera)>().iter() {
+        for (_, (t, previous, c)) in world
+            .query::<(&Transform, Option<&PreviousTransform>, &Camera)>()
+            .iter()
+        {
             if c.active {
+                // same position as the meshes, otherwise the view stutters.
+                let t = render_transform(t, previous, self.interpolation);
                 let [x, y, z, w]: [f32; 4] = t.rotation.into();
                 let front = glam::vec3(
                     2.0 * (x * z + w * y),
@@ -230,13 +319,12 @@ impl Renderer {
         }
     }
 
-    pub fn update_text(&mut self, surface: &mut GlfwSurface, world: &World) {
+    pub fn update_text(&mut self, surface: &mut GlfwSurface, world: &World, hud: &HudConfig) {
+        if !self.config.enable_text {
+            return;
+        }
         self.text_renderer
-            .update_text(surface, world, &mut self.glyph_brush);
-    }
-
-    pub fn next_blending_mod_lighting(&mut self) {
-        //  self.deferred_pbr_renderer.next_blending_mode();
+            .update_text(surface, world, &mut self.glyph_brush, hud);
     }
 
     pub fn check_updates(
@@ -258,13 +346,32 @@ impl Renderer {
         };
 
         if should_update {
-            self.update_text(surface, world);
+            let hud = resources
+                .fetch::<HudConfig>()
+                .map(|hud| (*hud).clone())
+                .unwrap_or_default();
+            self.update_text(surface, world, &hud);
+
+            // The crosshair might have been changed from the editor.
+            let sprite = &hud.crosshair_style.sprite;
+            if self.crosshair_sprite.as_ref() != Some(sprite) {
+                if let (Some(mut assets), Some(paths)) = (
+                    resources.fetch_mut::<AssetManager>(),
+                    resources.fetch::<Paths>(),
+                ) {
+                    if let Err(e) = assets.load_sprite(surface, &paths, sprite) {
+                        error!("Cannot load crosshair {}: {}", sprite, e);
+                    }
+                    self.crosshair_sprite = Some(sprite.clone());
+                }
+            }
         }
 
         if let Some(editor) = editor {
             if let Some(to_load) = editor.gltf_to_load.take() {
-                //                self.deferred_pbr_renderer
-                //                    .load_gltf(surface, world, to_load);
+                if let Err(e) = mesh::import::import_gltf(surface, world, resources, &to_load) {
+                    error!("Cannot import {}: {}", to_load, e);
+                }
             }
         }
     }
@@ -273,6 +380,23 @@ impl Renderer {
         self.debug = !self.debug;
     }
 
+    pub fn set_grid_visible(&mut self, visible: bool) {
+        self.show_grid = visible;
+    }
+
+    /// Queue debug lines, boxes and spheres. They are drawn in the next frame if the debug
+    /// rendering is enabled.
+    pub fn debug_renderer(&mut self) -> &mut DebugRenderer {
+        &mut self.debug_renderer
+    }
+
+    /// Fraction of the physics step that has elapsed since the last step, between 0 and 1.
+    /// Entities with a `PreviousTransform` are rendered between their previous and current
+    /// transforms. Keep it to 1 when the physics is stepped once per frame.
+    pub fn set_interpolation(&mut self, alpha: f32) {
+        self.interpolation = alpha.max(0.0).min(1.0);
+    }
+
     pub fn render(
         &mut self,
         surface: &mut GlfwSurface,
@@ -281,7 +405,14 @@ impl Renderer {
         editor: Option<(&imgui_luminance::Renderer, &DrawData)>,
     ) {
         self.shaders.update();
-        // self.deferred_pbr_renderer.check_updates();
+        let draw_debug = self.debug && self.config.enable_debug;
+        if draw_debug {
+            self.debug_renderer
+                .draw_colliders(world, self.interpolation);
+            self.debug_renderer.upload_lines(surface);
+        } else {
+            self.debug_renderer.clear_lines();
+        }
 
         let color = [0.8, 0.8, 0.8, 1.];
 
@@ -296,6 +427,12 @@ impl Renderer {
 
         // I - Render to screen !
         // =========================================================================================
+        let hud = resources
+            .fetch::<HudConfig>()
+            .map(|hud| (*hud).clone())
+            .unwrap_or_default();
+        let sprite_cache = resources.fetch::<AssetManager>();
+
         let backbuffer = surface.back_buffer().unwrap();
         surface.pipeline_builder().pipeline(
             &backbuffer,
@@ -307,13 +444,6 @@ impl Renderer {
                 //                    &self.offscreen_buffer,
                 //                    &self.shaders,
                 //                );
-                //                self.deferred_pbr_renderer.render(
-                //                    &pipeline,
-                //                    &mut shd_gate,
-                //                    &self.projection,
-                //                    &self.view,
-                //                    world,
-                //                );
 
                 self.pbr_renderer.render(
                     &pipeline,
@@ -322,21 +452,57 @@ impl Renderer {
                     &self.view,
                     world,
                     resources,
+                    self.interpolation,
+                );
+
+                if self.show_grid && self.config.enable_debug {
+                    self.debug_renderer.render_grid(
+                        &self.projection,
+                        &self.view,
+                        &mut shd_gate,
+                        &self.shaders,
+                    );
+                }
+
+                self.tracer_renderer.render(
+                    &self.projection,
+                    &self.view,
+                    &mut shd_gate,
+                    world,
+                    &self.shaders,
                 );
 
-                if self.debug {
+                if draw_debug {
                     self.debug_renderer.render(
                         &self.projection,
                         &self.view,
                         &mut shd_gate,
-                        world,
                         &self.shaders,
                     );
                 }
 
                 if should_render_player_ui {
-                    self.text_renderer
-                        .render(&pipeline, &mut shd_gate, &self.shaders);
+                    // sprites above the text layer are drawn after the text.
+                    for &after_text in &[false, true] {
+                        if after_text && self.config.enable_text {
+                            self.text_renderer
+                                .render(&pipeline, &mut shd_gate, &self.shaders);
+                        }
+                        if !self.config.enable_sprites {
+                            continue;
+                        }
+                        if let Some(ref assets) = sprite_cache {
+                            self.sprite_renderer.render(
+                                &pipeline,
+                                &mut shd_gate,
+                                world,
+                                &assets.sprites,
+                                &self.shaders,
+                                &hud,
+                                after_text,
+                            );
+                        }
+                    }
                 }
 
                 if let Some((editor, draw_data)) = editor {
@@ -1,6 +1,4 @@
-use crate::camera::Camera;
 use crate::colors::RgbColor;
-use crate::ecs::Transform;
 use crate::event::GameEvent;
 use crate::render::shaders::Shaders;
 use crate::resources::Resources;
@@ -18,8 +16,10 @@ use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
 use shrev::EventChannel;
 
-#[derive(Debug, Clone, Copy)]
+/// Lifetime of a particle in seconds.
+const PARTICLE_LIFE: f32 = 1.0;
 
+#[derive(Debug, Clone, Copy)]
 struct Particle {
     life: f32,
     position: glam::Vec3,
@@ -41,7 +41,7 @@ impl Particle {
     }
 
     fn respawn(&mut self, origin: glam::Vec3, velocity: glam::Vec3) {
-        self.life = 1.0;
+        self.life = PARTICLE_LIFE;
         self.position = origin;
         self.velocity = velocity;
     }
@@ -71,9 +71,15 @@ pub struct ParticleEmitter {
     /// Color of the particle
     color: RgbColor,
 
-    /// How long does the emitter live (in seconds)
+    /// How long does the emitter live (in seconds). After that, the particles are not
+    /// respawned and the emitter is deleted once they are all dead.
     #[serde(default)]
     life: Option<f32>,
+
+    /// One-shot emitter: all the particles are emitted at once and never respawned. The
+    /// emitter is deleted once they are all dead.
+    #[serde(default)]
+    burst: bool,
 }
 
 impl ParticleEmitter {
@@ -91,55 +97,85 @@ impl ParticleEmitter {
             particle_number,
             color,
             life,
+            burst: false,
+        }
+    }
+
+    /// Emitter that emits all its particles at once, then is deleted when they are dead.
+    pub fn burst(position: Vec3, velocity: Vec3, particle_number: usize, color: RgbColor) -> Self {
+        Self {
+            burst: true,
+            ..Self::new(position, velocity, particle_number, color, None)
         }
     }
 
+    /// True when the emitter does not emit new particles anymore.
+    fn is_finished(&self) -> bool {
+        self.life.map(|life| life <= 0.0).unwrap_or(false)
+            || (self.burst && self.particles.len() >= self.particle_number)
+    }
+
+    /// Random origin and velocity for a new particle.
+    fn spawn_parameters(&self, rng: &mut impl Rng) -> (Vec3, Vec3) {
+        let pos_offset: f32 = rng.gen_range(-1.0, 1.0);
+        let vel_offset: Vec3 = Vec3::new(
+            rng.gen_range(-1.0, 1.0),
+            rng.gen_range(-1.0, 1.0),
+            rng.gen_range(-1.0, 1.0),
+        );
+        (
+            self.position + pos_offset * self.velocity.normalize(),
+            self.velocity + vel_offset,
+        )
+    }
+
     /// Update the position and velocity of all particles. If a particle is dead, respawn it :)
-    /// Return true if should despawn the particle emitter.
+    /// When the emitter is finished, the dead particles are removed instead.
+    /// Return false if the particle emitter should be despawned.
     fn update(&mut self, dt: f32) -> bool {
         let mut rng = rand::thread_rng();
+        let finished = self.is_finished();
 
-        for p in &mut self.particles {
-            if p.alive() {
-                p.update(9.8, dt);
-            } else {
-                let pos_offset: f32 = rng.gen_range(-1.0, 1.0);
-
-                let vel_offset: Vec3 = Vec3::new(
-                    rng.gen_range(-1.0, 1.0),
-                    rng.gen_range(-1.0, 1.0),
-                    rng.gen_range(-1.0, 1.0),
-                );
-
-                p.respawn(
-                    self.position + pos_offset * self.velocity.normalize(),
-                    self.velocity + vel_offset,
-                );
+        for i in 0..self.particles.len() {
+            if self.particles[i].alive() {
+                self.particles[i].update(9.8, dt);
+            } else if !finished {
+                let (origin, velocity) = self.spawn_parameters(&mut rng);
+                self.particles[i].respawn(origin, velocity);
             }
         }
+        if finished {
+            self.particles.retain(Particle::alive);
+            return !self.particles.is_empty();
+        }
 
-        if self.particles.len() < self.particle_number {
+        // bursts emit everything at once, the other emitters one particle per frame.
+        let to_emit = if self.burst {
+            self.particle_number.saturating_sub(self.particles.len())
+        } else {
+            self.particle_number.min(self.particles.len() + 1) - self.particles.len()
+        };
+        self.particles.reserve(to_emit);
+        for _ in 0..to_emit {
+            let (origin, velocity) = self.spawn_parameters(&mut rng);
             self.particles
-                .reserve(self.particle_number - self.particles.len());
-            let pos_offset: f32 = rng.gen_range(-1.0, 1.0);
-            let vel_offset: Vec3 = Vec3::new(
-                rng.gen_range(-1.0, 1.0),
-                rng.gen_range(-1.0, 1.0),
-                rng.gen_range(-1.0, 1.0),
-            );
-            self.particles.push(Particle::new(
-                self.position + pos_offset * self.velocity.normalize(),
-                self.velocity + vel_offset,
-                self.color,
-            ));
+                .push(Particle::new(origin, velocity, self.color));
         }
 
         // update life of emitter.
         if let Some(life) = self.life.as_mut() {
             *life -= dt;
-            *life > 0.0
-        } else {
-            true
+        }
+        true
+    }
+}
+
+/// Update the emitters and send a `GameEvent::Delete` for the ones that are done, so that the
+/// `GarbageCollector` removes them.
+pub fn update_emitters(world: &World, dt: f32, chan: &mut EventChannel<GameEvent>) {
+    for (e, emitter) in world.query::<&mut ParticleEmitter>().iter() {
+        if !emitter.update(dt) {
+            chan.single_write(GameEvent::Delete(e));
         }
     }
 }
@@ -172,11 +208,7 @@ impl ParticleSystem {
 
     pub fn update(&mut self, world: &mut World, dt: f32, resources: &mut Resources) {
         let mut chan = resources.fetch_mut::<EventChannel<GameEvent>>().unwrap();
-        for (e, emitter) in world.query::<&mut ParticleEmitter>().iter() {
-            if !emitter.update(dt) {
-                chan.single_write(GameEvent::Delete(e));
-            }
-        }
+        update_emitters(world, dt, &mut chan);
     }
 
     pub fn render<S>(
@@ -189,46 +221,91 @@ impl ParticleSystem {
     ) where
         S: GraphicsContext,
     {
-        let camera_pos = {
-            world
-                .query::<(&Camera, &Transform)>()
-                .iter()
-                .filter_map(
-                    |(_, (c, t))| {
-                        if c.active {
-                            Some(t.translation)
-                        } else {
-                            None
-                        }
-                    },
-                )
-                .next()
-        };
+        // the view is interpolated like the rendered entities.
+        let camera_position = view.inverse().w_axis().truncate();
 
-        if let Some(camera_position) = camera_pos {
-            shd_gate.shade(&shaders.particle_program, |iface, mut rdr_gate| {
-                iface.projection.update(projection.to_cols_array_2d());
-                iface.view.update(view.to_cols_array_2d());
-                iface.camera_position.update(camera_position.into());
-
-                for (_, emitter) in world.query::<&mut ParticleEmitter>().iter() {
-                    for p in &emitter.particles {
-                        iface.color.update(p.color.to_normalized());
-                        iface.center.update(p.position.into());
-                        iface.model.update(
-                            Mat4::from_scale_rotation_translation(
-                                glam::vec3(0.1, 0.1, 0.1),
-                                Quat::identity(),
-                                p.position,
-                            )
-                            .to_cols_array_2d(),
-                        );
-                        rdr_gate.render(&RenderState::default(), |mut tess_gate| {
-                            tess_gate.render(self.tess.slice(..));
-                        });
-                    }
+        shd_gate.shade(&shaders.particle_program, |iface, mut rdr_gate| {
+            iface.projection.update(projection.to_cols_array_2d());
+            iface.view.update(view.to_cols_array_2d());
+            iface.camera_position.update(camera_position.into());
+
+            for (_, emitter) in world.query::<&mut ParticleEmitter>().iter() {
+                for p in &emitter.particles {
+                    iface.color.update(p.color.to_normalized());
+                    iface.center.update(p.position.into());
+                    iface.model.update(
+                        Mat4::from_scale_rotation_translation(
+                            glam::vec3(0.1, 0.1, 0.1),
+                            Quat::identity(),
+                            p.position,
+                        )
+                        .to_cols_array_2d(),
+                    );
+                    rdr_gate.render(&RenderState::default(), |mut tess_gate| {
+                        tess_gate.render(self.tess.slice(..));
+                    });
                 }
-            });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameplay::delete::GarbageCollector;
+
+    #[test]
+    fn burst_emitter_is_deleted_after_its_particles() {
+        let mut resources = Resources::new();
+        resources.insert(EventChannel::<GameEvent>::new());
+        let mut gc = GarbageCollector::new(&mut resources);
+
+        let mut world = World::new();
+        let e = world.spawn((ParticleEmitter::burst(
+            Vec3::zero(),
+            Vec3::unit_y(),
+            10,
+            RgbColor::new(255, 0, 0),
+        ),));
+
+        let dt = 0.1;
+        update_emitters(
+            &world,
+            dt,
+            &mut resources.fetch_mut::<EventChannel<GameEvent>>().unwrap(),
+        );
+        gc.collect_without_physics(&mut world, &resources);
+        assert_eq!(10, world.get::<ParticleEmitter>(e).unwrap().particles.len());
+
+        // a bit more than the life of the particles.
+        for _ in 0..(PARTICLE_LIFE / dt) as usize + 5 {
+            update_emitters(
+                &world,
+                dt,
+                &mut resources.fetch_mut::<EventChannel<GameEvent>>().unwrap(),
+            );
+            gc.collect_without_physics(&mut world, &resources);
+        }
+        assert!(world.get::<ParticleEmitter>(e).is_err());
+    }
+
+    #[test]
+    fn emitter_without_life_is_kept() {
+        let mut chan = EventChannel::<GameEvent>::new();
+        let mut rdr_id = chan.register_reader();
+        let mut world = World::new();
+        world.spawn((ParticleEmitter::new(
+            Vec3::zero(),
+            Vec3::unit_y(),
+            10,
+            RgbColor::new(255, 0, 0),
+            None,
+        ),));
+
+        for _ in 0..50 {
+            update_emitters(&world, 0.1, &mut chan);
         }
+        assert_eq!(0, chan.read(&mut rdr_id).count());
     }
 }
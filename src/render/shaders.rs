@@ -1,37 +1,186 @@
 use super::sprite;
+use crate::paths::Paths;
 use crate::render::lighting::{AmbientLightProgram, DirectionalLightProgram, PointLightProgram};
 use crate::render::particle::ParticleShaderInterface;
 use crate::render::skybox::SkyboxProgram;
-use crate::render::{billboard, debug, text, VertexSementics};
+use crate::render::{billboard, debug, text, tracer, VertexSementics};
+#[allow(unused_imports)]
+use log::{error, info};
 use luminance::linear::M44;
-use luminance::shader::program::{Program, Uniform, UniformInterface};
+use luminance::shader::program::{Program, ProgramError, Uniform, UniformInterface};
 use luminance::vertex::Semantics;
 use luminance_derive::UniformInterface;
 use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Receiver;
+use thiserror::Error;
 
-fn load_program<P, S, U>(vs_path: P, fs_path: P) -> Program<S, (), U>
+#[derive(Debug, Error)]
+pub enum ShaderError {
+    #[error("Cannot read {0}: {1}")]
+    IoError(String, std::io::Error),
+
+    #[error("Invalid include, expected #include \"file.glsl\": {0}")]
+    InvalidInclude(String),
+
+    #[error("Shader compilation error for {0}/{1} = {2:?}")]
+    CompilationError(String, String, ProgramError),
+}
+
+/// Vertex and fragment shaders of a program, relative to the assets directory.
+type ShaderPaths = (&'static str, &'static str);
+
+const REGULAR_SHADERS: ShaderPaths = ("shaders/deferred_vs.glsl", "shaders/deferred_fs.glsl");
+const SPRITE_SHADERS: ShaderPaths = ("shaders/sprite_2_vs.glsl", "shaders/sprite_fs.glsl");
+const TEXT_SHADERS: ShaderPaths = ("shaders/text_vs.glsl", "shaders/text_fs.glsl");
+const BILLBOARD_SHADERS: ShaderPaths = ("shaders/billboard_vs.glsl", "shaders/billboard_fs.glsl");
+const DEBUG_SHADERS: ShaderPaths = ("shaders/debug_vs.glsl", "shaders/debug_fs.glsl");
+const DEBUG_LINES_SHADERS: ShaderPaths =
+    ("shaders/debug_lines_vs.glsl", "shaders/debug_lines_fs.glsl");
+const TRACER_SHADERS: ShaderPaths = ("shaders/tracer_vs.glsl", "shaders/tracer_fs.glsl");
+const COPY_SHADERS: ShaderPaths = ("shaders/copy-vs.glsl", "shaders/copy-fs.glsl");
+const PARTICLE_SHADERS: ShaderPaths = ("shaders/particle_vs.glsl", "shaders/particle_fs.glsl");
+const AMBIENT_SHADERS: ShaderPaths = ("shaders/copy-vs.glsl", "shaders/ambient_light_fs.glsl");
+const DIRECTIONAL_SHADERS: ShaderPaths =
+    ("shaders/copy-vs.glsl", "shaders/directional_light_fs.glsl");
+const POINT_LIGHT_SHADERS: ShaderPaths = ("shaders/copy-vs.glsl", "shaders/point_light_fs.glsl");
+const SKYBOX_SHADERS: ShaderPaths = ("shaders/copy-vs.glsl", "shaders/skybox_fs.glsl");
+
+/// Directory of the shaders, relative to the assets. The includes are resolved from there.
+const SHADERS_DIR: &str = "shaders";
+
+/// Files included by each program, relative to the shaders directory.
+type Includes = HashMap<ShaderPaths, Vec<String>>;
+
+/// True if one of the changed files is a shader of the program or a file that it includes.
+fn depends_on((vs, fs): ShaderPaths, included: &[String], changed: &[PathBuf]) -> bool {
+    changed.iter().any(|p| {
+        p.ends_with(vs)
+            || p.ends_with(fs)
+            || included
+                .iter()
+                .any(|i| p.ends_with(Path::new(SHADERS_DIR).join(i)))
+    })
+}
+
+fn read_shader(path: &str) -> Result<String, ShaderError> {
+    fs::read_to_string(path).map_err(|e| ShaderError::IoError(path.to_owned(), e))
+}
+
+/// Replace the `#include "file.glsl"` lines by the content of the file, relative to the
+/// shaders directory. The included files are appended to `included`.
+pub fn preprocess(
+    paths: &Paths,
+    source: &str,
+    included: &mut Vec<String>,
+) -> Result<String, ShaderError> {
+    resolve_includes(
+        source,
+        &mut |name| read_shader(&paths.asset(&format!("{}/{}", SHADERS_DIR, name))),
+        included,
+    )
+}
+
+/// A file is only included once per shader, which also protects against include cycles.
+fn resolve_includes<F>(
+    source: &str,
+    read: &mut F,
+    included: &mut Vec<String>,
+) -> Result<String, ShaderError>
 where
-    P: AsRef<Path>,
+    F: FnMut(&str) -> Result<String, ShaderError>,
+{
+    let mut output = String::with_capacity(source.len());
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if !trimmed.starts_with("#include") {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+
+        let name = trimmed["#include".len()..].trim();
+        if name.len() < 2 || !name.starts_with('"') || !name.ends_with('"') {
+            return Err(ShaderError::InvalidInclude(line.to_owned()));
+        }
+        let name = &name[1..name.len() - 1];
+        if included.iter().any(|i| i == name) {
+            continue;
+        }
+        included.push(name.to_owned());
+        let content = read(name)?;
+        output.push_str(&resolve_includes(&content, read, included)?);
+    }
+
+    Ok(output)
+}
+
+/// Compile the program. Return it with the files included by its shaders.
+fn try_load_program<S, U>(
+    paths: &Paths,
+    (vs_name, fs_name): ShaderPaths,
+) -> Result<(Program<S, (), U>, Vec<String>), ShaderError>
+where
+    S: Semantics,
+    U: UniformInterface,
+{
+    let vs_path = paths.asset(vs_name);
+    let fs_path = paths.asset(fs_name);
+    let mut included = vec![];
+    let vs = preprocess(paths, &read_shader(&vs_path)?, &mut included)?;
+    // the fragment shader needs its own copy of the files already in the vertex shader.
+    let mut fs_included = vec![];
+    let fs = preprocess(paths, &read_shader(&fs_path)?, &mut fs_included)?;
+    included.extend(fs_included);
+
+    let program = Program::from_strings(None, &vs, None, &fs)
+        .map_err(|e| ShaderError::CompilationError(vs_path, fs_path, e))?
+        .ignore_warnings();
+    Ok((program, included))
+}
+
+/// Load a program at startup. There is nothing to fall back to so an error is fatal.
+fn load_program<S, U>(
+    paths: &Paths,
+    shaders: ShaderPaths,
+    includes: &mut Includes,
+) -> Program<S, (), U>
+where
+    S: Semantics,
+    U: UniformInterface,
+{
+    let (program, included) = try_load_program(paths, shaders).unwrap_or_else(|e| panic!("{}", e));
+    includes.insert(shaders, included);
+    program
+}
+
+/// Load the program again if one of its shaders has changed. On error, the previous program
+/// is kept so that a typo while editing a shader does not crash the game.
+fn reload_program<S, U>(
+    program: &mut Program<S, (), U>,
+    paths: &Paths,
+    shaders: ShaderPaths,
+    changed: &[PathBuf],
+    includes: &mut Includes,
+) where
     S: Semantics,
     U: UniformInterface,
 {
-    let vs = fs::read_to_string(vs_path.as_ref())
-        .unwrap_or_else(|_| panic!("{:?}", vs_path.as_ref().display()));
-    let fs = fs::read_to_string(fs_path.as_ref())
-        .unwrap_or_else(|_| panic!("{:?}", fs_path.as_ref().display()));
-    Program::from_strings(None, &vs, None, &fs)
-        .unwrap_or_else(|e| {
-            panic!(
-                "Shader compilation error for {:?}/{:?} = {:?}",
-                vs_path.as_ref().display(),
-                fs_path.as_ref().display(),
-                e
-            )
-        })
-        .ignore_warnings()
+    let included = includes.entry(shaders).or_default();
+    if !depends_on(shaders, included, changed) {
+        return;
+    }
+
+    info!("Reloading {} / {}", shaders.0, shaders.1);
+    match try_load_program(paths, shaders) {
+        Ok((new_program, new_included)) => {
+            *program = new_program;
+            *included = new_included;
+        }
+        Err(e) => error!("{}, keeping the previous program", e),
+    }
 }
 
 #[derive(Debug, UniformInterface)]
@@ -58,6 +207,8 @@ pub struct Shaders {
     pub text_program: Program<text::VertexSemantics, (), text::ShaderInterface>,
     pub billboard_program: Program<(), (), billboard::ShaderInterface>,
     pub debug_program: Program<debug::VertexSemantics, (), debug::ShaderInterface>,
+    pub debug_lines_program: Program<debug::VertexSemantics, (), debug::LinesShaderInterface>,
+    pub tracer_program: Program<(), (), tracer::ShaderInterface>,
     pub copy_program: Program<(), (), super::CopyShaderInterface>,
     pub particle_program: Program<(), (), ParticleShaderInterface>,
     pub ambient_program: AmbientLightProgram,
@@ -65,62 +216,30 @@ pub struct Shaders {
     pub point_light_program: PointLightProgram,
     pub skybox_program: SkyboxProgram,
 
+    /// Files included by the programs. Used to know what to reload.
+    includes: Includes,
     rx: Receiver<Result<notify::Event, notify::Error>>,
     _watcher: RecommendedWatcher,
-}
-
-fn get_program_path(program_name: &str) -> String {
-    format!("{}{}", std::env::var("ASSET_PATH").unwrap(), program_name)
+    paths: Paths,
 }
 
 impl Shaders {
-    pub fn new() -> Self {
-        let regular_program: Program<VertexSementics, (), AxisShaderInterface> = load_program(
-            get_program_path("shaders/deferred_vs.glsl"),
-            get_program_path("shaders/deferred_fs.glsl"),
-        );
-        let sprite_program = load_program(
-            get_program_path("shaders/sprite_2_vs.glsl"),
-            get_program_path("shaders/sprite_fs.glsl"),
-        );
-        let text_program = load_program(
-            get_program_path("shaders/text_vs.glsl"),
-            get_program_path("shaders/text_fs.glsl"),
-        );
-        let billboard_program = load_program(
-            get_program_path("shaders/billboard_vs.glsl"),
-            get_program_path("shaders/billboard_fs.glsl"),
-        );
-
-        let debug_program = load_program(
-            get_program_path("shaders/debug_vs.glsl"),
-            get_program_path("shaders/debug_fs.glsl"),
-        );
-
-        let copy_program = load_program(
-            get_program_path("shaders/copy-vs.glsl"),
-            get_program_path("shaders/copy-fs.glsl"),
-        );
-        let particle_program = load_program(
-            get_program_path("shaders/particle_vs.glsl"),
-            get_program_path("shaders/particle_fs.glsl"),
-        );
-        let ambient_program = load_program(
-            get_program_path("shaders/copy-vs.glsl"),
-            get_program_path("shaders/ambient_light_fs.glsl"),
-        );
-        let directional_program = load_program(
-            get_program_path("shaders/copy-vs.glsl"),
-            get_program_path("shaders/directional_light_fs.glsl"),
-        );
-        let point_light_program = load_program(
-            get_program_path("shaders/copy-vs.glsl"),
-            get_program_path("shaders/point_light_fs.glsl"),
-        );
-        let skybox_program = load_program(
-            get_program_path("shaders/copy-vs.glsl"),
-            get_program_path("shaders/skybox_fs.glsl"),
-        );
+    pub fn new(paths: &Paths) -> Self {
+        let mut includes = Includes::new();
+        let regular_program: Program<VertexSementics, (), AxisShaderInterface> =
+            load_program(paths, REGULAR_SHADERS, &mut includes);
+        let sprite_program = load_program(paths, SPRITE_SHADERS, &mut includes);
+        let text_program = load_program(paths, TEXT_SHADERS, &mut includes);
+        let billboard_program = load_program(paths, BILLBOARD_SHADERS, &mut includes);
+        let debug_program = load_program(paths, DEBUG_SHADERS, &mut includes);
+        let debug_lines_program = load_program(paths, DEBUG_LINES_SHADERS, &mut includes);
+        let tracer_program = load_program(paths, TRACER_SHADERS, &mut includes);
+        let copy_program = load_program(paths, COPY_SHADERS, &mut includes);
+        let particle_program = load_program(paths, PARTICLE_SHADERS, &mut includes);
+        let ambient_program = load_program(paths, AMBIENT_SHADERS, &mut includes);
+        let directional_program = load_program(paths, DIRECTIONAL_SHADERS, &mut includes);
+        let point_light_program = load_program(paths, POINT_LIGHT_SHADERS, &mut includes);
+        let skybox_program = load_program(paths, SKYBOX_SHADERS, &mut includes);
 
         let (tx, rx) = std::sync::mpsc::channel();
 
@@ -133,7 +252,7 @@ impl Shaders {
             Watcher::new_immediate(move |res| tx.send(res).unwrap()).unwrap();
 
         watcher
-            .watch(get_program_path("shaders/"), RecursiveMode::Recursive)
+            .watch(paths.asset("shaders/"), RecursiveMode::Recursive)
             .unwrap();
 
         Self {
@@ -143,75 +262,207 @@ impl Shaders {
             billboard_program,
             copy_program,
             debug_program,
+            debug_lines_program,
+            tracer_program,
             particle_program,
             ambient_program,
             directional_program,
             point_light_program,
             skybox_program,
+            includes,
             rx,
             _watcher: watcher,
+            paths: paths.clone(),
         }
     }
 
     pub fn update(&mut self) {
-        let mut should_reload = false;
-        for res in &self.rx.try_recv() {
-            match res {
-                Ok(Event {
-                    kind: EventKind::Modify(..),
-                    ..
-                }) => should_reload = true,
-                _ => (),
+        let mut changed = vec![];
+        while let Ok(res) = self.rx.try_recv() {
+            if let Ok(Event {
+                kind: EventKind::Modify(..),
+                paths,
+                ..
+            }) = res
+            {
+                changed.extend(paths);
             }
         }
 
-        if should_reload {
-            self.regular_program = load_program(
-                get_program_path("shaders/deferred_vs.glsl"),
-                get_program_path("shaders/deferred_fs.glsl"),
-            );
-
-            self.sprite_program = load_program(
-                get_program_path("shaders/sprite_2_vs.glsl"),
-                get_program_path("shaders/sprite_fs.glsl"),
-            );
-            self.billboard_program = load_program(
-                get_program_path("shaders/billboard_vs.glsl"),
-                get_program_path("shaders/billboard_fs.glsl"),
-            );
-            self.text_program = load_program(
-                get_program_path("shaders/text_vs.glsl"),
-                get_program_path("shaders/text_fs.glsl"),
-            );
-            self.debug_program = load_program(
-                get_program_path("shaders/debug_vs.glsl"),
-                get_program_path("shaders/debug_fs.glsl"),
-            );
-
-            self.copy_program = load_program(
-                get_program_path("shaders/copy-vs.glsl"),
-                get_program_path("shaders/copy-fs.glsl"),
-            );
-            self.particle_program = load_program(
-                get_program_path("shaders/particle_vs.glsl"),
-                get_program_path("shaders/particle_fs.glsl"),
-            );
-            self.ambient_program = load_program(
-                get_program_path("shaders/copy-vs.glsl"),
-                get_program_path("shaders/ambient_light_fs.glsl"),
-            );
-            self.directional_program = load_program(
-                get_program_path("shaders/copy-vs.glsl"),
-                get_program_path("shaders/directional_light_fs.glsl"),
-            );
-            self.point_light_program = load_program(
-                get_program_path("shaders/copy-vs.glsl"),
-                get_program_path("shaders/point_light_fs.glsl"),
-            );
-            self.skybox_program = load_program(
-                get_program_path("shaders/copy-vs.glsl"),
-                get_program_path("shaders/skybox_fs.glsl"),
-            );
+        if changed.is_empty() {
+            return;
+        }
+
+        let paths = &self.paths;
+        reload_program(
+            &mut self.regular_program,
+            paths,
+            REGULAR_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.sprite_program,
+            paths,
+            SPRITE_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.text_program,
+            paths,
+            TEXT_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.billboard_program,
+            paths,
+            BILLBOARD_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.debug_program,
+            paths,
+            DEBUG_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.debug_lines_program,
+            paths,
+            DEBUG_LINES_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.tracer_program,
+            paths,
+            TRACER_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.copy_program,
+            paths,
+            COPY_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.particle_program,
+            paths,
+            PARTICLE_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.ambient_program,
+            paths,
+            AMBIENT_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.directional_program,
+            paths,
+            DIRECTIONAL_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.point_light_program,
+            paths,
+            POINT_LIGHT_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+        reload_program(
+            &mut self.skybox_program,
+            paths,
+            SKYBOX_SHADERS,
+            &changed,
+            &mut self.includes,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_only_dependent_programs() {
+        let changed = vec![PathBuf::from("/game/assets/shaders/ambient_light_fs.glsl")];
+        assert!(depends_on(AMBIENT_SHADERS, &[], &changed));
+        assert!(!depends_on(DIRECTIONAL_SHADERS, &[], &changed));
+
+        // the vertex shader is shared by all the lighting programs.
+        let changed = vec![PathBuf::from("/game/assets/shaders/copy-vs.glsl")];
+        assert!(depends_on(AMBIENT_SHADERS, &[], &changed));
+        assert!(depends_on(DIRECTIONAL_SHADERS, &[], &changed));
+        assert!(!depends_on(REGULAR_SHADERS, &[], &changed));
+    }
+
+    #[test]
+    fn reload_programs_including_the_file() {
+        let changed = vec![PathBuf::from("/game/assets/shaders/lighting_common.glsl")];
+        let included = vec!["lighting_common.glsl".to_owned()];
+        assert!(depends_on(POINT_LIGHT_SHADERS, &included, &changed));
+        assert!(!depends_on(SKYBOX_SHADERS, &[], &changed));
+    }
+
+    fn read_from(
+        files: &HashMap<&str, &str>,
+    ) -> impl FnMut(&str) -> Result<String, ShaderError> + '_ {
+        move |name| {
+            files.get(name).map(|s| s.to_string()).ok_or_else(|| {
+                ShaderError::IoError(
+                    name.to_owned(),
+                    std::io::Error::from(std::io::ErrorKind::NotFound),
+                )
+            })
+        }
+    }
+
+    #[test]
+    fn include_files() {
+        let mut files = HashMap::new();
+        files.insert("common.glsl", "#include \"math.glsl\"\nfloat common;");
+        files.insert("math.glsl", "float math;");
+
+        let source =
+            "#version 330\n#include \"common.glsl\"\n  #include \"math.glsl\"\nvoid main() {}";
+        let mut included = vec![];
+        let output = resolve_includes(source, &mut read_from(&files), &mut included).unwrap();
+
+        // math.glsl is included only once.
+        assert_eq!(
+            "#version 330\nfloat math;\nfloat common;\nvoid main() {}\n",
+            output
+        );
+        assert_eq!(vec!["common.glsl", "math.glsl"], included);
+    }
+
+    #[test]
+    fn include_errors() {
+        let files = HashMap::new();
+        let mut included = vec![];
+        let missing = resolve_includes(
+            "#include \"nope.glsl\"",
+            &mut read_from(&files),
+            &mut included,
+        );
+        match missing {
+            Err(ShaderError::IoError(name, _)) => assert_eq!("nope.glsl", name),
+            other => panic!("Expected an io error, got {:?}", other),
+        }
+
+        let invalid = resolve_includes("#include nope.glsl", &mut read_from(&files), &mut included);
+        match invalid {
+            Err(ShaderError::InvalidInclude(_)) => (),
+            other => panic!("Expected an invalid include, got {:?}", other),
         }
     }
 }
@@ -1,6 +1,11 @@
+use crate::colors::{self, RgbColor};
+use crate::gameplay::ui::{HudConfig, HudElement};
 use crate::render::assets::SpriteCache;
 use crate::render::shaders::Shaders;
+use crate::render::RenderLayer;
 use hecs::World;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace};
 use luminance::blending::{Equation, Factor};
 use luminance::context::GraphicsContext;
 use luminance::linear::M44;
@@ -18,6 +23,7 @@ use serde_derive::{Deserialize, Serialize};
 /// Component to display a sprite on the screen.
 ///
 /// This component and the Transform component are necessary to display a sprite on screen.
+/// An optional `RgbColor` component tints the sprite.
 #[derive(Debug, Clone, Serialize, Deserialize, Default, Eq, PartialEq)]
 pub struct SpriteRender {
     /// Texture spritesheet to use for the sprite
@@ -89,6 +95,7 @@ pub struct ShaderInterface {
 
     pub spritesheet_dimensions: Uniform<[f32; 2]>,
     pub sprite_coord: Uniform<[f32; 4]>,
+    pub tint: Uniform<[f32; 3]>,
 
     pub tex: Uniform<&'static BoundTexture<'static, Dim2, NormUnsigned>>,
 }
@@ -127,16 +134,39 @@ impl SpriteRenderer {
         world: &World,
         sprite_cache: &SpriteCache,
         shaders: &Shaders,
+        hud: &HudConfig,
+        after_text: bool,
     ) where
         S: GraphicsContext,
     {
+        // Only the sprites on the requested side of the text layer, by increasing layer.
+        let mut query = world.query::<(&ScreenPosition, &SpriteRender, Option<&RenderLayer>)>();
+        let mut sprites: Vec<_> = query
+            .iter()
+            .map(|(e, (pos, sprite, layer))| (e, pos, sprite, layer.copied().unwrap_or_default()))
+            .filter(|(_, _, _, layer)| (layer.0 > hud.text_layer) == after_text)
+            .collect();
+        sprites.sort_by_key(|(e, _, _, layer)| (*layer, e.to_bits()));
+
         let projection = glam::Mat4::orthographic_rh_gl(0.0, self.w, 0.0, self.h, -1.0, 10.0);
 
         shd_gate.shade(&shaders.sprite_program, |iface, mut rdr_gate| {
             iface.projection.update(projection.to_cols_array_2d());
 
-            for (_, (pos, sprite)) in world.query::<(&ScreenPosition, &SpriteRender)>().iter() {
-                let assets = sprite_cache.get(&sprite.texture).unwrap();
+            for &(e, pos, sprite, _) in sprites.iter() {
+                if let Ok(element) = world.get::<HudElement>(e) {
+                    if !hud.is_visible(*element) {
+                        continue;
+                    }
+                }
+
+                let assets = match sprite_cache.get(&sprite.texture) {
+                    Some(assets) => assets,
+                    None => {
+                        trace!("Sprite {} is not loaded", sprite.texture);
+                        continue;
+                    }
+                };
                 let texture = pipeline.bind_texture(&assets.0);
                 let metadata = &assets.1;
 
@@ -150,6 +180,11 @@ impl SpriteRenderer {
                     .sprite_coord
                     .update(assets.1.sprites.get(sprite_idx).unwrap().as_array());
                 iface.spritesheet_dimensions.update(assets.1.dim_as_array());
+                let tint = world
+                    .get::<RgbColor>(e)
+                    .map(|c| *c)
+                    .unwrap_or(colors::WHITE);
+                iface.tint.update(tint.to_normalized());
                 let model = glam::Mat4::from_scale_rotation_translation(
                     glam::vec3(self.w * pos.w, self.h * pos.h, 1.0),
                     glam::Quat::identity(),
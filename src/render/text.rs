@@ -1,4 +1,5 @@
 use crate::colors::RgbColor;
+use crate::gameplay::ui::{HudConfig, HudElement};
 use crate::render::shaders::Shaders;
 use crate::render::sprite::ScreenPosition;
 use glyph_brush::{rusttype::*, *};
@@ -112,13 +113,20 @@ impl TextRenderer {
         surface: &mut GlfwSurface,
         world: &hecs::World,
         glyph_brush: &mut GlyphBrush<'static, Instance>,
+        hud: &HudConfig,
     ) {
         let width = surface.width() as f32;
         let height = surface.height() as f32;
 
-        for (_, (text, position, color)) in
+        for (e, (text, position, color)) in
             world.query::<(&Text, &ScreenPosition, &RgbColor)>().iter()
         {
+            if let Ok(element) = world.get::<HudElement>(e) {
+                if !hud.is_visible(*element) {
+                    continue;
+                }
+            }
+
             // screen position is left-bottom origin, and value is between 0 and 1.
             let pos_x = width * position.x;
             let pos_y = height * (1.0 - position.y);
@@ -0,0 +1,98 @@
+//! Texture parameters that luminance does not expose in its sampler, set directly through GL.
+//!
+//! Anisotropic filtering comes from the `GL_EXT_texture_filter_anisotropic` extension (core
+//! only since GL 4.6) so we need to check that it is supported before using it.
+use log::{debug, warn};
+use std::cell::Cell;
+use std::ffi::CStr;
+
+/// From `GL_EXT_texture_filter_anisotropic`. Not generated in the gl crate.
+const TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FF;
+
+const ANISOTROPY_EXTENSIONS: [&str; 2] = [
+    "GL_EXT_texture_filter_anisotropic",
+    "GL_ARB_texture_filter_anisotropic",
+];
+
+thread_local! {
+    /// Result of `query_max_anisotropy`. The GL context lives in the main thread and does not
+    /// change, so the extensions are only queried for the first texture.
+    static MAX_ANISOTROPY: Cell<Option<Option<f32>>> = Cell::new(None);
+}
+
+/// Maximum anisotropy level supported by the context. None if the extension is not
+/// available.
+pub fn max_anisotropy() -> Option<f32> {
+    MAX_ANISOTROPY.with(|cached| {
+        if let Some(max) = cached.get() {
+            return max;
+        }
+
+        let max = query_max_anisotropy();
+        if max.is_none() {
+            warn!("Anisotropic filtering is not supported, ignoring it");
+        }
+        cached.set(Some(max));
+        max
+    })
+}
+
+fn query_max_anisotropy() -> Option<f32> {
+    unsafe {
+        let mut nb_extensions = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut nb_extensions);
+        let supported = (0..nb_extensions as u32).any(|i| {
+            let ext = gl::GetStringi(gl::EXTENSIONS, i);
+            if ext.is_null() {
+                return false;
+            }
+            let ext = CStr::from_ptr(ext as *const _).to_string_lossy();
+            ANISOTROPY_EXTENSIONS.contains(&ext.as_ref())
+        });
+
+        if supported {
+            let mut max = 0.0;
+            gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+            Some(max)
+        } else {
+            None
+        }
+    }
+}
+
+/// GL name of the texture bound to `GL_TEXTURE_2D`. luminance does not expose the name of its
+/// textures but binds a texture when creating it, so call that right after `Texture::new`.
+pub fn bound_texture_2d() -> gl::types::GLuint {
+    let mut bound = 0;
+    unsafe {
+        gl::GetIntegerv(gl::TEXTURE_BINDING_2D, &mut bound);
+    }
+    bound as gl::types::GLuint
+}
+
+/// Set the anisotropy level of `texture`. The level is clamped to what the context supports and
+/// nothing is done if the extension is missing.
+///
+/// The texture is bound for the call and the previous binding is restored afterwards, as
+/// luminance keeps track of the bound textures.
+pub fn set_anisotropy(texture: gl::types::GLuint, level: f32) {
+    if level <= 1.0 {
+        return;
+    }
+    if texture == 0 {
+        debug!("No texture to set the anisotropy of");
+        return;
+    }
+
+    if let Some(max) = max_anisotropy() {
+        let level = level.min(max);
+        debug!("Set anisotropy of texture {} to {}", texture, level);
+        unsafe {
+            let previous = bound_texture_2d();
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameterf(gl::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY, level);
+            gl::BindTexture(gl::TEXTURE_2D, previous);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Tracers are the short-lived lines drawn from the gun to what was hit when shooting. They
+//! are drawn as a quad stretched between the two points and facing the camera, so that the
+//! width does not depend on the GL line width support.
+
+use crate::colors::RgbColor;
+use crate::render::shaders::Shaders;
+use glam::{Mat4, Vec3};
+use hecs::World;
+use luminance::blending::{Equation, Factor};
+use luminance::context::GraphicsContext;
+use luminance::linear::M44;
+use luminance::pipeline::ShadingGate;
+use luminance::render_state::RenderState;
+use luminance::shader::program::Uniform;
+use luminance::tess::{Mode, Tess, TessBuilder, TessSliceIndex};
+use luminance_derive::UniformInterface;
+use luminance_glfw::GlfwSurface;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tracer {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub color: RgbColor,
+    pub width: f32,
+    /// Total time the tracer is displayed, in seconds.
+    pub lifetime: f32,
+    /// Time left before the tracer disappears. It fades out as it decreases.
+    pub remaining: f32,
+}
+
+impl Tracer {
+    pub fn new(start: Vec3, end: Vec3, color: RgbColor, width: f32, lifetime: f32) -> Self {
+        Self {
+            start,
+            end,
+            color,
+            width,
+            lifetime,
+            remaining: lifetime,
+        }
+    }
+
+    fn alpha(&self) -> f32 {
+        if self.lifetime > 0.0 {
+            (self.remaining / self.lifetime).max(0.0).min(1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Fade the tracers and remove the ones that are finished.
+pub fn update_tracers(world: &mut World, dt: f32) {
+    let mut to_remove = vec![];
+    for (e, tracer) in world.query::<&mut Tracer>().iter() {
+        tracer.remaining -= dt;
+        if tracer.remaining <= 0.0 {
+            to_remove.push(e);
+        }
+    }
+
+    for e in to_remove {
+        let _ = world.despawn(e);
+    }
+}
+
+#[derive(UniformInterface)]
+pub struct ShaderInterface {
+    #[uniform(unbound)]
+    pub projection: Uniform<M44>,
+
+    #[uniform(unbound)]
+    pub view: Uniform<M44>,
+
+    #[uniform(unbound)]
+    pub model: Uniform<M44>,
+
+    #[uniform(unbound)]
+    pub color: Uniform<[f32; 4]>,
+}
+
+pub struct TracerRenderer {
+    tess: Tess,
+    render_state: RenderState,
+}
+
+impl TracerRenderer {
+    pub fn new(surface: &mut GlfwSurface) -> Self {
+        let tess = TessBuilder::new(surface)
+            .set_vertex_nb(4)
+            .set_mode(Mode::TriangleFan)
+            .build()
+            .unwrap();
+        let render_state = RenderState::default().set_face_culling(None).set_blending((
+            Equation::Additive,
+            Factor::SrcAlpha,
+            Factor::SrcAlphaComplement,
+        ));
+        Self { tess, render_state }
+    }
+
+    pub fn render<S>(
+        &self,
+        projection: &Mat4,
+        view: &Mat4,
+        shd_gate: &mut ShadingGate<S>,
+        world: &World,
+        shaders: &Shaders,
+    ) where
+        S: GraphicsContext,
+    {
+        let camera_position = view.inverse().w_axis().truncate();
+
+        shd_gate.shade(&shaders.tracer_program, |iface, mut rdr_gate| {
+            iface.projection.update(projection.to_cols_array_2d());
+            iface.view.update(view.to_cols_array_2d());
+
+            for (_, tracer) in world.query::<&Tracer>().iter() {
+                let model = match tracer_model(tracer, camera_position) {
+                    Some(model) => model,
+                    None => continue,
+                };
+                let [r, g, b] = tracer.color.to_normalized();
+                iface.model.update(model.to_cols_array_2d());
+                iface.color.update([r, g, b, tracer.alpha()]);
+                rdr_gate.render(&self.render_state, |mut tess_gate| {
+                    tess_gate.render(self.tess.slice(..));
+                });
+            }
+        });
+    }
+}
+
+/// Matrix that maps the unit quad of the shader to the tracer. None if the tracer is
+/// degenerated (zero length or pointing at the camera).
+fn tracer_model(tracer: &Tracer, camera_position: Vec3) -> Option<Mat4> {
+    let dir = tracer.end - tracer.start;
+    let side = dir.cross(camera_position - tracer.start);
+    if dir.length_squared() < std::f32::EPSILON || side.length_squared() < std::f32::EPSILON {
+        return None;
+    }
+    let side = side.normalize();
+    let normal = side.cross(dir).normalize();
+
+    Some(Mat4::from_cols(
+        (side * tracer.width).extend(0.0),
+        normal.extend(0.0),
+        dir.extend(0.0),
+        tracer.start.extend(1.0),
+    ))
+}
@@ -30,6 +30,13 @@ impl From<Transform> for LocalTransform {
     }
 }
 
+/// Same position as the parent.
+impl Default for LocalTransform {
+    fn default() -> Self {
+        Transform::default().into()
+    }
+}
+
 fn default_dirty() -> bool {
     true
 }